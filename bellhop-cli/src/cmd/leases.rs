@@ -0,0 +1,169 @@
+//! Command for interacting with the lease on a Bellhop asset.
+use bellhop_client::apis::client::APIClient;
+use bellhop_client::models::CreateLease;
+
+use crate::config::Config;
+
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use structopt::StructOpt;
+
+use super::{Api, CmdError, CmdErrorKind, ResultExt};
+
+/// How often to check whether the wrapped command has exited.
+const POLL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, StructOpt)]
+pub struct Leases {
+    #[structopt(flatten)]
+    api: Api,
+
+    #[structopt(
+        name = "asset",
+        help = "The unique identifier for the leased asset",
+        long = "asset"
+    )]
+    asset: i32,
+
+    #[structopt(subcommand)]
+    cmd: Cmd,
+}
+
+impl Leases {
+    pub fn execute(&self, cfg: &Config) -> Result<(), CmdError> {
+        let client = crate::client::build(&cfg.remote, self.api.insecure)?;
+
+        match self.cmd {
+            Cmd::Show => self.show(client),
+            Cmd::Release => self.release(client),
+            Cmd::Heartbeat => self.heartbeat(client),
+            Cmd::Run(ref run) => self.run(client, run),
+        }
+    }
+
+    fn show(&self, client: APIClient) -> Result<(), CmdError> {
+        let api = client.default_api();
+
+        let lease = api.show_lease(self.asset)?;
+
+        let at = serde_json::to_string_pretty(&lease).unwrap();
+        println!("{}", at);
+
+        Ok(())
+    }
+
+    fn release(&self, client: APIClient) -> Result<(), CmdError> {
+        let api = client.default_api();
+
+        api.delete_lease(self.asset)?;
+
+        Ok(())
+    }
+
+    fn heartbeat(&self, client: APIClient) -> Result<(), CmdError> {
+        let api = client.default_api();
+
+        let lease = api.heartbeat_lease(self.asset)?;
+
+        let at = serde_json::to_string_pretty(&lease).unwrap();
+        println!("{}", at);
+
+        Ok(())
+    }
+
+    /// Lease the asset, run the command while sending heartbeats, then
+    /// release the asset and exit with the command's exit code.
+    fn run(&self, client: APIClient, run: &Run) -> Result<(), CmdError> {
+        let api = client.default_api();
+
+        let mut create = CreateLease::new(run.end_time.clone());
+        create.heartbeat_secs = Some(run.heartbeat);
+
+        api.create_lease(self.asset, create)?;
+
+        let (program, args) = run.command.split_first().expect("command is required");
+
+        let mut child = match Command::new(program).args(args).spawn() {
+            Ok(x) => x,
+            Err(e) => {
+                api.delete_lease(self.asset)?;
+                return Err(e).chain_err(|| CmdErrorKind::Spawn(program.clone()));
+            }
+        };
+
+        // Leave plenty of room for slow requests before the lease is
+        // considered abandoned.
+        let period = Duration::from_secs((run.heartbeat as u64 / 3).max(1));
+        let mut next = Instant::now() + period;
+
+        let status = loop {
+            if let Some(status) = child
+                .try_wait()
+                .chain_err(|| CmdErrorKind::Spawn(program.clone()))?
+            {
+                break status;
+            }
+
+            if Instant::now() >= next {
+                // A missed heartbeat isn't fatal, so keep the command running
+                // and try again next time.
+                if let Err(e) = api.heartbeat_lease(self.asset) {
+                    eprintln!("unable to send heartbeat: {}", e);
+                }
+
+                next += period;
+            }
+
+            thread::sleep(POLL);
+        };
+
+        if let Err(e) = api.delete_lease(self.asset) {
+            eprintln!("unable to release lease: {}", e);
+        }
+
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum Cmd {
+    #[structopt(name = "show", about = "Print the asset's current lease")]
+    Show,
+
+    #[structopt(name = "release", about = "Release the asset's current lease")]
+    Release,
+
+    #[structopt(
+        name = "heartbeat",
+        about = "Keep the asset's current lease from being released as abandoned"
+    )]
+    Heartbeat,
+
+    #[structopt(
+        name = "run",
+        about = "Lease the asset while a command runs, sending heartbeats until it exits"
+    )]
+    Run(Run),
+}
+
+#[derive(Debug, StructOpt)]
+struct Run {
+    #[structopt(
+        long = "heartbeat",
+        help = "Seconds without a heartbeat before the lease is released as abandoned",
+        default_value = "300"
+    )]
+    heartbeat: i32,
+
+    #[structopt(long = "end-time", help = "When the lease ends, in RFC 3339 format")]
+    end_time: Option<String>,
+
+    #[structopt(
+        name = "command",
+        help = "The command to run, and its arguments",
+        raw(required = "true")
+    )]
+    command: Vec<String>,
+}
@@ -4,6 +4,7 @@
 mod asset_types;
 mod assets;
 mod config;
+mod leases;
 mod tag_types;
 mod tags;
 
@@ -28,6 +29,10 @@ mod error {
             Api {
                 description("the api client encountered a problem")
             }
+            Spawn(program: String) {
+                description("unable to run command")
+                display("unable to run `{}`", program)
+            }
         }
 
         links {
@@ -71,6 +76,7 @@ impl Opt {
             Cmd::Config(_) => unreachable!(),
             Cmd::AssetTypes(ref at) => at.execute(&cfg),
             Cmd::Assets(ref a) => a.execute(&cfg),
+            Cmd::Leases(ref l) => l.execute(&cfg),
             Cmd::Tags(ref t) => t.execute(&cfg),
             Cmd::TagTypes(ref tt) => tt.execute(&cfg),
         }
@@ -86,6 +92,8 @@ pub enum Cmd {
     AssetTypes(asset_types::AssetTypes),
     #[structopt(name = "assets", about = "View or modify assets")]
     Assets(assets::Assets),
+    #[structopt(name = "leases", about = "View or manage an asset's lease")]
+    Leases(leases::Leases),
     #[structopt(name = "tags", about = "View or modify tags")]
     Tags(tags::Tags),
     #[structopt(name = "tag-types", about = "View or modify tag types")]
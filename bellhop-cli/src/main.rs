@@ -16,6 +16,16 @@
 //! ```bash
 //! $ bellhop-cli asset-types list
 //! ```
+//!
+//! ### Holding an Asset While a Command Runs
+//!
+//! This command leases asset 7, sends heartbeats while `make test` runs, and
+//! releases the asset when it exits. If `bellhop-cli` dies first, Bellhop
+//! releases the asset once the heartbeats stop.
+//!
+//! ```bash
+//! $ bellhop-cli leases --asset 7 run --heartbeat 120 -- make test
+//! ```
 #![deny(missing_docs)]
 
 #[macro_use]
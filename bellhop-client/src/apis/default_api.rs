@@ -53,6 +53,7 @@ pub trait DefaultApi {
     fn delete_lease(&self, asset_id: i32) -> Result<(), Error>;
     fn delete_tag(&self, asset_id: i32, tag_type_id: i32) -> Result<(), Error>;
     fn delete_tag_type(&self, asset_type_id: i32, tag_type_id: i32) -> Result<(), Error>;
+    fn heartbeat_lease(&self, asset_id: i32) -> Result<::models::Lease, Error>;
     fn list_asset_types(&self) -> Result<::models::AssetTypes, Error>;
     fn list_assets(&self) -> Result<::models::Assets, Error>;
     fn list_sub_assets(&self, asset_type_id: i32) -> Result<::models::Assets, Error>;
@@ -379,6 +380,35 @@ impl DefaultApi for DefaultApiClient {
         Ok(())
     }
 
+    fn heartbeat_lease(&self, asset_id: i32) -> Result<::models::Lease, Error> {
+        let configuration: &configuration::Configuration = self.configuration.borrow();
+        let client = &configuration.client;
+
+        let uri_str = format!(
+            "{}/assets/{asset_id}/lease/heartbeat",
+            configuration.base_path,
+            asset_id = asset_id
+        );
+        let mut req_builder = client.post(uri_str.as_str());
+
+        if let Some(ref user_agent) = configuration.user_agent {
+            req_builder = req_builder.header(reqwest::header::USER_AGENT, user_agent.clone());
+        }
+        if let Some(ref apikey) = configuration.api_key {
+            let key = apikey.key.clone();
+            let val = match apikey.prefix {
+                Some(ref prefix) => format!("{} {}", prefix, key),
+                None => key,
+            };
+            req_builder = req_builder.header("X-Bellhop-Email", val);
+        };
+
+        // send request
+        let req = req_builder.build()?;
+
+        Ok(client.execute(req)?.error_for_status()?.json()?)
+    }
+
     fn list_asset_types(&self) -> Result<::models::AssetTypes, Error> {
         let configuration: &configuration::Configuration = self.configuration.borrow();
         let client = &configuration.client;
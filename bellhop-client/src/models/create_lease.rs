@@ -15,10 +15,15 @@ use serde_json::Value;
 pub struct CreateLease {
     #[serde(rename = "end_time")]
     pub end_time: Option<String>,
+    #[serde(rename = "heartbeat_secs", skip_serializing_if = "Option::is_none")]
+    pub heartbeat_secs: Option<i32>,
 }
 
 impl CreateLease {
     pub fn new(end_time: Option<String>) -> CreateLease {
-        CreateLease { end_time: end_time }
+        CreateLease {
+            end_time: end_time,
+            heartbeat_secs: None,
+        }
     }
 }
@@ -23,6 +23,10 @@ pub struct Lease {
     pub end_time: Option<String>,
     #[serde(rename = "start_time", skip_serializing_if = "Option::is_none")]
     pub start_time: Option<String>,
+    #[serde(rename = "heartbeat_secs", skip_serializing_if = "Option::is_none")]
+    pub heartbeat_secs: Option<i32>,
+    #[serde(rename = "last_heartbeat", skip_serializing_if = "Option::is_none")]
+    pub last_heartbeat: Option<String>,
 }
 
 impl Lease {
@@ -38,6 +42,8 @@ impl Lease {
             last_notified: last_notified,
             end_time: end_time,
             start_time: None,
+            heartbeat_secs: None,
+            last_heartbeat: None,
         }
     }
 }
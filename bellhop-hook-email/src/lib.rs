@@ -1,5 +1,7 @@
 //! An implementation of [`bellhop::hooks::Hook`] that sends an email warning
-//! when leases are about to expire.
+//! when leases are about to expire, and a notice when a lease is overdue,
+//! extended, transferred or revoked, an asset is handed to someone on a
+//! waitlist, or a lease request needs approving, is approved, or is denied.
 //!
 //! ## Routes
 //!
@@ -32,9 +34,12 @@
 extern crate serde_derive;
 
 use bellhop::db::Db;
-use bellhop::hooks::{Data, Error, ErrorKind, Hook};
+use bellhop::hooks::{Data, Delivery, Error, ErrorKind, Hook, RequestData};
+use bellhop::models::lease_request::RequestStatus;
 use bellhop::models::user::User;
 
+use chrono::{Duration, Utc};
+
 use lettre::smtp::authentication::{Credentials, Mechanism};
 use lettre::smtp::client::net::{ClientTlsParameters, DEFAULT_TLS_PROTOCOLS};
 use lettre::{ClientSecurity, SmtpClient, Transport};
@@ -66,6 +71,38 @@ fn default_subject() -> String {
     "Bellhop Reservation Expiry Warning".to_owned()
 }
 
+fn default_overdue_subject() -> String {
+    "Bellhop Reservation Overdue".to_owned()
+}
+
+fn default_handed_off_subject() -> String {
+    "Bellhop Waitlist Asset Available".to_owned()
+}
+
+fn default_extended_subject() -> String {
+    "Bellhop Reservation Extended".to_owned()
+}
+
+fn default_transferred_subject() -> String {
+    "Bellhop Reservation Transferred".to_owned()
+}
+
+fn default_revoked_subject() -> String {
+    "Bellhop Reservation Revoked".to_owned()
+}
+
+fn default_requested_subject() -> String {
+    "Bellhop Reservation Awaiting Approval".to_owned()
+}
+
+fn default_approved_subject() -> String {
+    "Bellhop Reservation Approved".to_owned()
+}
+
+fn default_denied_subject() -> String {
+    "Bellhop Reservation Denied".to_owned()
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct Config {
     from: String,
@@ -73,6 +110,30 @@ struct Config {
     #[serde(default = "default_subject")]
     subject: String,
 
+    #[serde(default = "default_overdue_subject")]
+    overdue_subject: String,
+
+    #[serde(default = "default_handed_off_subject")]
+    handed_off_subject: String,
+
+    #[serde(default = "default_extended_subject")]
+    extended_subject: String,
+
+    #[serde(default = "default_transferred_subject")]
+    transferred_subject: String,
+
+    #[serde(default = "default_revoked_subject")]
+    revoked_subject: String,
+
+    #[serde(default = "default_requested_subject")]
+    requested_subject: String,
+
+    #[serde(default = "default_approved_subject")]
+    approved_subject: String,
+
+    #[serde(default = "default_denied_subject")]
+    denied_subject: String,
+
     smtp_host: String,
     smtp_port: u16,
 
@@ -121,7 +182,8 @@ impl Config {
     }
 }
 
-/// Sends email when leases are about to expire.
+/// Sends email when leases are about to expire or are overdue, when a
+/// waitlisted user is handed an asset, or when a lease request changes.
 ///
 /// See the crate documentation for more information.
 #[derive(Debug, Default)]
@@ -134,9 +196,23 @@ impl Email {
     pub fn new() -> Self {
         Self::default()
     }
+
+    fn config(&self) -> Config {
+        self.config.lock().unwrap().as_ref().unwrap().clone()
+    }
 }
 
 impl Hook for Email {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    /// Sending mail is slow, and a mail server that's down shouldn't hold up
+    /// leases.
+    fn delivery(&self) -> Delivery {
+        Delivery::Outbox
+    }
+
     fn prelaunch(&self, rocket: Rocket) -> Rocket {
         let config_slot = self.config.clone();
 
@@ -154,43 +230,259 @@ impl Hook for Email {
         }))
     }
 
-    fn warned(&self, db: &Db, data: Data) -> Result<(), Error> {
-        let lease = data.lease();
+    fn warned(&self, _db: &Db, data: Data) -> Result<(), Error> {
+        let config = self.config();
 
-        let user = User::by_id(db, lease.user_id())
-            .map_err(Error::for_kind(ErrorKind::msg("unable to fetch users")))?;
+        let mut lines = vec![];
 
-        let user = match user {
-            Some(x) => x,
-            None => {
-                return Err(Error::with_msg(format!(
-                    "No userid found for lease: {:?}",
-                    lease
-                )));
-            }
+        if let Some(purpose) = data.lease().purpose() {
+            lines.push(format!("Purpose: {}", purpose));
+        }
+
+        for (name, value) in data.fields() {
+            lines.push(format!("{}: {}", name, value));
+        }
+
+        let details = if lines.is_empty() {
+            String::new()
+        } else {
+            format!("\n\n{}", lines.join("\n"))
+        };
+
+        let when = match data.warning() {
+            Some(x) => format!("in {}", describe(x.remaining())),
+            None => "soon".to_owned(),
+        };
+
+        for user in data.owners() {
+            let text = format!("This is the bellhop Sheriff letting you know that your reservation (id: {}) on {} is going to expire {}! Best of luck.{}", data.lease().id(), data.asset().name(), when, details);
+
+            send(&config, user, &config.subject, text);
+        }
+
+        Ok(())
+    }
+
+    fn overdue(&self, _db: &Db, data: Data) -> Result<(), Error> {
+        let config = self.config();
+
+        // The mail may go out a while after the lease became overdue, so
+        // count down from now rather than from the grace period.
+        let now = Utc::now();
+        let when = match data.evict_at() {
+            Some(x) if x > now => format!("at {} (in {})", x.to_rfc3339(), describe(x - now)),
+            Some(_) => "any moment now".to_owned(),
+            None => "soon".to_owned(),
+        };
+
+        for user in data.owners() {
+            let text = format!("This is the bellhop Sheriff letting you know that your reservation (id: {}) on {} has expired. It will be evicted {} unless you extend or return it.", data.lease().id(), data.asset().name(), when);
+
+            send(&config, user, &config.overdue_subject, text);
+        }
+
+        Ok(())
+    }
+
+    fn extended(&self, db: &Db, data: Data) -> Result<(), Error> {
+        let user = lease_user(db, &data)?;
+        let config = self.config();
+
+        let end_time = match data.lease().end_time() {
+            Some(x) => x.to_rfc3339(),
+            None => "forever".to_owned(),
+        };
+
+        let text = format!(
+            "Your reservation (id: {}) on {} has been extended until {}.",
+            data.lease().id(),
+            data.asset().name(),
+            end_time,
+        );
+
+        send(&config, &user, &config.extended_subject, text);
+
+        Ok(())
+    }
+
+    fn revoked(&self, db: &Db, data: Data) -> Result<(), Error> {
+        let user = lease_user(db, &data)?;
+        let config = self.config();
+
+        let reason = data
+            .ended()
+            .and_then(|x| x.revoke_reason())
+            .unwrap_or("no reason given");
+
+        let text = format!(
+            "An administrator has revoked your reservation (id: {}) on {}: {}",
+            data.lease().id(),
+            data.asset().name(),
+            reason,
+        );
+
+        send(&config, &user, &config.revoked_subject, text);
+
+        Ok(())
+    }
+
+    fn handed_off(&self, db: &Db, data: Data) -> Result<(), Error> {
+        let user = lease_user(db, &data)?;
+        let config = self.config();
+
+        let text = format!(
+            "Your wait is over! You now have a lease (id: {}) on {}.",
+            data.lease().id(),
+            data.asset().name(),
+        );
+
+        send(&config, &user, &config.handed_off_subject, text);
+
+        Ok(())
+    }
+
+    fn transferred(&self, _db: &Db, data: Data, from: &User, to: &User) -> Result<(), Error> {
+        let config = self.config();
+
+        let text = format!(
+            "Your reservation (id: {}) on {} has been transferred to {}.",
+            data.lease().id(),
+            data.asset().name(),
+            to.email(),
+        );
+
+        send(&config, from, &config.transferred_subject, text);
+
+        let text = format!(
+            "{} has transferred their reservation (id: {}) on {} to you.",
+            from.email(),
+            data.lease().id(),
+            data.asset().name(),
+        );
+
+        send(&config, to, &config.transferred_subject, text);
+
+        Ok(())
+    }
+
+    fn requested(&self, _db: &Db, data: RequestData) -> Result<(), Error> {
+        let config = self.config();
+        let request = data.request();
+
+        let until = match request.end_time() {
+            Some(x) => x.to_rfc3339(),
+            None => "forever".to_owned(),
         };
 
-        let config = self.config.lock().unwrap().as_ref().unwrap().clone();
+        let purpose = match request.purpose() {
+            Some(x) => format!("\n\nPurpose: {}", x),
+            None => String::new(),
+        };
+
+        let text = format!(
+            "{} has asked for {} from {} until {}. Approve or deny the request (id: {}) on the approvals page before {}.{}",
+            data.requester().email(),
+            data.asset().name(),
+            request.start_time().to_rfc3339(),
+            until,
+            request.id(),
+            request.expires_at().to_rfc3339(),
+            purpose,
+        );
+
+        for approver in data.approvers() {
+            send(&config, approver, &config.requested_subject, text.clone());
+        }
+
+        Ok(())
+    }
+
+    fn approved(&self, _db: &Db, data: RequestData) -> Result<(), Error> {
+        let config = self.config();
 
-        let email = EmailBuilder::new()
-            .to((user.email(), "Bellhop User"))
-            // ... or by an address only
-            .from(config.from.as_str())
-            .subject(config.subject.as_str())
-            .text(format!("This is the bellhop Sheriff letting you know that your reservation (id: {}) is going to expire soon! Best of luck.",user.id()))
-            .build()
-            .unwrap();
+        let text = format!(
+            "Your request (id: {}) for {} has been approved.",
+            data.request().id(),
+            data.asset().name(),
+        );
 
-        let mut mailer = config.create_client().transport();
+        send(&config, data.requester(), &config.approved_subject, text);
 
-        // Send the email
-        let result = mailer.send(email.into());
+        Ok(())
+    }
 
-        match result {
-            Ok(_) => {}
-            Err(e) => println!("Error sending eviction notice: {}", e),
+    fn denied(&self, _db: &Db, data: RequestData) -> Result<(), Error> {
+        let config = self.config();
+        let request = data.request();
+
+        let text = if request.status() == RequestStatus::Expired {
+            format!(
+                "Your request (id: {}) for {} expired before anyone approved it.",
+                request.id(),
+                data.asset().name(),
+            )
+        } else {
+            format!(
+                "Your request (id: {}) for {} has been denied: {}",
+                request.id(),
+                data.asset().name(),
+                request.decision_reason().unwrap_or("no reason given"),
+            )
         };
 
+        send(&config, data.requester(), &config.denied_subject, text);
+
         Ok(())
     }
 }
+
+fn lease_user(db: &Db, data: &Data) -> Result<User, Error> {
+    let lease = data.lease();
+
+    let user = User::by_id(db, lease.user_id())
+        .map_err(Error::for_kind(ErrorKind::msg("unable to fetch users")))?;
+
+    match user {
+        Some(x) => Ok(x),
+        None => Err(Error::with_msg(format!(
+            "No userid found for lease: {:?}",
+            lease
+        ))),
+    }
+}
+
+/// Round `time` to something a person would say, like "10 minutes".
+fn describe(time: Duration) -> String {
+    let (count, unit) = if time.num_days() > 1 {
+        (time.num_days(), "day")
+    } else if time.num_hours() > 1 {
+        (time.num_hours(), "hour")
+    } else if time.num_minutes() > 1 {
+        (time.num_minutes(), "minute")
+    } else {
+        return "less than a couple of minutes".to_owned();
+    };
+
+    format!("{} {}s", count, unit)
+}
+
+fn send(config: &Config, user: &User, subject: &str, text: String) {
+    let email = EmailBuilder::new()
+        .to((user.email(), "Bellhop User"))
+        // ... or by an address only
+        .from(config.from.as_str())
+        .subject(subject)
+        .text(text)
+        .build()
+        .unwrap();
+
+    let mut mailer = config.create_client().transport();
+
+    // Send the email
+    let result = mailer.send(email.into());
+
+    match result {
+        Ok(_) => {}
+        Err(e) => println!("Error sending email: {}", e),
+    };
+}
@@ -1,5 +1,6 @@
 //! An implementation of [`bellhop::hooks::Hook`] that starts a Jenkins job
-//! when leases are created or released, or when they're about to expire.
+//! when leases are created, extended, transferred, released or revoked, or
+//! when they're about to expire.
 //!
 //! ## Routes
 //!
@@ -13,6 +14,11 @@
 //!
 //! None yet :(
 //!
+//! ## Job Parameters
+//!
+//! Jobs get `hook_at`, the lease's `purpose` if it has one, and each custom
+//! lease field as `field_<name>`.
+//!
 //! ## Example
 //!
 //! ```no_run
@@ -40,7 +46,8 @@ mod schema;
 use crate::models::{HookPoint, JenkinsHook};
 
 use bellhop::db::Db;
-use bellhop::hooks::{Data, Error, ErrorKind, Hook};
+use bellhop::hooks::{Data, Delivery, Error, ErrorKind, Hook};
+use bellhop::models::user::User;
 
 use diesel::prelude::*;
 
@@ -78,12 +85,26 @@ impl Jenkins {
             parameter: Vec<Kv>,
         }
 
-        let body = Body {
-            parameter: vec![Kv {
-                name: "hook_at".to_owned(),
-                value: by_hook_at.to_string(),
-            }],
-        };
+        let mut parameter = vec![Kv {
+            name: "hook_at".to_owned(),
+            value: by_hook_at.to_string(),
+        }];
+
+        if let Some(purpose) = data.lease().purpose() {
+            parameter.push(Kv {
+                name: "purpose".to_owned(),
+                value: purpose.to_owned(),
+            });
+        }
+
+        // The asset type's custom lease fields become job parameters too,
+        // prefixed so they can't collide with the ones above.
+        parameter.extend(data.fields().iter().map(|(name, value)| Kv {
+            name: format!("field_{}", name),
+            value: value.clone(),
+        }));
+
+        let body = Body { parameter };
 
         let client = Client::new();
         let resp = client
@@ -100,6 +121,16 @@ impl Jenkins {
 }
 
 impl Hook for Jenkins {
+    fn name(&self) -> &str {
+        "jenkins"
+    }
+
+    /// Jenkins can be slow to answer, so jobs are started from the outbox,
+    /// and retried if it's down.
+    fn delivery(&self) -> Delivery {
+        Delivery::Outbox
+    }
+
     fn leased(&self, db: &Db, data: Data) -> Result<(), Error> {
         Self::run(db, data, HookPoint::Leased)
     }
@@ -111,4 +142,16 @@ impl Hook for Jenkins {
     fn evicted(&self, db: &Db, data: Data) -> Result<(), Error> {
         Self::run(db, data, HookPoint::Evicted)
     }
+
+    fn extended(&self, db: &Db, data: Data) -> Result<(), Error> {
+        Self::run(db, data, HookPoint::Extended)
+    }
+
+    fn revoked(&self, db: &Db, data: Data) -> Result<(), Error> {
+        Self::run(db, data, HookPoint::Revoked)
+    }
+
+    fn transferred(&self, db: &Db, data: Data, _from: &User, _to: &User) -> Result<(), Error> {
+        Self::run(db, data, HookPoint::Transferred)
+    }
 }
@@ -10,6 +10,9 @@ pub enum HookPoint {
     Leased = 0,
     Returned = 1,
     Evicted = 2,
+    Extended = 3,
+    Transferred = 4,
+    Revoked = 5,
 }
 
 impl fmt::Display for HookPoint {
@@ -18,6 +21,9 @@ impl fmt::Display for HookPoint {
             HookPoint::Leased => write!(f, "leased"),
             HookPoint::Returned => write!(f, "returned"),
             HookPoint::Evicted => write!(f, "evicted"),
+            HookPoint::Extended => write!(f, "extended"),
+            HookPoint::Transferred => write!(f, "transferred"),
+            HookPoint::Revoked => write!(f, "revoked"),
         }
     }
 }
@@ -28,6 +34,9 @@ impl From<i16> for HookPoint {
             0 => HookPoint::Leased,
             1 => HookPoint::Returned,
             2 => HookPoint::Evicted,
+            3 => HookPoint::Extended,
+            4 => HookPoint::Transferred,
+            5 => HookPoint::Revoked,
             _ => panic!("unknown hook point"),
         }
     }
@@ -0,0 +1,10 @@
+DROP TRIGGER reservations_follow_lease_trigger ON leases;
+DROP FUNCTION reservations_follow_lease();
+
+-- Upcoming reservations can't be represented without the reservations table.
+DELETE FROM leases WHERE id NOT IN (
+    SELECT lease_id FROM assets WHERE lease_id IS NOT NULL
+);
+
+DROP TABLE reservations;
+DROP EXTENSION IF EXISTS btree_gist;
@@ -0,0 +1,66 @@
+-- Needed so the exclusion constraint below can compare integers with gist.
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
+CREATE TABLE reservations (
+    lease_id INTEGER NOT NULL,
+    asset_id INTEGER NOT NULL,
+
+    -- Mirrors of the lease's times, since exclusion constraints can't look
+    -- at other tables. Kept up to date by reservations_follow_lease().
+    start_time TIMESTAMP with time zone NOT NULL,
+    end_time TIMESTAMP with time zone,
+
+    FOREIGN KEY(lease_id) REFERENCES leases(id) ON DELETE CASCADE,
+    FOREIGN KEY(asset_id) REFERENCES assets(id) ON DELETE CASCADE,
+    PRIMARY KEY(lease_id, asset_id),
+
+    CONSTRAINT reservations_times_ordered CHECK (
+        end_time IS NULL
+        OR start_time < end_time
+    ),
+
+    -- A null end_time is an unbounded range, so an infinite lease blocks every
+    -- reservation after it.
+    CONSTRAINT reservations_no_overlap EXCLUDE USING gist (
+        asset_id WITH =,
+        tstzrange(start_time, end_time) WITH &&
+    )
+);
+
+-- Every lease that currently belongs to an asset becomes a reservation.
+INSERT INTO
+    reservations (lease_id, asset_id, start_time, end_time)
+SELECT
+    leases.id, assets.id, leases.start_time, leases.end_time
+FROM
+    assets
+INNER JOIN
+    leases ON assets.lease_id = leases.id;
+
+-- Any other lease is orphaned and was going to be evicted anyway.
+DELETE FROM leases WHERE id NOT IN (SELECT lease_id FROM reservations);
+
+-- This function is used by a trigger to copy changes to a lease's times into
+-- all of the reservations for that lease.
+CREATE FUNCTION reservations_follow_lease() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE
+        reservations
+    SET
+        start_time = NEW.start_time,
+        end_time = NEW.end_time
+    WHERE
+        lease_id = NEW.id;
+    RETURN NEW;
+END;
+$$
+LANGUAGE PLPGSQL;
+
+CREATE TRIGGER
+    reservations_follow_lease_trigger
+AFTER
+    UPDATE OF start_time, end_time
+ON
+    leases
+FOR EACH ROW
+    EXECUTE PROCEDURE reservations_follow_lease();
@@ -0,0 +1,2 @@
+DROP TABLE waitlist_tags;
+DROP TABLE waitlist_entries;
@@ -0,0 +1,27 @@
+CREATE TABLE waitlist_entries (
+    id SERIAL PRIMARY KEY NOT NULL,
+    asset_type_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+
+    created_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+
+    -- How long the lease handed to this user lasts, or null if it never ends.
+    lease_seconds INTEGER CHECK (lease_seconds > 0),
+
+    FOREIGN KEY(asset_type_id) REFERENCES asset_types(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX waitlist_entries_queue ON waitlist_entries (asset_type_id, created_at, id);
+
+-- Tag values an asset must have before it is handed to a waitlist entry.
+CREATE TABLE waitlist_tags (
+    waitlist_entry_id INTEGER NOT NULL,
+    tag_type_id INTEGER NOT NULL,
+
+    value VARCHAR(255) NOT NULL,
+
+    FOREIGN KEY(waitlist_entry_id) REFERENCES waitlist_entries(id) ON DELETE CASCADE,
+    FOREIGN KEY(tag_type_id) REFERENCES tag_types(id) ON DELETE CASCADE,
+    PRIMARY KEY(waitlist_entry_id, tag_type_id)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE asset_types DROP COLUMN max_extensions;
+ALTER TABLE asset_types DROP COLUMN max_lease_seconds;
+
+ALTER TABLE leases DROP COLUMN extensions;
@@ -0,0 +1,5 @@
+ALTER TABLE leases ADD COLUMN extensions INTEGER NOT NULL DEFAULT 0;
+
+-- Limits on extending leases. Null means unlimited.
+ALTER TABLE asset_types ADD COLUMN max_lease_seconds INTEGER CHECK (max_lease_seconds > 0);
+ALTER TABLE asset_types ADD COLUMN max_extensions INTEGER CHECK (max_extensions >= 0);
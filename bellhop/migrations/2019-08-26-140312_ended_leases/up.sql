@@ -0,0 +1,22 @@
+-- Leases that have ended, one row for each asset the lease covered.
+CREATE TABLE ended_leases (
+    lease_id INTEGER NOT NULL,
+    asset_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+
+    start_time TIMESTAMP with time zone NOT NULL,
+    end_time TIMESTAMP with time zone,
+    extensions INTEGER NOT NULL,
+
+    ended_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+
+    -- 0: returned, 1: evicted, 2: revoked, 3: transferred
+    end_reason SMALLINT NOT NULL,
+
+    FOREIGN KEY(asset_id) REFERENCES assets(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY(lease_id, asset_id)
+);
+
+CREATE INDEX ended_leases_by_asset ON ended_leases (asset_id, ended_at);
+CREATE INDEX ended_leases_by_user ON ended_leases (user_id, ended_at);
@@ -0,0 +1 @@
+DROP TABLE lease_users;
@@ -0,0 +1,9 @@
+-- Users who share a lease with its owner.
+CREATE TABLE lease_users (
+    lease_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+
+    FOREIGN KEY(lease_id) REFERENCES leases(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY(lease_id, user_id)
+);
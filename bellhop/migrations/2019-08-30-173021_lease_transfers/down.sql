@@ -0,0 +1,5 @@
+DROP TABLE lease_transfers;
+
+DELETE FROM ended_leases WHERE end_reason = 3;
+ALTER TABLE ended_leases DROP COLUMN id;
+ALTER TABLE ended_leases ADD PRIMARY KEY (lease_id, asset_id);
@@ -0,0 +1,16 @@
+-- A lease can be transferred more than once, so each ended owner gets a row.
+ALTER TABLE ended_leases DROP CONSTRAINT ended_leases_pkey;
+ALTER TABLE ended_leases ADD COLUMN id SERIAL PRIMARY KEY NOT NULL;
+
+-- Transfers waiting for the recipient to accept them.
+CREATE TABLE lease_transfers (
+    lease_id INTEGER PRIMARY KEY NOT NULL,
+    from_user_id INTEGER NOT NULL,
+    to_user_id INTEGER NOT NULL,
+
+    created_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+
+    FOREIGN KEY(lease_id) REFERENCES leases(id) ON DELETE CASCADE,
+    FOREIGN KEY(from_user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(to_user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+ALTER TABLE ended_leases DROP CONSTRAINT ended_leases_revoked_by_fkey;
+
+ALTER TABLE ended_leases DROP COLUMN revoke_reason;
+ALTER TABLE ended_leases DROP COLUMN revoked_by;
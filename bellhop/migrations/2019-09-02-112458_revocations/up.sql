@@ -0,0 +1,5 @@
+ALTER TABLE ended_leases ADD COLUMN revoked_by INTEGER NULL;
+ALTER TABLE ended_leases ADD COLUMN revoke_reason TEXT NULL;
+
+ALTER TABLE ended_leases ADD CONSTRAINT ended_leases_revoked_by_fkey
+    FOREIGN KEY(revoked_by) REFERENCES users(id) ON DELETE SET NULL;
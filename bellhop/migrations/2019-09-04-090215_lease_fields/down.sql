@@ -0,0 +1,4 @@
+DROP TABLE lease_field_values;
+DROP TABLE lease_fields;
+
+ALTER TABLE leases DROP COLUMN purpose;
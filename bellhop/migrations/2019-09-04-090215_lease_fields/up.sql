@@ -0,0 +1,25 @@
+ALTER TABLE leases ADD COLUMN purpose TEXT NULL;
+
+-- Extra information an asset type asks for when its assets are leased.
+CREATE TABLE lease_fields (
+    id SERIAL PRIMARY KEY NOT NULL,
+    asset_type_id INTEGER NOT NULL,
+
+    name TEXT NOT NULL,
+    required BOOLEAN NOT NULL DEFAULT FALSE,
+    pattern TEXT NULL,
+
+    UNIQUE(asset_type_id, name),
+    FOREIGN KEY(asset_type_id) REFERENCES asset_types(id) ON DELETE CASCADE
+);
+
+CREATE TABLE lease_field_values (
+    lease_id INTEGER NOT NULL,
+    lease_field_id INTEGER NOT NULL,
+
+    value TEXT NOT NULL,
+
+    PRIMARY KEY(lease_id, lease_field_id),
+    FOREIGN KEY(lease_id) REFERENCES leases(id) ON DELETE CASCADE,
+    FOREIGN KEY(lease_field_id) REFERENCES lease_fields(id) ON DELETE CASCADE
+);
@@ -0,0 +1,26 @@
+-- Limits on how much of an asset type one user can hold. A row without a
+-- user_id applies to everyone who doesn't have a row of their own.
+CREATE TABLE lease_quotas (
+    id SERIAL PRIMARY KEY NOT NULL,
+    asset_type_id INTEGER NOT NULL,
+    user_id INTEGER NULL,
+
+    max_leases INTEGER NULL,
+    max_hours_per_week INTEGER NULL,
+    allow_infinite BOOLEAN NOT NULL DEFAULT TRUE,
+
+    CONSTRAINT lease_quotas_positive CHECK (
+        (max_leases IS NULL OR max_leases > 0)
+        AND (max_hours_per_week IS NULL OR max_hours_per_week > 0)
+    ),
+
+    FOREIGN KEY(asset_type_id) REFERENCES asset_types(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- NULLs are never equal, so a plain UNIQUE(asset_type_id, user_id) would allow
+-- more than one default per asset type.
+CREATE UNIQUE INDEX lease_quotas_default ON lease_quotas (asset_type_id)
+    WHERE user_id IS NULL;
+CREATE UNIQUE INDEX lease_quotas_user ON lease_quotas (asset_type_id, user_id)
+    WHERE user_id IS NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE assets DROP COLUMN expected_back;
+ALTER TABLE assets DROP COLUMN status_reason;
+ALTER TABLE assets DROP COLUMN status;
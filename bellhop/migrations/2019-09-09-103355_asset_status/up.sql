@@ -0,0 +1,4 @@
+-- 0 is available, 1 is in maintenance, and 2 is retired.
+ALTER TABLE assets ADD COLUMN status SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE assets ADD COLUMN status_reason TEXT NULL;
+ALTER TABLE assets ADD COLUMN expected_back TIMESTAMP with time zone NULL;
@@ -0,0 +1,3 @@
+DROP TABLE prober;
+DROP TABLE probe_results;
+DROP TABLE probes;
@@ -0,0 +1,41 @@
+-- Health checks to run against every asset of a type. kind is 0 for a TCP
+-- connect, 1 for an HTTP GET, and 2 for a local command. `{name}` in target is
+-- replaced with the value of the asset's tag of that name.
+CREATE TABLE probes (
+    id SERIAL PRIMARY KEY NOT NULL,
+    asset_type_id INTEGER NOT NULL,
+
+    name TEXT NOT NULL,
+    kind SMALLINT NOT NULL,
+    target TEXT NOT NULL,
+    timeout_ms INTEGER NOT NULL DEFAULT 5000 CHECK (timeout_ms > 0),
+
+    -- Move assets that fail this probe into maintenance.
+    auto_maintenance BOOLEAN NOT NULL DEFAULT FALSE,
+
+    UNIQUE(asset_type_id, name),
+    FOREIGN KEY(asset_type_id) REFERENCES asset_types(id) ON DELETE CASCADE
+);
+
+-- The most recent outcome of each probe, for each asset.
+CREATE TABLE probe_results (
+    probe_id INTEGER NOT NULL,
+    asset_id INTEGER NOT NULL,
+
+    ok BOOLEAN NOT NULL,
+    latency_ms INTEGER NULL,
+    message TEXT NULL,
+    checked_at TIMESTAMP with time zone NOT NULL,
+
+    PRIMARY KEY(probe_id, asset_id),
+    FOREIGN KEY(probe_id) REFERENCES probes(id) ON DELETE CASCADE,
+    FOREIGN KEY(asset_id) REFERENCES assets(id) ON DELETE CASCADE
+);
+
+-- Like the sheriff table, so only one server probes at a time.
+CREATE TABLE prober (
+    primary_key BOOLEAN PRIMARY KEY NOT NULL DEFAULT true CHECK (primary_key),
+    last_checked TIMESTAMP with time zone NOT NULL
+);
+
+INSERT INTO prober (last_checked) VALUES ('epoch');
@@ -0,0 +1,3 @@
+ALTER TABLE leases
+    DROP COLUMN heartbeat_secs,
+    DROP COLUMN last_heartbeat;
@@ -0,0 +1,5 @@
+-- Leases with a heartbeat interval are abandoned if their holder doesn't
+-- check in for that many seconds. The first interval counts from start_time.
+ALTER TABLE leases
+    ADD COLUMN heartbeat_secs INTEGER NULL CHECK (heartbeat_secs > 0),
+    ADD COLUMN last_heartbeat TIMESTAMP with time zone NULL;
@@ -0,0 +1,3 @@
+DROP TABLE recurring_leases;
+DROP TABLE recurring_reservation_tags;
+DROP TABLE recurring_reservations;
@@ -0,0 +1,51 @@
+-- Rules the sheriff turns into upcoming leases. frequency is 0 for daily, 1
+-- for weekdays, and 2 for weekly on day_of_week (1 is Monday, as in ISO 8601).
+-- start_time is local to timezone, which is an IANA zone name.
+CREATE TABLE recurring_reservations (
+    id SERIAL PRIMARY KEY NOT NULL,
+    asset_type_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+
+    -- A specific asset, or null for any asset of the type that has the tags
+    -- in recurring_reservation_tags.
+    asset_id INTEGER NULL,
+
+    frequency SMALLINT NOT NULL,
+    day_of_week SMALLINT NULL CHECK (day_of_week BETWEEN 1 AND 7),
+    start_time TIME NOT NULL,
+    duration_mins INTEGER NOT NULL CHECK (duration_mins > 0),
+    timezone TEXT NOT NULL,
+
+    purpose TEXT NULL,
+
+    -- Occurrences starting before this have already been turned into leases.
+    scheduled_until TIMESTAMP with time zone NULL,
+
+    CHECK ((frequency = 2) = (day_of_week IS NOT NULL)),
+
+    FOREIGN KEY(asset_type_id) REFERENCES asset_types(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(asset_id) REFERENCES assets(id) ON DELETE CASCADE
+);
+
+CREATE TABLE recurring_reservation_tags (
+    recurring_reservation_id INTEGER NOT NULL,
+    tag_type_id INTEGER NOT NULL,
+
+    value VARCHAR(255) NOT NULL,
+
+    FOREIGN KEY(recurring_reservation_id) REFERENCES recurring_reservations(id) ON DELETE CASCADE,
+    FOREIGN KEY(tag_type_id) REFERENCES tag_types(id) ON DELETE CASCADE,
+    PRIMARY KEY(recurring_reservation_id, tag_type_id)
+);
+
+-- Leases created from a recurring reservation, so upcoming ones can be
+-- cleaned up when the rule changes.
+CREATE TABLE recurring_leases (
+    recurring_reservation_id INTEGER NOT NULL,
+    lease_id INTEGER NOT NULL,
+
+    FOREIGN KEY(recurring_reservation_id) REFERENCES recurring_reservations(id) ON DELETE CASCADE,
+    FOREIGN KEY(lease_id) REFERENCES leases(id) ON DELETE CASCADE,
+    PRIMARY KEY(recurring_reservation_id, lease_id)
+);
@@ -0,0 +1,12 @@
+-- Secret tokens that let calendar apps, which can't log in, read iCalendar
+-- feeds. Each user can have several, so one app can be cut off at a time.
+CREATE TABLE feed_tokens (
+    id SERIAL PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+
+    token VARCHAR(64) NOT NULL UNIQUE,
+
+    created_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,6 @@
+DROP TABLE lease_request_fields;
+DROP TABLE lease_requests;
+DROP TABLE approvers;
+
+ALTER TABLE asset_types
+    DROP COLUMN requires_approval;
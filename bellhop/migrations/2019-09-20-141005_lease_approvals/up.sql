@@ -0,0 +1,54 @@
+-- Leases on asset types that require approval start out as requests, which
+-- one of the type's approvers has to approve before the lease is created.
+ALTER TABLE asset_types
+    ADD COLUMN requires_approval BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE approvers (
+    asset_type_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+
+    FOREIGN KEY(asset_type_id) REFERENCES asset_types(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY(asset_type_id, user_id)
+);
+
+-- status is 0 while pending, then 1 for approved, 2 for denied, or 3 for
+-- expired. lease_id is set once a request is approved.
+CREATE TABLE lease_requests (
+    id SERIAL PRIMARY KEY NOT NULL,
+    asset_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+
+    start_time TIMESTAMP with time zone NOT NULL,
+    end_time TIMESTAMP with time zone NULL,
+    purpose TEXT NULL,
+    heartbeat_secs INTEGER NULL CHECK (heartbeat_secs > 0),
+
+    status SMALLINT NOT NULL DEFAULT 0,
+    requested_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+    expires_at TIMESTAMP with time zone NOT NULL,
+
+    decided_by INTEGER NULL,
+    decided_at TIMESTAMP with time zone NULL,
+    decision_reason TEXT NULL,
+
+    lease_id INTEGER NULL,
+
+    FOREIGN KEY(asset_id) REFERENCES assets(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(decided_by) REFERENCES users(id) ON DELETE SET NULL,
+    FOREIGN KEY(lease_id) REFERENCES leases(id) ON DELETE SET NULL
+);
+
+CREATE INDEX lease_requests_pending ON lease_requests (expires_at) WHERE status = 0;
+
+-- Custom field values given with a request, by field name. They're checked
+-- again when the request is approved.
+CREATE TABLE lease_request_fields (
+    lease_request_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+
+    FOREIGN KEY(lease_request_id) REFERENCES lease_requests(id) ON DELETE CASCADE,
+    PRIMARY KEY(lease_request_id, name)
+);
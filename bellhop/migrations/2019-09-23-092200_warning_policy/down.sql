@@ -0,0 +1,3 @@
+ALTER TABLE asset_types
+    DROP COLUMN warn_before_secs,
+    DROP COLUMN warn_percent;
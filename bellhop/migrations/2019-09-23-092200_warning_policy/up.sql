@@ -0,0 +1,5 @@
+-- Overrides of the sheriff's global warning policy for leases on assets of a
+-- type. NULL means the global setting applies.
+ALTER TABLE asset_types
+    ADD COLUMN warn_before_secs INTEGER NULL CHECK (warn_before_secs >= 0),
+    ADD COLUMN warn_percent INTEGER NULL CHECK (warn_percent BETWEEN 0 AND 100);
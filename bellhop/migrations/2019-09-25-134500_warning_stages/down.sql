@@ -0,0 +1,6 @@
+ALTER TABLE asset_types
+    DROP CONSTRAINT asset_types_warn_before_secs_check,
+    ALTER COLUMN warn_before_secs TYPE INTEGER USING warn_before_secs[1],
+    ADD CONSTRAINT asset_types_warn_before_secs_check CHECK (warn_before_secs >= 0);
+
+DROP TABLE lease_warnings;
@@ -0,0 +1,24 @@
+-- Owners can be warned several times before their lease ends. Each warning is
+-- identified by how long before the end of the lease it was due, and covers
+-- every stage due earlier than it.
+CREATE TABLE lease_warnings (
+    lease_id INTEGER NOT NULL REFERENCES leases(id) ON DELETE CASCADE,
+    before_secs INTEGER NOT NULL CHECK (before_secs >= 0),
+    warned_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (lease_id, before_secs)
+);
+
+-- Leases that were already warned shouldn't be warned again for the same end
+-- time.
+INSERT INTO lease_warnings (lease_id, before_secs, warned_at)
+    SELECT id, GREATEST(EXTRACT(EPOCH FROM end_time - last_notified), 0)::INTEGER, last_notified
+    FROM leases
+    WHERE last_notified IS NOT NULL AND end_time IS NOT NULL;
+
+-- Asset types can override the lead time of every stage, not just one.
+ALTER TABLE asset_types
+    DROP CONSTRAINT asset_types_warn_before_secs_check,
+    ALTER COLUMN warn_before_secs TYPE INTEGER[] USING
+        CASE WHEN warn_before_secs IS NULL THEN NULL ELSE ARRAY[warn_before_secs] END,
+    ADD CONSTRAINT asset_types_warn_before_secs_check CHECK (0 <= ALL(warn_before_secs));
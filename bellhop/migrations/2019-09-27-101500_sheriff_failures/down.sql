@@ -0,0 +1 @@
+DROP TABLE sheriff_failures;
@@ -0,0 +1,17 @@
+-- Errors the sheriff ran into, kept so admins can see what went wrong
+-- without digging through the server's logs.
+CREATE TABLE sheriff_failures (
+    id SERIAL PRIMARY KEY NOT NULL,
+
+    task VARCHAR(64) NOT NULL,
+
+    -- Not a foreign key, since the lease is often gone by the time anyone
+    -- looks, but the id is still useful to find its ended lease.
+    lease_id INTEGER,
+
+    message TEXT NOT NULL,
+
+    failed_at TIMESTAMP with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX sheriff_failures_failed_at_idx ON sheriff_failures (failed_at);
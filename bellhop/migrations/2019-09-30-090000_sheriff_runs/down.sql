@@ -0,0 +1,3 @@
+ALTER TABLE sheriff DROP COLUMN next_run;
+
+DROP TABLE sheriff_runs;
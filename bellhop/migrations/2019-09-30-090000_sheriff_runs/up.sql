@@ -0,0 +1,23 @@
+-- One row for each time the sheriff ran its tasks, so admins can see what it
+-- has been doing.
+CREATE TABLE sheriff_runs (
+    id SERIAL PRIMARY KEY NOT NULL,
+
+    -- Started by an admin, rather than on schedule.
+    manual BOOLEAN NOT NULL DEFAULT false,
+
+    started_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+    finished_at TIMESTAMP with time zone,
+
+    evicted INTEGER NOT NULL DEFAULT 0,
+    scheduled INTEGER NOT NULL DEFAULT 0,
+    expired INTEGER NOT NULL DEFAULT 0,
+    activated INTEGER NOT NULL DEFAULT 0,
+    warned INTEGER NOT NULL DEFAULT 0,
+    failures INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX sheriff_runs_started_at_idx ON sheriff_runs (started_at);
+
+-- When the leader plans to run next.
+ALTER TABLE sheriff ADD COLUMN next_run TIMESTAMP with time zone;
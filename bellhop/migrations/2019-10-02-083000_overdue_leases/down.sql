@@ -0,0 +1,12 @@
+-- Assets that are cooling down have nowhere to go but back into service.
+UPDATE assets
+    SET status = 0, status_reason = NULL, expected_back = NULL
+    WHERE status = 3;
+
+ALTER TABLE sheriff_runs DROP COLUMN overdue;
+
+ALTER TABLE leases DROP COLUMN overdue_at;
+
+ALTER TABLE asset_types
+    DROP COLUMN cooldown_secs,
+    DROP COLUMN grace_secs;
@@ -0,0 +1,11 @@
+-- How long an expired lease is left alone, marked as overdue, before the
+-- sheriff evicts it, and how long its assets then rest before anyone else can
+-- lease them.
+ALTER TABLE asset_types
+    ADD COLUMN grace_secs INTEGER NOT NULL DEFAULT 0 CHECK (grace_secs >= 0),
+    ADD COLUMN cooldown_secs INTEGER NOT NULL DEFAULT 0 CHECK (cooldown_secs >= 0);
+
+-- When the sheriff noticed the lease was past its end time.
+ALTER TABLE leases ADD COLUMN overdue_at TIMESTAMP with time zone;
+
+ALTER TABLE sheriff_runs ADD COLUMN overdue INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+DROP TABLE hook_deliveries;
@@ -0,0 +1,26 @@
+-- Hook events waiting to be delivered, or kept for a while afterwards, so
+-- that a slow or broken hook doesn't hold up the change that caused them.
+CREATE TABLE hook_deliveries (
+    id SERIAL PRIMARY KEY NOT NULL,
+
+    -- Which hook the event is for, and which of its functions to call.
+    hook VARCHAR(255) NOT NULL,
+    event SMALLINT NOT NULL,
+
+    -- Copies of the lease, assets, users and so on, since they might be gone
+    -- by the time the event is delivered.
+    payload JSONB NOT NULL,
+
+    -- 0: pending, 1: delivered, 2: dead.
+    status SMALLINT NOT NULL DEFAULT 0 CHECK (status >= 0 AND status <= 2),
+
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+
+    created_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+    next_attempt_at TIMESTAMP with time zone NOT NULL DEFAULT now(),
+    delivered_at TIMESTAMP with time zone
+);
+
+CREATE INDEX hook_deliveries_due_idx ON hook_deliveries (status, next_attempt_at);
+CREATE INDEX hook_deliveries_created_at_idx ON hook_deliveries (created_at);
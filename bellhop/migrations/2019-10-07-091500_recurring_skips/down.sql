@@ -0,0 +1,3 @@
+ALTER TABLE recurring_reservations
+    DROP COLUMN skipped_reason,
+    DROP COLUMN skipped_at;
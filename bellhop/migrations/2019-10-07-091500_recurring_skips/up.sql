@@ -0,0 +1,5 @@
+-- The last occurrence the sheriff couldn't turn into a lease, and why, so the
+-- owner of the rule can find out.
+ALTER TABLE recurring_reservations
+    ADD COLUMN skipped_at TIMESTAMP with time zone NULL,
+    ADD COLUMN skipped_reason TEXT NULL;
@@ -0,0 +1,235 @@
+//! Turns lease requests on asset types that require approval into leases.
+
+use crate::db::Db as PubDb;
+use crate::errors::*;
+use crate::hooks::{Data as HookData, RequestData};
+use crate::internal::hooks::Hooks;
+use crate::models::approver::Approver;
+use crate::models::asset::Asset;
+use crate::models::asset_type::AssetType;
+use crate::models::lease::{CreateLease, Lease, Reserved};
+use crate::models::lease_field::{FieldValue, LeaseField};
+use crate::models::lease_quota;
+use crate::models::lease_request::{self, LeaseRequest, RequestStatus};
+use crate::models::user::User;
+use crate::sheriff::{self, Failures};
+
+use chrono::prelude::*;
+
+use diesel::prelude::*;
+
+use std::collections::BTreeMap;
+
+/// Ask the approvers of `asset_type` for the lease in `create` on `asset`.
+///
+/// `fields` must already have been checked against the type's lease fields.
+pub(crate) fn request(
+    c: &PgConnection,
+    hooks: &Hooks,
+    asset: &Asset,
+    asset_type: &AssetType,
+    requester: &User,
+    create: &CreateLease,
+    fields: &BTreeMap<String, String>,
+) -> Result<LeaseRequest> {
+    c.transaction::<_, Error, _>(|| {
+        let request = lease_request::insert(c, create, asset.id(), fields)?;
+
+        let approvers = Approver::for_type(c, asset_type.id())?;
+        let data = RequestData::new(&request, requester, asset, asset_type, &approvers);
+
+        hooks.requested(c, data)?;
+
+        Ok(request)
+    })
+}
+
+/// Refuse to let the user identified by `user_id` hold `lease`, by transfer
+/// or as a co-owner, if any of its asset types require approval and they
+/// aren't one of that type's approvers, since nobody approved them.
+///
+/// The error has `ErrorKind::Rejected`.
+pub(crate) fn check_holder(c: &PgConnection, lease: &Lease, user_id: i32) -> Result<()> {
+    for type_id in lease.type_ids(c)? {
+        let asset_type = AssetType::by_id(c, type_id)?.chain_err(|| "missing asset_type")?;
+
+        if asset_type.requires_approval() && !Approver::exists(c, type_id, user_id)? {
+            let reason = format!(
+                "{} require approval, so leases on them can only be shared with or handed \
+                 to their approvers.",
+                asset_type.plural_name()
+            );
+
+            return Err(ErrorKind::Rejected(reason).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The outcome of [`approve`].
+#[derive(Debug)]
+pub(crate) enum Approved {
+    /// The lease was created.
+    Approved(LeaseRequest, Lease),
+
+    /// The request was already decided on, or has expired.
+    NotPending,
+
+    /// The asset is out of service, or already leased for some of the
+    /// requested time.
+    Conflict,
+
+    /// The request's custom fields no longer satisfy the asset type's lease
+    /// fields, or the lease would already be over.
+    Invalid,
+}
+
+/// Approve the pending request identified by `request_id` on behalf of
+/// `approver`, and create the lease it asked for.
+///
+/// Quotas and `before_lease` hooks apply as if the requester had taken the
+/// lease themselves, so they can still refuse it.
+pub(crate) fn approve(
+    c: &PgConnection,
+    hooks: &Hooks,
+    request_id: i32,
+    approver: &User,
+) -> Result<Approved> {
+    c.transaction::<_, Error, _>(|| {
+        let request = match LeaseRequest::lock(c, request_id)? {
+            Some(ref x) if x.status() != RequestStatus::Pending => return Ok(Approved::NotPending),
+            Some(x) => x,
+            None => return Ok(Approved::NotPending),
+        };
+
+        // The sheriff might not have gotten around to expiring it yet.
+        if request.expires_at() <= Utc::now() {
+            return Ok(Approved::NotPending);
+        }
+
+        let asset = Asset::by_id(c, request.asset_id())?.chain_err(|| "missing asset")?;
+        if !asset.is_available() {
+            return Ok(Approved::Conflict);
+        }
+
+        let asset_type =
+            AssetType::by_id(c, asset.type_id())?.chain_err(|| "missing asset_type")?;
+
+        let lease_fields = LeaseField::for_types(c, &[asset_type.id()])?;
+        let fields = match LeaseField::resolve(&lease_fields, &request.fields(c)?) {
+            Some(x) => x,
+            None => return Ok(Approved::Invalid),
+        };
+
+        let create = request.to_create_lease();
+
+        let (lease, started) = match create.reserve(c, asset.id(), &fields)? {
+            Reserved::Started(lease, asset) => (lease, Some(asset)),
+            Reserved::Upcoming(lease) => (lease, None),
+            Reserved::Conflict => return Ok(Approved::Conflict),
+            Reserved::Invalid => return Ok(Approved::Invalid),
+        };
+
+        lease_quota::enforce(c, &lease, &[asset_type.id()])?;
+
+        let fields = FieldValue::for_lease(c, lease.id())?;
+
+        let held = started.as_ref().unwrap_or(&asset);
+        let data = HookData::new(&lease, held, &asset_type).with_fields(&fields);
+
+        hooks.before_lease(c, data.clone())?;
+
+        let request = request
+            .decide(
+                c,
+                RequestStatus::Approved,
+                Some(approver.id()),
+                None,
+                Some(lease.id()),
+            )?
+            .chain_err(|| "lease request changed while locked")?;
+
+        let requester =
+            User::by_id(&PubDb::from(c), request.user_id())?.chain_err(|| "missing user")?;
+        let approvers = Approver::for_type(c, asset_type.id())?;
+
+        let request_data = RequestData::new(&request, &requester, held, &asset_type, &approvers);
+        hooks.approved(c, request_data)?;
+
+        // Upcoming leases don't hold the asset yet, so the sheriff calls
+        // `leased` when they start.
+        if started.is_some() {
+            hooks.leased(c, data)?;
+        }
+
+        Ok(Approved::Approved(request, lease))
+    })
+}
+
+/// Move the pending `request` to `status`, which must be either `Denied` or
+/// `Expired`, and tell the hooks.
+///
+/// Returns `None` if the request isn't pending anymore.
+pub(crate) fn deny(
+    c: &PgConnection,
+    hooks: &Hooks,
+    request: &LeaseRequest,
+    status: RequestStatus,
+    by: Option<&User>,
+    reason: Option<&str>,
+) -> Result<Option<LeaseRequest>> {
+    c.transaction::<_, Error, _>(|| {
+        let request = match request.decide(c, status, by.map(User::id), reason, None)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let asset = Asset::by_id(c, request.asset_id())?.chain_err(|| "missing asset")?;
+        let asset_type =
+            AssetType::by_id(c, asset.type_id())?.chain_err(|| "missing asset_type")?;
+
+        let requester =
+            User::by_id(&PubDb::from(c), request.user_id())?.chain_err(|| "missing user")?;
+        let approvers = Approver::for_type(c, asset_type.id())?;
+
+        let data = RequestData::new(&request, &requester, &asset, &asset_type, &approvers);
+        hooks.denied(c, data)?;
+
+        Ok(Some(request))
+    })
+}
+
+/// Expire every pending request that nobody decided on in time.
+pub(crate) fn expire(c: &PgConnection, hooks: &Hooks, failures: &Failures) -> Result<usize> {
+    // Each request gets its own transaction, so a failed hook only keeps
+    // that request pending until the next run, and the others stay expired
+    // even if a later one fails.
+    let mut num_expired = 0;
+
+    for request in LeaseRequest::expired(c)? {
+        let denied = deny(c, hooks, &request, RequestStatus::Expired, None, None);
+
+        if let Some(Some(_)) = sheriff::isolate(failures, sheriff::EXPIRE, None, denied)? {
+            num_expired += 1;
+        }
+    }
+
+    if num_expired > 0 {
+        println!("The sheriff expired {:?} lease requests.", num_expired);
+    }
+
+    Ok(num_expired)
+}
+
+/// Returns `true` if `user` can decide on `request`. Approvers can't decide
+/// on their own requests.
+pub(crate) fn can_decide(c: &PgConnection, user: &User, request: &LeaseRequest) -> Result<bool> {
+    if request.user_id() == user.id() {
+        return Ok(false);
+    }
+
+    let asset = Asset::by_id(c, request.asset_id())?.chain_err(|| "missing asset")?;
+
+    Approver::exists(c, asset.type_id(), user.id())
+}
@@ -1,3 +1,24 @@
 #![allow(missing_docs)]
 
-error_chain! {}
+error_chain! {
+    foreign_links {
+        Diesel(::diesel::result::Error);
+    }
+
+    errors {
+        Rejected(reason: String) {
+            description("rejected by a hook")
+            display("{}", reason)
+        }
+
+        QuotaExceeded(exceeded: crate::models::lease_quota::Exceeded) {
+            description("lease quota exceeded")
+            display("{}", exceeded)
+        }
+
+        Unavailable {
+            description("database unavailable")
+            display("couldn't get database connection")
+        }
+    }
+}
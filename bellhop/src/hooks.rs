@@ -11,7 +11,12 @@
 use crate::db::Db;
 use crate::models::asset::Asset;
 use crate::models::asset_type::AssetType;
+use crate::models::ended_lease::EndedLease;
 use crate::models::lease::Lease;
+use crate::models::lease_request::LeaseRequest;
+use crate::models::user::User;
+
+use chrono::{DateTime, Duration, Utc};
 
 use rocket::Rocket;
 
@@ -23,6 +28,10 @@ use std::fmt;
 pub enum ErrorKind {
     /// A custom error string.
     Msg(String),
+
+    /// The operation isn't allowed, for a reason the user should see. Only
+    /// meaningful when returned from the `before_*` hooks.
+    Rejected(String),
 }
 
 impl ErrorKind {
@@ -36,6 +45,7 @@ impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ErrorKind::Msg(ref x) => write!(f, "{}", x),
+            ErrorKind::Rejected(ref x) => write!(f, "{}", x),
         }
     }
 }
@@ -75,6 +85,18 @@ impl Error {
     pub fn with_msg<S: Into<String>>(s: S) -> Self {
         Error(ErrorKind::Msg(s.into()), None)
     }
+
+    /// Shortcut for creating an [`Error`] with [`ErrorKind::Rejected`] and no
+    /// cause.
+    ///
+    /// ```
+    /// use bellhop::hooks::Error;
+    ///
+    /// let error = Error::rejected("device is offline");
+    /// ```
+    pub fn rejected<S: Into<String>>(reason: S) -> Self {
+        Error(ErrorKind::Rejected(reason.into()), None)
+    }
 }
 
 impl fmt::Display for Error {
@@ -95,6 +117,11 @@ pub struct Data<'a> {
     asset_type: &'a AssetType,
     asset: &'a Asset,
     lease: &'a Lease,
+    fields: &'a [(String, String)],
+    owners: Option<&'a [User]>,
+    ended: Option<&'a EndedLease>,
+    warning: Option<&'a Warning>,
+    evict_at: Option<DateTime<Utc>>,
 }
 
 impl<'a> Data<'a> {
@@ -103,9 +130,45 @@ impl<'a> Data<'a> {
             lease,
             asset,
             asset_type,
+            fields: &[],
+            owners: None,
+            ended: None,
+            warning: None,
+            evict_at: None,
         }
     }
 
+    pub(crate) fn with_fields(mut self, fields: &'a [(String, String)]) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Only needed where the lease ends before the hooks are called, since
+    /// its co-owners go with it. Otherwise they're looked up for the hooks.
+    pub(crate) fn with_owners(mut self, owners: &'a [User]) -> Self {
+        self.owners = Some(owners);
+        self
+    }
+
+    pub(crate) fn has_owners(&self) -> bool {
+        self.owners.is_some()
+    }
+
+    pub(crate) fn with_ended(mut self, ended: &'a EndedLease) -> Self {
+        self.ended = Some(ended);
+        self
+    }
+
+    pub(crate) fn with_warning(mut self, warning: &'a Warning) -> Self {
+        self.warning = Some(warning);
+        self
+    }
+
+    pub(crate) fn with_evict_at(mut self, evict_at: DateTime<Utc>) -> Self {
+        self.evict_at = Some(evict_at);
+        self
+    }
+
     /// The `AssetType` associated with the `Asset` that generated this event.
     pub fn asset_type(&self) -> &AssetType {
         self.asset_type
@@ -123,33 +186,317 @@ impl<'a> Data<'a> {
     pub fn lease(&self) -> &Lease {
         self.lease
     }
+
+    /// The name and value of each custom field given when the `Lease` was
+    /// created, as configured on its asset types.
+    pub fn fields(&self) -> &[(String, String)] {
+        self.fields
+    }
+
+    /// Everyone who shared the `Lease` when the event happened, starting with
+    /// its owner.
+    ///
+    /// Unlike [`Lease::owners`], this still includes the co-owners after the
+    /// lease has ended, and for events delivered through the outbox.
+    pub fn owners(&self) -> &[User] {
+        self.owners.unwrap_or(&[])
+    }
+
+    /// The archived copy of the `Lease`, if this event ended it.
+    ///
+    /// Unlike the `Lease` itself, this is still in the database when the hook
+    /// is invoked.
+    pub fn ended(&self) -> Option<&EndedLease> {
+        self.ended
+    }
+
+    /// Which warning stage was reached, if this event is a warning.
+    pub fn warning(&self) -> Option<&Warning> {
+        self.warning
+    }
+
+    /// When the sheriff will evict the `Lease`, if this event is about it
+    /// being overdue. That's once the longest grace period of all the asset
+    /// types the lease holds has run out.
+    pub fn evict_at(&self) -> Option<DateTime<Utc>> {
+        self.evict_at
+    }
+}
+
+/// A stage of the warnings sent before a `Lease` ends.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    stage: usize,
+    stages: usize,
+    before: Duration,
+    remaining: Duration,
+}
+
+impl Warning {
+    pub(crate) fn new(stage: usize, stages: usize, before: Duration, remaining: Duration) -> Self {
+        Warning {
+            stage,
+            stages,
+            before,
+            remaining,
+        }
+    }
+
+    /// The index of this stage, starting from zero for the earliest warning.
+    /// Stages that were skipped, because a later stage was already due, still
+    /// count.
+    pub fn stage(&self) -> usize {
+        self.stage
+    }
+
+    /// How many stages there are for the `Lease`.
+    pub fn stages(&self) -> usize {
+        self.stages
+    }
+
+    /// Whether this is the final warning before the `Lease` ends.
+    pub fn is_last(&self) -> bool {
+        self.stage + 1 == self.stages
+    }
+
+    /// How long before the end of the `Lease` this stage was due.
+    pub fn before(&self) -> Duration {
+        self.before
+    }
+
+    /// How much time was left on the `Lease` when the warning was sent.
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+}
+
+/// Data that is provided to `Hook` functions about a `LeaseRequest`.
+#[derive(Debug, Clone)]
+pub struct RequestData<'a> {
+    asset_type: &'a AssetType,
+    asset: &'a Asset,
+    request: &'a LeaseRequest,
+    requester: &'a User,
+    approvers: &'a [User],
+}
+
+impl<'a> RequestData<'a> {
+    pub(crate) fn new(
+        request: &'a LeaseRequest,
+        requester: &'a User,
+        asset: &'a Asset,
+        asset_type: &'a AssetType,
+        approvers: &'a [User],
+    ) -> Self {
+        Self {
+            asset_type,
+            asset,
+            request,
+            requester,
+            approvers,
+        }
+    }
+
+    /// The `AssetType` of the requested `Asset`, which requires approval.
+    pub fn asset_type(&self) -> &AssetType {
+        self.asset_type
+    }
+
+    /// The requested `Asset`.
+    pub fn asset(&self) -> &Asset {
+        self.asset
+    }
+
+    /// The `LeaseRequest` associated with this event. Its status says what
+    /// became of it.
+    pub fn request(&self) -> &LeaseRequest {
+        self.request
+    }
+
+    /// The `User` who asked for the lease.
+    pub fn requester(&self) -> &User {
+        self.requester
+    }
+
+    /// Every `User` who can approve requests for the asset type.
+    pub fn approvers(&self) -> &[User] {
+        self.approvers
+    }
+}
+
+/// How events are handed to a `Hook`. The `before_*` functions are always
+/// called right away, since they can cancel what's happening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Call the hook right away, in the request or sheriff run that caused
+    /// the event. A slow hook holds it up, and an error fails it.
+    Immediate,
+
+    /// Write the event to the outbox, in the same transaction as the change
+    /// that caused it, and call the hook from a background worker. Failed
+    /// calls are retried with backoff, until they're given up on as dead.
+    ///
+    /// The hook sees copies of the lease, assets and users as they were when
+    /// the event happened, and may be called more than once for the same
+    /// event.
+    Outbox,
 }
 
 /// Trait for plugins that want notifications when `Lease` events are generated.
 pub trait Hook: fmt::Debug {
+    /// A short name for this hook, like `"email"`, which must be different
+    /// from every other registered hook's.
+    ///
+    /// Events in the outbox are stored under this name, so it has to stay the
+    /// same between releases. Renaming a hook orphans its pending events.
+    ///
+    /// Defaults to the name of the hook's type, which changes if the type is
+    /// moved or renamed, so hooks using [`Delivery::Outbox`] should pick
+    /// their own.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
     /// Perform Rocket related setup, like attaching routes and fairings,
     /// reading configuration values, etc.
     fn prelaunch(&self, rocket: Rocket) -> Rocket {
         rocket
     }
 
+    /// How events, other than the `before_*` ones, are handed to this hook.
+    /// Defaults to [`Delivery::Immediate`].
+    fn delivery(&self) -> Delivery {
+        Delivery::Immediate
+    }
+
+    /// Called for each hook when a lease is about to be created, for every
+    /// asset it will hold.
+    ///
+    /// Runs inside the transaction that creates the lease, so the lease is
+    /// already visible through `conn`. Returning [`Error::rejected`] cancels
+    /// the lease and shows the reason to the user. Any other error cancels it
+    /// too, as an internal error.
+    fn before_lease(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Called for each hook when a lease is created.
+    ///
+    /// With [`Delivery::Immediate`], runs inside the same transaction as
+    /// `before_lease`, so an error here also cancels the lease.
     fn leased(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
         Ok(())
     }
 
+    /// Called for each hook when a lease's owner is about to return it, for
+    /// every asset it holds.
+    ///
+    /// Returning [`Error::rejected`] keeps the lease, and shows the reason to
+    /// the user.
+    fn before_return(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Called for each hook when a lease is returned before it expires.
+    ///
+    /// With [`Delivery::Immediate`], runs inside the transaction that ends
+    /// the lease, so an error here keeps the lease.
     fn returned(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
         Ok(())
     }
 
     /// Called for each hook after a lease has been deleted.
+    ///
+    /// Covers leases that expired as well as leases that stopped sending
+    /// heartbeats; the ended lease's reason tells them apart.
     fn evicted(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
         Ok(())
     }
 
-    /// Called for each hook when the eviction notice should be sent.
+    /// Called for each hook when the eviction notice should be sent, once for
+    /// each warning stage. [`Data::warning`] says which stage was reached.
+    ///
+    /// Notices should go to every one of [`Data::owners`], not just the
+    /// lease's `user_id`.
     fn warned(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
         Ok(())
     }
+
+    /// Called for each hook when a lease is past its end time, but its asset
+    /// type's grace period hasn't run out, so the sheriff hasn't evicted it
+    /// yet. Called once for each lease, unless it's extended.
+    /// [`Data::evict_at`] says when it will be.
+    ///
+    /// Like warnings, notices should go to every one of [`Data::owners`].
+    fn overdue(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when a lease's end time is pushed out.
+    fn extended(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook after an administrator ends someone else's lease.
+    /// The reason given, and who gave it, are on `data.ended()`.
+    fn revoked(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when a freed asset is handed to the next user on
+    /// its type's waitlist. `leased` is called for the new lease as well.
+    fn handed_off(&self, _conn: &Db, _data: Data) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when a lease is handed from one user to another.
+    /// The lease in `data` already belongs to `to`, and `data.ended()` is
+    /// the archived copy for `from`.
+    fn transferred(&self, _conn: &Db, _data: Data, _from: &User, _to: &User) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when someone asks for a lease on an asset whose
+    /// type requires approval.
+    ///
+    /// With [`Delivery::Immediate`], runs inside the transaction that creates
+    /// the request, so an error here cancels it.
+    fn requested(&self, _conn: &Db, _data: RequestData) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when an approver approves a lease request.
+    /// `leased` is called for the new lease as well, once it starts.
+    fn approved(&self, _conn: &Db, _data: RequestData) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when a lease request is denied by an approver,
+    /// or expires before anyone decides on it. The request's status tells
+    /// them apart.
+    fn denied(&self, _conn: &Db, _data: RequestData) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when an asset is taken out of service for
+    /// maintenance. The reason and expected return time are on `asset`.
+    fn entered_maintenance(
+        &self,
+        _conn: &Db,
+        _asset: &Asset,
+        _asset_type: &AssetType,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each hook when an asset in maintenance changes to any other
+    /// status, either back in service or retired.
+    fn left_maintenance(
+        &self,
+        _conn: &Db,
+        _asset: &Asset,
+        _asset_type: &AssetType,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
 }
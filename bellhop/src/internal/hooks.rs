@@ -1,64 +1,589 @@
 use crate::db::Db as PubDb;
-use crate::hooks::{Data, Hook};
+use crate::hooks::{
+    Data, Delivery, Error as HookError, ErrorKind as HookErrorKind, Hook, RequestData, Warning,
+};
+use crate::models::asset::Asset;
+use crate::models::asset_type::AssetType;
+use crate::models::ended_lease::EndedLease;
+use crate::models::hook_delivery::{HookDelivery, HookEvent};
+use crate::models::lease::Lease;
+use crate::models::lease_request::LeaseRequest;
+use crate::models::user::User;
+
+use chrono::{DateTime, Duration, Utc};
 
 use diesel::prelude::*;
 
+use rocket::Rocket;
+
+use serde::de::DeserializeOwned;
+
+use serde_json::{json, Value};
+
 use std::sync::Arc;
 
 #[derive(Debug, Default, Clone)]
 pub(crate) struct Hooks(pub Arc<Vec<Box<dyn Hook + Sync + Send>>>);
 
+/// Turn a `before_*` hook's rejection into an `ErrorKind::Rejected`, so it can
+/// be shown to the user. Other errors are chained as usual.
+fn veto(e: HookError) -> crate::errors::Error {
+    use crate::errors::*;
+
+    match e.0 {
+        HookErrorKind::Rejected(ref reason) => ErrorKind::Rejected(reason.clone()).into(),
+        _ => Error::with_chain(e, "error running hook"),
+    }
+}
+
 impl Hooks {
+    pub fn prelaunch(&self, mut rocket: Rocket) -> Rocket {
+        for hook in self.0.iter() {
+            rocket = hook.prelaunch(rocket);
+        }
+
+        rocket
+    }
+
+    /// Every hook, for the `before_*` functions.
+    fn all(&self) -> impl Iterator<Item = &(dyn Hook + Sync + Send)> {
+        self.0.iter().map(|x| x.as_ref())
+    }
+
+    /// The hooks that want their events right away.
+    fn immediate(&self) -> impl Iterator<Item = &(dyn Hook + Sync + Send)> {
+        self.all().filter(|x| x.delivery() == Delivery::Immediate)
+    }
+
+    /// Queue `event` for each hook that takes its events from the outbox.
+    /// `payload` is only built if there are any.
+    fn enqueue<F>(
+        &self,
+        db: &PgConnection,
+        event: HookEvent,
+        payload: F,
+    ) -> crate::errors::Result<()>
+    where
+        F: FnOnce() -> Value,
+    {
+        let mut queued = self
+            .all()
+            .filter(|x| x.delivery() == Delivery::Outbox)
+            .peekable();
+
+        if queued.peek().is_none() {
+            return Ok(());
+        }
+
+        let payload = payload();
+
+        for hook in queued {
+            HookDelivery::enqueue(db, hook.name(), event, &payload)?;
+        }
+
+        Ok(())
+    }
+
+    /// Call the hook `delivery` is for with its event, as the outbox worker
+    /// does.
+    pub fn deliver(&self, db: &PgConnection, delivery: &HookDelivery) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        let hook = match self.all().find(|x| x.name() == delivery.hook()) {
+            Some(x) => x,
+            None => {
+                let msg = format!("no hook named {} is registered", delivery.hook());
+                return Err(msg.into());
+            }
+        };
+
+        let db = PubDb::from(db);
+        let payload = delivery.payload().clone();
+
+        let ran = match delivery.event() {
+            HookEvent::Leased => parse::<LeasePayload>(payload)?.with_data(|x| hook.leased(&db, x)),
+            HookEvent::Returned => {
+                parse::<LeasePayload>(payload)?.with_data(|x| hook.returned(&db, x))
+            }
+            HookEvent::Evicted => {
+                parse::<LeasePayload>(payload)?.with_data(|x| hook.evicted(&db, x))
+            }
+            HookEvent::Warned => parse::<LeasePayload>(payload)?.with_data(|x| hook.warned(&db, x)),
+            HookEvent::Overdue => {
+                parse::<LeasePayload>(payload)?.with_data(|x| hook.overdue(&db, x))
+            }
+            HookEvent::Extended => {
+                parse::<LeasePayload>(payload)?.with_data(|x| hook.extended(&db, x))
+            }
+            HookEvent::Revoked => {
+                parse::<LeasePayload>(payload)?.with_data(|x| hook.revoked(&db, x))
+            }
+            HookEvent::HandedOff => {
+                parse::<LeasePayload>(payload)?.with_data(|x| hook.handed_off(&db, x))
+            }
+            HookEvent::Transferred => {
+                let p = parse::<TransferPayload>(payload)?;
+                p.data
+                    .with_data(|x| hook.transferred(&db, x, &p.from, &p.to))
+            }
+            HookEvent::Requested => {
+                parse::<RequestPayload>(payload)?.with_data(|x| hook.requested(&db, x))
+            }
+            HookEvent::Approved => {
+                parse::<RequestPayload>(payload)?.with_data(|x| hook.approved(&db, x))
+            }
+            HookEvent::Denied => {
+                parse::<RequestPayload>(payload)?.with_data(|x| hook.denied(&db, x))
+            }
+            HookEvent::EnteredMaintenance => {
+                let p = parse::<MaintenancePayload>(payload)?;
+                hook.entered_maintenance(&db, &p.asset, &p.asset_type)
+            }
+            HookEvent::LeftMaintenance => {
+                let p = parse::<MaintenancePayload>(payload)?;
+                hook.left_maintenance(&db, &p.asset, &p.asset_type)
+            }
+        };
+
+        ran.chain_err(|| "error running hook")
+    }
+
+    pub fn before_lease(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        owned(db, data, |data| {
+            for hook in self.all() {
+                hook.before_lease(&PubDb::from(db), data.clone())
+                    .map_err(veto)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn before_return(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        owned(db, data, |data| {
+            for hook in self.all() {
+                hook.before_return(&PubDb::from(db), data.clone())
+                    .map_err(veto)?;
+            }
+
+            Ok(())
+        })
+    }
+
     pub fn returned(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
         use crate::errors::*;
 
-        for hook in self.0.iter() {
-            hook.returned(&PubDb::from(db), data.clone())
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Returned, || LeasePayload::value(&data))?;
+
+            for hook in self.immediate() {
+                hook.returned(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook")?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn leased(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Leased, || LeasePayload::value(&data))?;
+
+            for hook in self.immediate() {
+                hook.leased(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook")?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// The lease is already gone, so every hook is called even if an earlier
+    /// one fails. Only the first error is returned.
+    pub fn evicted(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Evicted, || LeasePayload::value(&data))?;
+
+            let mut result = Ok(());
+
+            for hook in self.immediate() {
+                let ran = hook
+                    .evicted(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook");
+
+                result = result.and(ran);
+            }
+
+            result
+        })
+    }
+
+    /// Every hook is called even if an earlier one fails, since the warning
+    /// isn't sent again. Only the first error is returned.
+    pub fn warned(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Warned, || LeasePayload::value(&data))?;
+
+            let mut result = Ok(());
+
+            for hook in self.immediate() {
+                let ran = hook
+                    .warned(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook");
+
+                result = result.and(ran);
+            }
+
+            result
+        })
+    }
+
+    /// Every hook is called even if an earlier one fails, since the lease
+    /// isn't marked as overdue again. Only the first error is returned.
+    pub fn overdue(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Overdue, || LeasePayload::value(&data))?;
+
+            let mut result = Ok(());
+
+            for hook in self.immediate() {
+                let ran = hook
+                    .overdue(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook");
+
+                result = result.and(ran);
+            }
+
+            result
+        })
+    }
+
+    pub fn extended(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Extended, || LeasePayload::value(&data))?;
+
+            for hook in self.immediate() {
+                hook.extended(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook")?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn revoked(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Revoked, || LeasePayload::value(&data))?;
+
+            for hook in self.immediate() {
+                hook.revoked(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook")?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn handed_off(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::HandedOff, || LeasePayload::value(&data))?;
+
+            for hook in self.immediate() {
+                hook.handed_off(&PubDb::from(db), data.clone())
+                    .chain_err(|| "error running hook")?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn transferred(
+        &self,
+        db: &PgConnection,
+        data: Data,
+        from: &User,
+        to: &User,
+    ) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        owned(db, data, |data| {
+            self.enqueue(db, HookEvent::Transferred, || {
+                let mut payload = LeasePayload::value(&data);
+                payload["from"] = json!(from);
+                payload["to"] = json!(to);
+                payload
+            })?;
+
+            for hook in self.immediate() {
+                hook.transferred(&PubDb::from(db), data.clone(), from, to)
+                    .chain_err(|| "error running hook")?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn requested(&self, db: &PgConnection, data: RequestData) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        self.enqueue(db, HookEvent::Requested, || RequestPayload::value(&data))?;
+
+        for hook in self.immediate() {
+            hook.requested(&PubDb::from(db), data.clone())
                 .chain_err(|| "error running hook")?;
         }
 
         Ok(())
     }
 
-    pub fn leased(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+    pub fn approved(&self, db: &PgConnection, data: RequestData) -> crate::errors::Result<()> {
         use crate::errors::*;
 
-        for hook in self.0.iter() {
-            hook.leased(&PubDb::from(db), data.clone())
+        self.enqueue(db, HookEvent::Approved, || RequestPayload::value(&data))?;
+
+        for hook in self.immediate() {
+            hook.approved(&PubDb::from(db), data.clone())
                 .chain_err(|| "error running hook")?;
         }
 
         Ok(())
     }
 
-    pub fn evicted(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+    pub fn denied(&self, db: &PgConnection, data: RequestData) -> crate::errors::Result<()> {
         use crate::errors::*;
 
-        for hook in self.0.iter() {
-            hook.evicted(&PubDb::from(db), data.clone())
+        self.enqueue(db, HookEvent::Denied, || RequestPayload::value(&data))?;
+
+        for hook in self.immediate() {
+            hook.denied(&PubDb::from(db), data.clone())
                 .chain_err(|| "error running hook")?;
         }
 
         Ok(())
     }
 
-    pub fn warned(&self, db: &PgConnection, data: Data) -> crate::errors::Result<()> {
+    pub fn entered_maintenance(
+        &self,
+        db: &PgConnection,
+        asset: &Asset,
+        asset_type: &AssetType,
+    ) -> crate::errors::Result<()> {
         use crate::errors::*;
 
-        for hook in self.0.iter() {
-            hook.warned(&PubDb::from(db), data.clone())
+        let payload = || json!({ "asset": asset, "asset_type": asset_type });
+        self.enqueue(db, HookEvent::EnteredMaintenance, payload)?;
+
+        for hook in self.immediate() {
+            hook.entered_maintenance(&PubDb::from(db), asset, asset_type)
                 .chain_err(|| "error running hook")?;
         }
 
         Ok(())
     }
 
+    pub fn left_maintenance(
+        &self,
+        db: &PgConnection,
+        asset: &Asset,
+        asset_type: &AssetType,
+    ) -> crate::errors::Result<()> {
+        use crate::errors::*;
+
+        let payload = || json!({ "asset": asset, "asset_type": asset_type });
+        self.enqueue(db, HookEvent::LeftMaintenance, payload)?;
+
+        for hook in self.immediate() {
+            hook.left_maintenance(&PubDb::from(db), asset, asset_type)
+                .chain_err(|| "error running hook")?;
+        }
+
+        Ok(())
+    }
+
+    /// Add `hook`, unless its name is blank or already taken, since events in
+    /// the outbox are matched to hooks by name.
     pub fn try_push(&mut self, hook: Box<dyn Hook + Sync + Send>) -> crate::errors::Result<()> {
         use crate::errors::*;
 
-        Arc::get_mut(&mut self.0)
-            .chain_err(|| "failed to push hook")?
-            .push(hook);
+        let name = hook.name();
+
+        if name.trim().is_empty() {
+            return Err(format!("hook {:?} has a blank name", hook).into());
+        }
+
+        let hooks = Arc::get_mut(&mut self.0).chain_err(|| "failed to push hook")?;
+
+        if hooks.iter().any(|x| x.name() == name) {
+            return Err(format!("a hook named {} is already registered", name).into());
+        }
+
+        hooks.push(hook);
         Ok(())
     }
 }
+
+/// Call `f` with `data`, after looking up who shares its lease, unless that
+/// was already done.
+fn owned<T, F>(db: &PgConnection, data: Data, f: F) -> crate::errors::Result<T>
+where
+    F: FnOnce(Data) -> crate::errors::Result<T>,
+{
+    if data.has_owners() {
+        return f(data);
+    }
+
+    let owners = data.lease().owners(&PubDb::from(db))?;
+    f(data.with_owners(&owners))
+}
+
+fn parse<T: DeserializeOwned>(payload: Value) -> crate::errors::Result<T> {
+    use crate::errors::*;
+
+    serde_json::from_value(payload).chain_err(|| "unable to read hook payload")
+}
+
+/// A copy of `Data` for the outbox.
+#[derive(Debug, Deserialize)]
+struct LeasePayload {
+    lease: Lease,
+    asset: Asset,
+    asset_type: AssetType,
+    fields: Vec<(String, String)>,
+    owners: Vec<User>,
+    ended: Option<EndedLease>,
+    warning: Option<WarningPayload>,
+    evict_at: Option<DateTime<Utc>>,
+}
+
+impl LeasePayload {
+    fn value(data: &Data) -> Value {
+        json!({
+            "lease": data.lease(),
+            "asset": data.asset(),
+            "asset_type": data.asset_type(),
+            "fields": data.fields(),
+            "owners": data.owners(),
+            "ended": data.ended(),
+            "warning": data.warning().map(WarningPayload::new),
+            "evict_at": data.evict_at(),
+        })
+    }
+
+    fn with_data<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(Data) -> T,
+    {
+        let warning = self.warning.as_ref().map(WarningPayload::to_warning);
+
+        let mut data = Data::new(&self.lease, &self.asset, &self.asset_type)
+            .with_fields(&self.fields)
+            .with_owners(&self.owners);
+
+        if let Some(ref x) = self.ended {
+            data = data.with_ended(x);
+        }
+
+        if let Some(ref x) = warning {
+            data = data.with_warning(x);
+        }
+
+        if let Some(x) = self.evict_at {
+            data = data.with_evict_at(x);
+        }
+
+        f(data)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WarningPayload {
+    stage: usize,
+    stages: usize,
+    before_secs: i64,
+    remaining_secs: i64,
+}
+
+impl WarningPayload {
+    fn new(warning: &Warning) -> Self {
+        WarningPayload {
+            stage: warning.stage(),
+            stages: warning.stages(),
+            before_secs: warning.before().num_seconds(),
+            remaining_secs: warning.remaining().num_seconds(),
+        }
+    }
+
+    fn to_warning(&self) -> Warning {
+        Warning::new(
+            self.stage,
+            self.stages,
+            Duration::seconds(self.before_secs),
+            Duration::seconds(self.remaining_secs),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferPayload {
+    #[serde(flatten)]
+    data: LeasePayload,
+    from: User,
+    to: User,
+}
+
+/// A copy of `RequestData` for the outbox.
+#[derive(Debug, Deserialize)]
+struct RequestPayload {
+    request: LeaseRequest,
+    requester: User,
+    asset: Asset,
+    asset_type: AssetType,
+    approvers: Vec<User>,
+}
+
+impl RequestPayload {
+    fn value(data: &RequestData) -> Value {
+        json!({
+            "request": data.request(),
+            "requester": data.requester(),
+            "asset": data.asset(),
+            "asset_type": data.asset_type(),
+            "approvers": data.approvers(),
+        })
+    }
+
+    fn with_data<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(RequestData) -> T,
+    {
+        let data = RequestData::new(
+            &self.request,
+            &self.requester,
+            &self.asset,
+            &self.asset_type,
+            &self.approvers,
+        );
+
+        f(data)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MaintenancePayload {
+    asset: Asset,
+    asset_type: AssetType,
+}
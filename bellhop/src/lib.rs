@@ -23,15 +23,19 @@ extern crate serde_derive;
 #[macro_use]
 extern crate typed_builder;
 
+mod approvals;
 pub mod auth;
 pub mod db;
 pub mod errors;
 pub mod hooks;
 mod internal;
 pub mod models;
+mod outbox;
+mod prober;
 mod schema;
 mod sheriff;
 mod views;
+mod waitlist;
 
 use crate::auth::Auth;
 use crate::hooks::Hook;
@@ -74,6 +78,8 @@ impl Bellhop {
     ///
     /// `Hook` plugins provide additional functionality when the status of an
     /// [`models::asset::Asset`] changes.
+    ///
+    /// Panics if another hook has the same [`Hook::name`].
     pub fn hook<H>(mut self, hook: H) -> Self
     where
         H: 'static + Send + Sync + Hook,
@@ -112,6 +118,34 @@ impl Bellhop {
                     views::api::v0::types::delete,
                     views::api::v0::types::create_tag_type,
                     views::api::v0::types::delete_tag_type,
+                    views::api::v0::types::lease_fields,
+                    views::api::v0::types::create_lease_field,
+                    views::api::v0::types::delete_lease_field,
+                    views::api::v0::types::waitlist,
+                    views::api::v0::types::waitlist_detail,
+                    views::api::v0::types::join_waitlist,
+                    views::api::v0::types::leave_waitlist,
+                    views::api::v0::types::claim_lease,
+                    views::api::v0::types::quotas,
+                    views::api::v0::types::set_default_quota,
+                    views::api::v0::types::delete_default_quota,
+                    views::api::v0::types::set_user_quota,
+                    views::api::v0::types::delete_user_quota,
+                    views::api::v0::types::probes,
+                    views::api::v0::types::create_probe,
+                    views::api::v0::types::delete_probe,
+                    views::api::v0::types::health,
+                    views::api::v0::types::recurring,
+                    views::api::v0::types::recurring_detail,
+                    views::api::v0::types::create_recurring,
+                    views::api::v0::types::update_recurring,
+                    views::api::v0::types::delete_recurring,
+                    views::api::v0::types::set_approval,
+                    views::api::v0::types::set_warnings,
+                    views::api::v0::types::set_grace,
+                    views::api::v0::types::approvers,
+                    views::api::v0::types::add_approver,
+                    views::api::v0::types::delete_approver,
                 ],
             )
             .mount(
@@ -121,6 +155,14 @@ impl Bellhop {
                     views::api::v0::assets::delete,
                     views::api::v0::assets::create_lease,
                     views::api::v0::assets::delete_lease,
+                    views::api::v0::assets::extend_lease,
+                    views::api::v0::assets::heartbeat,
+                    views::api::v0::assets::revoke_lease,
+                    views::api::v0::assets::set_status,
+                    views::api::v0::assets::transfer_lease,
+                    views::api::v0::assets::pending_transfer,
+                    views::api::v0::assets::accept_transfer,
+                    views::api::v0::assets::delete_transfer,
                     views::api::v0::assets::list,
                     views::api::v0::assets::detail,
                     views::api::v0::assets::tags,
@@ -128,20 +170,111 @@ impl Bellhop {
                     views::api::v0::assets::create_tag,
                     views::api::v0::assets::delete_tag,
                     views::api::v0::assets::lease,
+                    views::api::v0::assets::reservations,
+                    views::api::v0::assets::leases,
+                    views::api::v0::assets::delete_reservation,
+                    views::api::v0::assets::health,
+                ],
+            )
+            .mount(
+                "/api/v0/leases/",
+                routes![
+                    views::api::v0::leases::create,
+                    views::api::v0::leases::detail,
+                    views::api::v0::leases::delete,
+                    views::api::v0::leases::co_owners,
+                    views::api::v0::leases::add_co_owner,
+                    views::api::v0::leases::remove_co_owner,
+                ],
+            )
+            .mount(
+                "/api/v0/requests/",
+                routes![
+                    views::api::v0::requests::list,
+                    views::api::v0::requests::detail,
+                    views::api::v0::requests::approve,
+                    views::api::v0::requests::deny,
+                    views::api::v0::requests::delete,
+                ],
+            )
+            .mount("/api/v0/users/", routes![views::api::v0::users::leases])
+            .mount(
+                "/api/v0/sheriff/",
+                routes![
+                    views::api::v0::sheriff::status,
+                    views::api::v0::sheriff::run,
+                    views::api::v0::sheriff::failures,
+                ],
+            )
+            .mount(
+                "/api/v0/hooks/",
+                routes![
+                    views::api::v0::hooks::deliveries,
+                    views::api::v0::hooks::delivery,
+                    views::api::v0::hooks::replay,
+                    views::api::v0::hooks::replay_dead,
+                ],
+            )
+            .mount(
+                "/api/v0/feed-tokens/",
+                routes![
+                    views::api::v0::feed_tokens::list,
+                    views::api::v0::feed_tokens::detail,
+                    views::api::v0::feed_tokens::create,
+                    views::api::v0::feed_tokens::delete,
+                ],
+            )
+            .mount(
+                "/ical",
+                routes![
+                    views::ical::asset,
+                    views::ical::asset_type,
+                    views::ical::user
                 ],
             )
             .mount("/", routes![views::types::have_access])
             .mount("/", routes![views::favicon::favicon])
             .mount(
                 "/types",
-                routes![views::types::request_access, views::types::detail],
+                routes![
+                    views::types::request_access,
+                    views::types::detail,
+                    views::types::join_waitlist,
+                    views::types::leave_waitlist,
+                    views::types::delete_recurring
+                ],
+            )
+            .mount(
+                "/users",
+                routes![
+                    views::user::detail,
+                    views::user::create_feed_token,
+                    views::user::delete_feed_token
+                ],
+            )
+            .mount(
+                "/approvals",
+                routes![
+                    views::approvals::list,
+                    views::approvals::approve,
+                    views::approvals::deny,
+                    views::approvals::delete
+                ],
             )
-            .mount("/users", routes![views::user::detail])
             .mount(
                 "/assets",
                 routes![
                     views::assets::create_lease,
                     views::assets::delete_lease,
+                    views::assets::extend_lease,
+                    views::assets::revoke_lease,
+                    views::assets::set_status,
+                    views::assets::transfer_lease,
+                    views::assets::accept_transfer,
+                    views::assets::delete_transfer,
+                    views::assets::add_co_owner,
+                    views::assets::remove_co_owner,
+                    views::assets::delete_reservation,
                     views::assets::detail
                 ],
             )
@@ -165,9 +298,7 @@ impl Bellhop {
             .attach(Template::fairing())
             .attach(internal::db::Db::fairing());
 
-        for hook in self.hooks.0.iter() {
-            r = hook.prelaunch(r);
-        }
+        r = self.hooks.prelaunch(r);
 
         for auth in self.auths.0.iter() {
             r = auth.prelaunch(r);
@@ -176,6 +307,8 @@ impl Bellhop {
         r.manage(self.hooks)
             .manage(self.auths)
             .attach(sheriff::Sheriff::fairing())
+            .attach(prober::Prober::fairing())
+            .attach(outbox::Outbox::fairing())
             .launch();
     }
 }
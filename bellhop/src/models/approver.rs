@@ -0,0 +1,93 @@
+//! An `Approver` decides on lease requests for an `AssetType` that requires
+//! approval.
+
+use crate::errors::*;
+use crate::schema::approvers;
+
+use super::asset_type::AssetType;
+use super::user::User;
+
+use diesel::prelude::*;
+
+/// A `User` who can approve or deny lease requests for an `AssetType`.
+#[derive(Debug, Associations, Insertable, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(asset_type_id, user_id)]
+#[belongs_to(AssetType)]
+#[belongs_to(User)]
+pub struct Approver {
+    asset_type_id: i32,
+    user_id: i32,
+}
+
+impl Approver {
+    pub fn new(asset_type_id: i32, user_id: i32) -> Self {
+        Approver {
+            asset_type_id,
+            user_id,
+        }
+    }
+
+    /// The approvers for the `AssetType` identified by `type_id`.
+    pub fn for_type(c: &PgConnection, type_id: i32) -> Result<Vec<User>> {
+        use self::approvers::dsl as ap;
+        use crate::schema::users::dsl as u;
+
+        ap::approvers
+            .inner_join(u::users)
+            .filter(ap::asset_type_id.eq(type_id))
+            .order(u::email.asc())
+            .select(crate::schema::users::all_columns)
+            .load(c)
+            .chain_err(|| "unable to get approvers for asset type")
+    }
+
+    /// The primary keys of the asset types `user_id` can approve requests for.
+    pub fn type_ids_for(c: &PgConnection, by_user_id: i32) -> Result<Vec<i32>> {
+        use self::approvers::dsl::*;
+
+        approvers
+            .filter(user_id.eq(by_user_id))
+            .select(asset_type_id)
+            .load(c)
+            .chain_err(|| "unable to get asset types for approver")
+    }
+
+    /// Returns `true` if `user_id` can approve requests for `type_id`.
+    pub fn exists(c: &PgConnection, type_id: i32, user_id: i32) -> Result<bool> {
+        use self::approvers::dsl as ap;
+
+        let found = ap::approvers
+            .find((type_id, user_id))
+            .get_result::<Approver>(c)
+            .optional()
+            .chain_err(|| "unable to check approver")?;
+
+        Ok(found.is_some())
+    }
+
+    /// Insert the `Approver` into the database.
+    ///
+    /// Returns `false` if the user was already an approver.
+    pub fn insert(&self, c: &PgConnection) -> Result<bool> {
+        use self::approvers::dsl::*;
+
+        let count = diesel::insert_into(approvers)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(c)
+            .chain_err(|| "unable to insert approver")?;
+
+        Ok(count == 1)
+    }
+
+    /// Delete the `Approver` from the database.
+    ///
+    /// Returns `false` if the user wasn't an approver.
+    pub fn delete(&self, c: &PgConnection) -> Result<bool> {
+        let count = diesel::delete(self)
+            .execute(c)
+            .chain_err(|| "unable to delete approver")?;
+
+        Ok(count == 1)
+    }
+}
@@ -11,13 +11,79 @@ use super::asset_type::AssetType;
 use super::lease::Lease;
 use super::user::User;
 
+use chrono::prelude::*;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+
+use rocket::http::RawStr;
+use rocket::request::{FormItems, FromForm, FromFormValue};
+
+use std::io::Write;
+use std::result::Result as StdResult;
+
+/// Whether an `Asset` can be leased.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "SmallInt"]
+#[repr(i16)]
+pub enum AssetStatus {
+    /// The asset is in service, and can be leased whenever it's free.
+    Available = 0,
+
+    /// The asset is out of service for now, but is expected to come back.
+    Maintenance = 1,
+
+    /// The asset is out of service for good.
+    Retired = 2,
+
+    /// The asset is resting after the sheriff evicted its lease, and comes
+    /// back into service on its own.
+    CoolingDown = 3,
+}
+
+impl ToSql<SmallInt, Pg> for AssetStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<SmallInt, Pg>::to_sql(&(*self as i16), out)
+    }
+}
+
+impl FromSql<SmallInt, Pg> for AssetStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(AssetStatus::Available),
+            1 => Ok(AssetStatus::Maintenance),
+            2 => Ok(AssetStatus::Retired),
+            3 => Ok(AssetStatus::CoolingDown),
+            x => Err(format!("unknown asset status: {}", x).into()),
+        }
+    }
+}
+
+impl<'v> FromFormValue<'v> for AssetStatus {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> StdResult<Self, &'v RawStr> {
+        match form_value.as_str() {
+            "available" => Ok(AssetStatus::Available),
+            "maintenance" => Ok(AssetStatus::Maintenance),
+            "retired" => Ok(AssetStatus::Retired),
+            "cooling_down" => Ok(AssetStatus::CoolingDown),
+            _ => Err(form_value),
+        }
+    }
+}
 
 /// An `Asset` is a resource that can be loaned and returned.
 ///
 /// `Asset`s are the _raison d'être_ for Bellhop. This struct represents the
 /// things people want to share and borrow.
-#[derive(Debug, Clone, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[derive(
+    Debug, Clone, Associations, Serialize, Deserialize, Queryable, Identifiable, PartialEq, Eq,
+)]
 #[belongs_to(AssetType, foreign_key = "type_id")]
 #[belongs_to(Lease)]
 pub struct Asset {
@@ -26,6 +92,10 @@ pub struct Asset {
     lease_id: Option<i32>,
 
     name: String,
+
+    status: AssetStatus,
+    status_reason: Option<String>,
+    expected_back: Option<DateTime<Utc>>,
 }
 
 impl Asset {
@@ -41,6 +111,19 @@ impl Asset {
         Ok(asset.pop())
     }
 
+    /// Like `by_id`, but locks the asset until the end of the transaction, so
+    /// its status can't change underneath the caller.
+    pub(crate) fn lock(c: &PgConnection, by_id: i32) -> Result<Option<Asset>> {
+        use self::assets::dsl::*;
+
+        assets
+            .for_update()
+            .filter(id.eq(by_id))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to lock asset")
+    }
+
     pub(crate) fn fetch_lease_owner(&self, c: &PgConnection) -> Result<Option<(Lease, User)>> {
         use crate::schema::leases::dsl::*;
         use crate::schema::users::dsl as u;
@@ -77,6 +160,129 @@ impl Asset {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Whether this `Asset` is in service.
+    pub fn status(&self) -> AssetStatus {
+        self.status
+    }
+
+    /// Why this `Asset` is out of service, if anyone said.
+    pub fn status_reason(&self) -> Option<&str> {
+        self.status_reason.as_ref().map(String::as_str)
+    }
+
+    /// When this `Asset` is expected to be back in service, if anyone knows.
+    pub fn expected_back(&self) -> Option<DateTime<Utc>> {
+        self.expected_back
+    }
+
+    /// Returns `true` if this `Asset` is in service.
+    pub fn is_available(&self) -> bool {
+        self.status == AssetStatus::Available
+    }
+
+    /// Change the status of this `Asset`, and return the updated asset.
+    pub(crate) fn set_status(&self, c: &PgConnection, form: &SetAssetStatusForm) -> Result<Asset> {
+        use self::assets::dsl::*;
+
+        // Available assets have nothing to explain.
+        let (reason, back) = match form.status {
+            AssetStatus::Available => (None, None),
+            _ => (form.reason(), form.expected_back),
+        };
+
+        diesel::update(self)
+            .set((
+                status.eq(form.status),
+                status_reason.eq(reason),
+                expected_back.eq(back),
+            ))
+            .get_result(c)
+            .chain_err(|| "unable to set asset status")
+    }
+}
+
+/// Request to take an `Asset` out of service, or put it back.
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetAssetStatusForm {
+    status: AssetStatus,
+
+    #[serde(default)]
+    reason: Option<String>,
+
+    #[serde(default)]
+    expected_back: Option<DateTime<Utc>>,
+}
+
+impl SetAssetStatusForm {
+    /// Move an asset into maintenance, with no idea when it'll be back.
+    pub fn maintenance(reason: String) -> Self {
+        SetAssetStatusForm {
+            status: AssetStatus::Maintenance,
+            reason: Some(reason),
+            expected_back: None,
+        }
+    }
+
+    pub fn status(&self) -> AssetStatus {
+        self.status
+    }
+
+    /// Rest an asset until `until`, after its lease was evicted.
+    pub fn cool_down(until: DateTime<Utc>) -> Self {
+        SetAssetStatusForm {
+            status: AssetStatus::CoolingDown,
+            reason: Some("Cooling down after its lease was evicted.".to_owned()),
+            expected_back: Some(until),
+        }
+    }
+
+    /// The reason, trimmed, or `None` if it's blank.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason
+            .as_ref()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+    }
+}
+
+/// HTML forms leave `expected_back` blank rather than leaving it out.
+impl<'f> FromForm<'f> for SetAssetStatusForm {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, strict: bool) -> StdResult<Self, ()> {
+        let mut status = None;
+        let mut reason = None;
+        let mut expected_back = None;
+
+        for item in items {
+            let key = String::from_form_value(item.key).map_err(|_| ())?;
+
+            match key.as_str() {
+                "status" => {
+                    status = Some(AssetStatus::from_form_value(item.value).map_err(|_| ())?)
+                }
+                "reason" => reason = Some(String::from_form_value(item.value).map_err(|_| ())?),
+                "expected_back" => {
+                    // The field is optional, but a time that doesn't parse is
+                    // a mistake, not a reason to forget it.
+                    let value = String::from_form_value(item.value).map_err(|_| ())?;
+                    if !value.trim().is_empty() {
+                        expected_back = Some(value.trim().parse().map_err(|_| ())?);
+                    }
+                }
+                "_method" => (),
+                _ if strict => return Err(()),
+                _ => (),
+            }
+        }
+
+        Ok(SetAssetStatusForm {
+            status: status.ok_or(())?,
+            reason,
+            expected_back,
+        })
+    }
 }
 
 /// The insertable companion of `Asset`.
@@ -6,12 +6,28 @@ use crate::schema::asset_types;
 
 use diesel::prelude::*;
 
+use rocket::request::{FormItems, FromForm, FromFormValue};
+
+use std::result::Result as StdResult;
+use std::str::FromStr;
+
 /// An `AssetType` is the family an `Asset` belongs to.
-#[derive(Debug, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, PartialEq, Eq)]
 pub struct AssetType {
     id: i32,
     name: String,
     plural_name: String,
+
+    max_lease_seconds: Option<i32>,
+    max_extensions: Option<i32>,
+
+    requires_approval: bool,
+
+    warn_before_secs: Option<Vec<i32>>,
+    warn_percent: Option<i32>,
+
+    grace_secs: i32,
+    cooldown_secs: i32,
 }
 
 impl AssetType {
@@ -41,6 +57,145 @@ impl AssetType {
     pub fn plural_name(&self) -> &str {
         &self.plural_name
     }
+
+    /// The longest a `Lease` on an `Asset` of this type may be extended to,
+    /// measured from when it started.
+    pub fn max_lease_seconds(&self) -> Option<i32> {
+        self.max_lease_seconds
+    }
+
+    /// How many times a `Lease` on an `Asset` of this type may be extended.
+    pub fn max_extensions(&self) -> Option<i32> {
+        self.max_extensions
+    }
+
+    /// Whether a `Lease` on an `Asset` of this type has to be approved by
+    /// one of the type's approvers before it's created.
+    pub fn requires_approval(&self) -> bool {
+        self.requires_approval
+    }
+
+    /// How long before a `Lease` on an `Asset` of this type ends to warn its
+    /// owners, in seconds, once for each entry, overriding the sheriff's
+    /// configuration.
+    pub fn warn_before_secs(&self) -> Option<&[i32]> {
+        self.warn_before_secs.as_ref().map(Vec::as_slice)
+    }
+
+    /// How much of a `Lease` on an `Asset` of this type has to be left, as a
+    /// percentage of its length, before its owners are warned, overriding
+    /// the sheriff's configuration.
+    pub fn warn_percent(&self) -> Option<i32> {
+        self.warn_percent
+    }
+
+    /// How long after a `Lease` on an `Asset` of this type ends it's left
+    /// alone, marked as overdue, before the sheriff evicts it, in seconds.
+    pub fn grace_secs(&self) -> i32 {
+        self.grace_secs
+    }
+
+    /// How long an `Asset` of this type rests after the sheriff evicts its
+    /// `Lease`, before it can be leased again, in seconds.
+    pub fn cooldown_secs(&self) -> i32 {
+        self.cooldown_secs
+    }
+
+    /// Replace the grace and cool-down periods of this `AssetType`, and
+    /// return the updated asset type.
+    pub(crate) fn set_grace(&self, c: &PgConnection, form: &SetGraceForm) -> Result<AssetType> {
+        use self::asset_types::dsl::*;
+
+        diesel::update(self)
+            .set((
+                grace_secs.eq(form.grace_secs),
+                cooldown_secs.eq(form.cooldown_secs),
+            ))
+            .get_result(c)
+            .chain_err(|| "unable to update asset type")
+    }
+
+    /// Replace the warning policy overrides of this `AssetType`, and return
+    /// the updated asset type.
+    pub(crate) fn set_warnings(
+        &self,
+        c: &PgConnection,
+        form: &SetWarningsForm,
+    ) -> Result<AssetType> {
+        use self::asset_types::dsl::*;
+
+        diesel::update(self)
+            .set((
+                warn_before_secs.eq(&form.warn_before_secs),
+                warn_percent.eq(form.warn_percent),
+            ))
+            .get_result(c)
+            .chain_err(|| "unable to update asset type")
+    }
+
+    /// Turn approval on or off for this `AssetType`, and return the updated
+    /// asset type. Pending requests stay pending either way.
+    pub(crate) fn set_approval(
+        &self,
+        c: &PgConnection,
+        form: &SetApprovalForm,
+    ) -> Result<AssetType> {
+        use self::asset_types::dsl::*;
+
+        diesel::update(self)
+            .set(requires_approval.eq(form.requires_approval))
+            .get_result(c)
+            .chain_err(|| "unable to update asset type")
+    }
+}
+
+/// Request to turn approval on or off for an `AssetType`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetApprovalForm {
+    requires_approval: bool,
+}
+
+/// Request to override the sheriff's warning policy for an `AssetType`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetWarningsForm {
+    #[serde(default)]
+    warn_before_secs: Option<Vec<i32>>,
+
+    #[serde(default)]
+    warn_percent: Option<i32>,
+}
+
+impl SetWarningsForm {
+    /// Returns `false` if a lead time is negative, or the percentage isn't
+    /// between 0 and 100.
+    pub fn is_valid(&self) -> bool {
+        let before_secs = self.warn_before_secs.as_ref().map(Vec::as_slice);
+        is_valid_warning(before_secs, self.warn_percent)
+    }
+}
+
+/// Request to change how long an expired `Lease` on an `AssetType` is left
+/// alone, and how long its assets rest afterwards.
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetGraceForm {
+    #[serde(default)]
+    grace_secs: i32,
+
+    #[serde(default)]
+    cooldown_secs: i32,
+}
+
+impl SetGraceForm {
+    /// Returns `false` if either period is negative.
+    pub fn is_valid(&self) -> bool {
+        self.grace_secs >= 0 && self.cooldown_secs >= 0
+    }
+}
+
+fn is_valid_warning(before_secs: Option<&[i32]>, percent: Option<i32>) -> bool {
+    let before_valid = before_secs.map_or(true, |x| x.iter().all(|y| *y >= 0));
+
+    before_valid && percent.map_or(true, |x| (0..=100).contains(&x))
 }
 
 /// The insertable companion of `AssetType`.
@@ -62,11 +217,39 @@ impl AssetType {
 ///         .unwrap();
 /// }
 /// ```
-#[derive(Debug, Deserialize, Insertable, TypedBuilder, FromForm)]
+#[derive(Debug, Deserialize, Insertable, TypedBuilder)]
 #[table_name = "asset_types"]
 pub struct CreateAssetType {
     name: String,
     plural_name: String,
+
+    #[serde(default)]
+    #[builder(default)]
+    max_lease_seconds: Option<i32>,
+
+    #[serde(default)]
+    #[builder(default)]
+    max_extensions: Option<i32>,
+
+    #[serde(default)]
+    #[builder(default)]
+    requires_approval: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    warn_before_secs: Option<Vec<i32>>,
+
+    #[serde(default)]
+    #[builder(default)]
+    warn_percent: Option<i32>,
+
+    #[serde(default)]
+    #[builder(default)]
+    grace_secs: i32,
+
+    #[serde(default)]
+    #[builder(default)]
+    cooldown_secs: i32,
 }
 
 impl CreateAssetType {
@@ -75,6 +258,17 @@ impl CreateAssetType {
         &self.name
     }
 
+    /// Returns `false` if a warning lead time, the grace period, or the
+    /// cool-down period is negative, or the warning percentage isn't between
+    /// 0 and 100.
+    pub fn is_valid(&self) -> bool {
+        let before_secs = self.warn_before_secs.as_ref().map(Vec::as_slice);
+
+        is_valid_warning(before_secs, self.warn_percent)
+            && self.grace_secs >= 0
+            && self.cooldown_secs >= 0
+    }
+
     /// Insert the `AssetType` into the database and return it.
     ///
     /// See the struct documentation for an example.
@@ -87,3 +281,66 @@ impl CreateAssetType {
             .chain_err(|| "unable to insert asset type")
     }
 }
+
+impl<'f> FromForm<'f> for CreateAssetType {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, strict: bool) -> StdResult<Self, ()> {
+        let mut name = None;
+        let mut plural_name = None;
+        let mut max_lease_seconds = None;
+        let mut max_extensions = None;
+        let mut requires_approval = false;
+        let mut warn_before_secs: Option<Vec<i32>> = None;
+        let mut warn_percent = None;
+        let mut grace_secs = 0;
+        let mut cooldown_secs = 0;
+
+        for item in items {
+            let key = String::from_form_value(item.key).map_err(|_| ())?;
+            let value = String::from_form_value(item.value).map_err(|_| ())?;
+
+            match key.as_str() {
+                "name" => name = Some(value),
+                "plural_name" => plural_name = Some(value),
+                "max_lease_seconds" => max_lease_seconds = optional(&value)?,
+                "max_extensions" => max_extensions = optional(&value)?,
+                "requires_approval" => {
+                    requires_approval = bool::from_form_value(item.value).map_err(|_| ())?
+                }
+                // Given once for each warning stage.
+                "warn_before_secs" => {
+                    if let Some(x) = optional(&value)? {
+                        warn_before_secs.get_or_insert_with(Vec::new).push(x);
+                    }
+                }
+                "warn_percent" => warn_percent = optional(&value)?,
+                "grace_secs" => grace_secs = optional(&value)?.unwrap_or(0),
+                "cooldown_secs" => cooldown_secs = optional(&value)?.unwrap_or(0),
+                "_method" => (),
+                _ if strict => return Err(()),
+                _ => (),
+            }
+        }
+
+        Ok(CreateAssetType {
+            name: name.ok_or(())?,
+            plural_name: plural_name.ok_or(())?,
+            max_lease_seconds,
+            max_extensions,
+            requires_approval,
+            warn_before_secs,
+            warn_percent,
+            grace_secs,
+            cooldown_secs,
+        })
+    }
+}
+
+/// Parse a form value that can be left blank, but not filled in wrong.
+fn optional<T: FromStr>(value: &str) -> StdResult<Option<T>, ()> {
+    match value.trim() {
+        "" => Ok(None),
+        x => x.parse().map(Some).map_err(|_| ()),
+    }
+}
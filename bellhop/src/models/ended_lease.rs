@@ -0,0 +1,229 @@
+//! An `EndedLease` is the archived copy of a `Lease` that is over.
+
+use crate::errors::*;
+use crate::schema::ended_leases;
+
+use super::asset::Asset;
+use super::lease::Lease;
+use super::user::User;
+
+use chrono::prelude::*;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+
+use std::io::Write;
+use std::result::Result as StdResult;
+
+/// Why a `Lease` ended.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "SmallInt"]
+#[repr(i16)]
+pub enum EndReason {
+    /// The owner released the lease.
+    Returned = 0,
+
+    /// The sheriff ended the lease because it expired.
+    Evicted = 1,
+
+    /// An administrator took the asset away.
+    Revoked = 2,
+
+    /// The lease was handed to another user. The lease itself carries on
+    /// under its new owner.
+    Transferred = 3,
+
+    /// The sheriff ended the lease because its holder stopped sending
+    /// heartbeats.
+    Abandoned = 4,
+}
+
+impl ToSql<SmallInt, Pg> for EndReason {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<SmallInt, Pg>::to_sql(&(*self as i16), out)
+    }
+}
+
+impl FromSql<SmallInt, Pg> for EndReason {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(EndReason::Returned),
+            1 => Ok(EndReason::Evicted),
+            2 => Ok(EndReason::Revoked),
+            3 => Ok(EndReason::Transferred),
+            4 => Ok(EndReason::Abandoned),
+            x => Err(format!("unknown end reason: {}", x).into()),
+        }
+    }
+}
+
+/// A `Lease` that has ended, as it was for one of the `Asset`s it covered.
+#[derive(
+    Debug, Clone, Associations, Serialize, Deserialize, Queryable, Identifiable, PartialEq, Eq,
+)]
+#[belongs_to(Asset)]
+#[belongs_to(User)]
+pub struct EndedLease {
+    id: i32,
+    lease_id: i32,
+    asset_id: i32,
+    user_id: i32,
+
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+    extensions: i32,
+
+    ended_at: DateTime<Utc>,
+    end_reason: EndReason,
+
+    revoked_by: Option<i32>,
+    revoke_reason: Option<String>,
+}
+
+impl EndedLease {
+    /// Leases that used to hold `asset_id`, and their owners, most recently
+    /// ended first.
+    pub(crate) fn for_asset(c: &PgConnection, by_asset_id: i32) -> Result<Vec<(EndedLease, User)>> {
+        use self::ended_leases::dsl::*;
+        use crate::schema::users::dsl as u;
+
+        ended_leases
+            .inner_join(u::users)
+            .filter(asset_id.eq(by_asset_id))
+            .order(ended_at.desc())
+            .load(c)
+            .chain_err(|| "unable to get lease history for asset")
+    }
+
+    /// Leases that used to belong to `user_id`, and the assets they held,
+    /// most recently ended first.
+    pub(crate) fn for_user(c: &PgConnection, by_user_id: i32) -> Result<Vec<(EndedLease, Asset)>> {
+        use self::ended_leases::dsl::*;
+        use crate::schema::assets::dsl as a;
+
+        ended_leases
+            .inner_join(a::assets)
+            .filter(user_id.eq(by_user_id))
+            .order(ended_at.desc())
+            .load(c)
+            .chain_err(|| "unable to get lease history for user")
+    }
+
+    /// Archive a copy of `lease` for each of the `Asset`s identified by
+    /// `asset_ids`, ending now.
+    pub(crate) fn archive(
+        c: &PgConnection,
+        lease: &Lease,
+        asset_ids: &[i32],
+        reason: EndReason,
+    ) -> StdResult<Vec<EndedLease>, DieselError> {
+        use self::ended_leases::dsl::*;
+
+        if asset_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let now = Utc::now();
+
+        let records: Vec<_> = asset_ids
+            .iter()
+            .map(|x| CreateEndedLease::new(lease, *x, now, reason))
+            .collect();
+
+        diesel::insert_into(ended_leases)
+            .values(&records)
+            .get_results(c)
+    }
+
+    /// The primary key of this `EndedLease`.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The primary key of the `Lease` before it ended.
+    pub fn lease_id(&self) -> i32 {
+        self.lease_id
+    }
+
+    /// The primary key of the `Asset` this lease held.
+    pub fn asset_id(&self) -> i32 {
+        self.asset_id
+    }
+
+    /// The primary key of this lease's owner.
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// When this lease came into effect.
+    pub fn start_time(&self) -> DateTime<Utc> {
+        self.start_time
+    }
+
+    /// When this lease was scheduled to end.
+    pub fn end_time(&self) -> Option<DateTime<Utc>> {
+        self.end_time
+    }
+
+    /// How many times this lease was extended.
+    pub fn extensions(&self) -> i32 {
+        self.extensions
+    }
+
+    /// When this lease actually ended.
+    pub fn ended_at(&self) -> DateTime<Utc> {
+        self.ended_at
+    }
+
+    /// Why this lease ended.
+    pub fn end_reason(&self) -> EndReason {
+        self.end_reason
+    }
+
+    /// The primary key of the `User` who revoked this lease, if it was
+    /// revoked and they still exist.
+    pub fn revoked_by(&self) -> Option<i32> {
+        self.revoked_by
+    }
+
+    /// The explanation given when this lease was revoked.
+    pub fn revoke_reason(&self) -> Option<&str> {
+        self.revoke_reason.as_ref().map(String::as_str)
+    }
+}
+
+/// The insertable companion of `EndedLease`.
+#[derive(Debug, Insertable)]
+#[table_name = "ended_leases"]
+struct CreateEndedLease {
+    lease_id: i32,
+    asset_id: i32,
+    user_id: i32,
+
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+    extensions: i32,
+
+    ended_at: DateTime<Utc>,
+    end_reason: EndReason,
+}
+
+impl CreateEndedLease {
+    fn new(lease: &Lease, asset_id: i32, ended_at: DateTime<Utc>, end_reason: EndReason) -> Self {
+        CreateEndedLease {
+            asset_id,
+            ended_at,
+            end_reason,
+            lease_id: lease.id(),
+            user_id: lease.user_id(),
+            start_time: lease.start_time(),
+            end_time: lease.end_time(),
+            extensions: lease.extensions(),
+        }
+    }
+}
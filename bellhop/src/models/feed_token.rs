@@ -0,0 +1,105 @@
+//! A `FeedToken` lets a calendar app read iCalendar feeds on behalf of a
+//! `User`, without going through authentication plugins.
+
+use crate::errors::*;
+use crate::schema::feed_tokens;
+
+use super::user::User;
+
+use chrono::prelude::*;
+
+use diesel::prelude::*;
+
+use rand::RngCore;
+
+use std::fmt::Write;
+
+/// Number of random bytes in a token. Tokens are hex encoded, so they're
+/// twice as long as this.
+const TOKEN_BYTES: usize = 32;
+
+/// A secret that grants read-only access to calendar feeds.
+#[derive(Debug, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[belongs_to(User)]
+pub struct FeedToken {
+    id: i32,
+    user_id: i32,
+
+    token: String,
+
+    created_at: DateTime<Utc>,
+}
+
+impl FeedToken {
+    pub fn by_id(c: &PgConnection, by_id: i32) -> Result<Option<FeedToken>> {
+        use self::feed_tokens::dsl::*;
+
+        feed_tokens
+            .filter(id.eq(by_id))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to find feed token by id")
+    }
+
+    /// Find the `FeedToken` with the secret `value`.
+    pub fn by_token(c: &PgConnection, value: &str) -> Result<Option<FeedToken>> {
+        use self::feed_tokens::dsl::*;
+
+        feed_tokens
+            .filter(token.eq(value))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to find feed token")
+    }
+
+    /// The tokens belonging to `user_id`, oldest first.
+    pub fn for_user(c: &PgConnection, by_user_id: i32) -> Result<Vec<FeedToken>> {
+        use self::feed_tokens::dsl::*;
+
+        feed_tokens
+            .filter(user_id.eq(by_user_id))
+            .order(created_at.asc())
+            .load(c)
+            .chain_err(|| "failed to get feed tokens for user")
+    }
+
+    /// Create a new, random token for `user_id`.
+    pub fn create(c: &PgConnection, user_id: i32) -> Result<FeedToken> {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        let mut token = String::with_capacity(TOKEN_BYTES * 2);
+        for byte in bytes.iter() {
+            write!(token, "{:02x}", byte).unwrap();
+        }
+
+        let create = CreateFeedToken {
+            user_id,
+            token: &token,
+        };
+
+        diesel::insert_into(feed_tokens::table)
+            .values(&create)
+            .get_result(c)
+            .chain_err(|| "unable to create feed token")
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "feed_tokens"]
+struct CreateFeedToken<'a> {
+    user_id: i32,
+    token: &'a str,
+}
@@ -0,0 +1,335 @@
+//! A `HookDelivery` is a hook event waiting in the outbox, or one that was
+//! already delivered or given up on.
+
+use crate::errors::*;
+use crate::schema::hook_deliveries;
+
+use chrono::prelude::*;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+
+use rocket::http::RawStr;
+use rocket::request::FromFormValue;
+
+use serde_json::Value;
+
+use std::io::Write;
+use std::result::Result as StdResult;
+
+/// Which `Hook` function an event is for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "SmallInt"]
+#[repr(i16)]
+pub enum HookEvent {
+    Leased = 0,
+    Returned = 1,
+    Evicted = 2,
+    Warned = 3,
+    Overdue = 4,
+    Extended = 5,
+    Revoked = 6,
+    HandedOff = 7,
+    Transferred = 8,
+    Requested = 9,
+    Approved = 10,
+    Denied = 11,
+    EnteredMaintenance = 12,
+    LeftMaintenance = 13,
+}
+
+impl ToSql<SmallInt, Pg> for HookEvent {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<SmallInt, Pg>::to_sql(&(*self as i16), out)
+    }
+}
+
+impl FromSql<SmallInt, Pg> for HookEvent {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(HookEvent::Leased),
+            1 => Ok(HookEvent::Returned),
+            2 => Ok(HookEvent::Evicted),
+            3 => Ok(HookEvent::Warned),
+            4 => Ok(HookEvent::Overdue),
+            5 => Ok(HookEvent::Extended),
+            6 => Ok(HookEvent::Revoked),
+            7 => Ok(HookEvent::HandedOff),
+            8 => Ok(HookEvent::Transferred),
+            9 => Ok(HookEvent::Requested),
+            10 => Ok(HookEvent::Approved),
+            11 => Ok(HookEvent::Denied),
+            12 => Ok(HookEvent::EnteredMaintenance),
+            13 => Ok(HookEvent::LeftMaintenance),
+            x => Err(format!("unknown hook event: {}", x).into()),
+        }
+    }
+}
+
+/// Where a `HookDelivery` is in the outbox.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "SmallInt"]
+#[repr(i16)]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt, or for a retry.
+    Pending = 0,
+
+    /// The hook accepted the event.
+    Delivered = 1,
+
+    /// Every attempt failed, so it won't be tried again unless an admin
+    /// replays it.
+    Dead = 2,
+}
+
+impl ToSql<SmallInt, Pg> for DeliveryStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<SmallInt, Pg>::to_sql(&(*self as i16), out)
+    }
+}
+
+impl FromSql<SmallInt, Pg> for DeliveryStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(DeliveryStatus::Pending),
+            1 => Ok(DeliveryStatus::Delivered),
+            2 => Ok(DeliveryStatus::Dead),
+            x => Err(format!("unknown delivery status: {}", x).into()),
+        }
+    }
+}
+
+impl<'v> FromFormValue<'v> for DeliveryStatus {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> StdResult<Self, &'v RawStr> {
+        match form_value.as_str() {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "dead" => Ok(DeliveryStatus::Dead),
+            _ => Err(form_value),
+        }
+    }
+}
+
+/// One event for one hook, delivered in the background.
+#[derive(Debug, Serialize, Queryable, Identifiable)]
+#[table_name = "hook_deliveries"]
+pub struct HookDelivery {
+    id: i32,
+
+    hook: String,
+    event: HookEvent,
+    payload: Value,
+
+    status: DeliveryStatus,
+
+    attempts: i32,
+    last_error: Option<String>,
+
+    created_at: DateTime<Utc>,
+    next_attempt_at: DateTime<Utc>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl HookDelivery {
+    /// Queue `event` for the hook named `hook`. Inside a transaction, the
+    /// event is only delivered if the transaction commits.
+    pub fn enqueue(c: &PgConnection, hook: &str, event: HookEvent, payload: &Value) -> Result<()> {
+        let create = CreateHookDelivery {
+            hook,
+            event,
+            payload,
+        };
+
+        diesel::insert_into(hook_deliveries::table)
+            .values(&create)
+            .execute(c)
+            .chain_err(|| "unable to insert hook delivery")?;
+
+        Ok(())
+    }
+
+    pub fn by_id(c: &PgConnection, by_id: i32) -> Result<Option<HookDelivery>> {
+        use self::hook_deliveries::dsl::*;
+
+        hook_deliveries
+            .filter(id.eq(by_id))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to find hook delivery by id")
+    }
+
+    /// The `limit` most recent deliveries, newest first, optionally only
+    /// those with `by_status`.
+    pub fn recent(
+        c: &PgConnection,
+        by_status: Option<DeliveryStatus>,
+        limit: i64,
+    ) -> Result<Vec<HookDelivery>> {
+        use self::hook_deliveries::dsl::*;
+
+        let mut query = hook_deliveries
+            .order(created_at.desc())
+            .limit(limit)
+            .into_boxed();
+
+        if let Some(x) = by_status {
+            query = query.filter(status.eq(x));
+        }
+
+        query.load(c).chain_err(|| "failed to get hook deliveries")
+    }
+
+    /// Lock the pending delivery that has been due the longest, skipping any
+    /// that another connection has locked. Must be called inside a
+    /// transaction, which holds the lock.
+    pub fn next_due(c: &PgConnection) -> Result<Option<HookDelivery>> {
+        use self::hook_deliveries::dsl::*;
+
+        hook_deliveries
+            .filter(status.eq(DeliveryStatus::Pending))
+            .filter(next_attempt_at.le(diesel::dsl::now))
+            .order(next_attempt_at.asc())
+            .limit(1)
+            .for_update()
+            .skip_locked()
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to get next hook delivery")
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The name of the hook the event is for.
+    pub fn hook(&self) -> &str {
+        &self.hook
+    }
+
+    pub fn event(&self) -> HookEvent {
+        self.event
+    }
+
+    /// What the hook is called with, as JSON.
+    pub fn payload(&self) -> &Value {
+        &self.payload
+    }
+
+    pub fn status(&self) -> DeliveryStatus {
+        self.status
+    }
+
+    /// How many times delivery was tried, since it was queued or replayed.
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+
+    /// Record that the hook accepted the event.
+    pub fn delivered(&self, c: &PgConnection) -> Result<HookDelivery> {
+        use self::hook_deliveries::dsl::*;
+
+        diesel::update(self)
+            .set((
+                status.eq(DeliveryStatus::Delivered),
+                attempts.eq(attempts + 1),
+                delivered_at.eq(Some(Utc::now())),
+            ))
+            .get_result(c)
+            .chain_err(|| "unable to update hook delivery")
+    }
+
+    /// Record that the hook failed with `error`. It's tried again at
+    /// `retry_at`, or marked as dead if that's `None`.
+    pub fn failed(
+        &self,
+        c: &PgConnection,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<HookDelivery> {
+        use self::hook_deliveries::dsl::*;
+
+        let new_status = match retry_at {
+            Some(_) => DeliveryStatus::Pending,
+            None => DeliveryStatus::Dead,
+        };
+
+        diesel::update(self)
+            .set((
+                status.eq(new_status),
+                attempts.eq(attempts + 1),
+                last_error.eq(Some(error)),
+                next_attempt_at.eq(retry_at.unwrap_or(self.next_attempt_at)),
+            ))
+            .get_result(c)
+            .chain_err(|| "unable to update hook delivery")
+    }
+
+    /// Queue this delivery again right away, with a fresh set of attempts,
+    /// whatever became of it before.
+    pub fn replay(&self, c: &PgConnection) -> Result<HookDelivery> {
+        use self::hook_deliveries::dsl::*;
+
+        diesel::update(self)
+            .set((
+                status.eq(DeliveryStatus::Pending),
+                attempts.eq(0),
+                next_attempt_at.eq(Utc::now()),
+                delivered_at.eq(None::<DateTime<Utc>>),
+            ))
+            .get_result(c)
+            .chain_err(|| "unable to replay hook delivery")
+    }
+
+    /// Queue every dead delivery again, optionally only those for
+    /// `for_hook`. Returns how many were replayed.
+    pub fn replay_dead(c: &PgConnection, for_hook: Option<&str>) -> Result<usize> {
+        use self::hook_deliveries::dsl::*;
+
+        let mut target = diesel::update(hook_deliveries.filter(status.eq(DeliveryStatus::Dead)))
+            .set((
+                status.eq(DeliveryStatus::Pending),
+                attempts.eq(0),
+                next_attempt_at.eq(Utc::now()),
+            ))
+            .into_boxed();
+
+        if let Some(x) = for_hook {
+            target = target.filter(hook.eq(x));
+        }
+
+        target
+            .execute(c)
+            .chain_err(|| "unable to replay dead hook deliveries")
+    }
+
+    /// Forget delivered events queued before `before`. Dead ones are kept
+    /// until someone replays them.
+    pub fn prune(c: &PgConnection, before: DateTime<Utc>) -> Result<()> {
+        use self::hook_deliveries::dsl::*;
+
+        let target = hook_deliveries
+            .filter(status.eq(DeliveryStatus::Delivered))
+            .filter(created_at.lt(before));
+
+        diesel::delete(target)
+            .execute(c)
+            .chain_err(|| "unable to delete old hook deliveries")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "hook_deliveries"]
+struct CreateHookDelivery<'a> {
+    hook: &'a str,
+    event: HookEvent,
+    payload: &'a Value,
+}
@@ -1,23 +1,34 @@
-//! A `Lease` is a duration of time that a `User` owns an `Asset`.
+//! A `Lease` is a duration of time that a `User` owns one or more `Asset`s.
 use crate::db::Db as PubDb;
 use crate::errors::*;
 use crate::schema::leases;
 
+use super::asset::{Asset, AssetStatus};
+use super::asset_type::AssetType;
+use super::ended_lease::{EndReason, EndedLease};
+use super::lease_field::FieldValue;
+use super::lease_quota;
+use super::lease_warning::LeaseWarning;
+use super::reservation::{CreateReservation, Reservation};
+use super::tag::{Tag, WantedTag};
 use super::user::User;
 
 use chrono::prelude::*;
+use chrono::Duration;
 
 use diesel::prelude::*;
+use diesel::result::Error as DieselError;
 
 use rocket::http::RawStr;
-use rocket::request::FromFormValue;
+use rocket::request::{FormItems, FromForm, FromFormValue};
 
+use std::collections::{BTreeMap, HashSet};
 use std::ops::{Deref, DerefMut};
 use std::result::Result as StdResult;
 use std::str::FromStr;
 
 /// A `Lease` is a duration of time that a `User` owns an `Asset`.
-#[derive(Debug, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[derive(Debug, Associations, Serialize, Deserialize, Queryable, Identifiable, PartialEq, Eq)]
 #[belongs_to(User)]
 pub struct Lease {
     id: i32,
@@ -26,6 +37,15 @@ pub struct Lease {
     last_notified: Option<DateTime<Utc>>,
     start_time: DateTime<Utc>,
     end_time: Option<DateTime<Utc>>,
+
+    extensions: i32,
+
+    purpose: Option<String>,
+
+    heartbeat_secs: Option<i32>,
+    last_heartbeat: Option<DateTime<Utc>>,
+
+    overdue_at: Option<DateTime<Utc>>,
 }
 
 impl Lease {
@@ -56,7 +76,7 @@ impl Lease {
         self.last_notified
     }
 
-    /// When this `Lease` was created.
+    /// When this `Lease` comes into effect.
     pub fn start_time(&self) -> DateTime<Utc> {
         self.start_time
     }
@@ -65,6 +85,322 @@ impl Lease {
     pub fn end_time(&self) -> Option<DateTime<Utc>> {
         self.end_time
     }
+
+    /// How many times this `Lease` has been extended.
+    pub fn extensions(&self) -> i32 {
+        self.extensions
+    }
+
+    /// Why this `Lease` was taken, in its owner's words.
+    pub fn purpose(&self) -> Option<&str> {
+        self.purpose.as_ref().map(String::as_str)
+    }
+
+    /// How many seconds this `Lease` can go without a heartbeat before the
+    /// sheriff treats it as abandoned, or `None` if it doesn't need any.
+    pub fn heartbeat_secs(&self) -> Option<i32> {
+        self.heartbeat_secs
+    }
+
+    /// When the last heartbeat was received for this `Lease`.
+    pub fn last_heartbeat(&self) -> Option<DateTime<Utc>> {
+        self.last_heartbeat
+    }
+
+    /// When the sheriff found this `Lease` past its end time, but still in its
+    /// grace period. Overdue leases can still be extended.
+    pub fn overdue_at(&self) -> Option<DateTime<Utc>> {
+        self.overdue_at
+    }
+
+    /// When this `Lease` will be abandoned unless it gets a heartbeat.
+    pub fn heartbeat_due(&self) -> Option<DateTime<Utc>> {
+        let since = self.last_heartbeat.unwrap_or(self.start_time);
+        self.heartbeat_secs
+            .map(|x| since + Duration::seconds(x.into()))
+    }
+
+    /// Everyone who shares this `Lease`, starting with its owner.
+    pub fn owners(&self, c: &PubDb) -> Result<Vec<User>> {
+        let owner = User::by_id(c, self.user_id)?.chain_err(|| "missing lease owner")?;
+
+        let mut owners = vec![owner];
+        owners.extend(self.co_owners(c.db())?);
+
+        Ok(owners)
+    }
+
+    /// Users who share this `Lease` with its owner.
+    pub(crate) fn co_owners(&self, c: &PgConnection) -> Result<Vec<User>> {
+        use crate::schema::lease_users::dsl as lu;
+        use crate::schema::users::dsl as u;
+
+        lu::lease_users
+            .inner_join(u::users)
+            .filter(lu::lease_id.eq(self.id))
+            .order(u::email.asc())
+            .select(crate::schema::users::all_columns)
+            .load(c)
+            .chain_err(|| "unable to get co-owners for lease")
+    }
+
+    /// The primary keys of the asset types of every `Asset` this `Lease`
+    /// holds or has reserved.
+    pub(crate) fn type_ids(&self, c: &PgConnection) -> Result<Vec<i32>> {
+        use crate::schema::assets::dsl as a;
+        use crate::schema::reservations::dsl as r;
+
+        r::reservations
+            .inner_join(a::assets)
+            .filter(r::lease_id.eq(self.id))
+            .select(a::type_id)
+            .distinct()
+            .load(c)
+            .chain_err(|| "unable to get asset types for lease")
+    }
+
+    /// Returns `true` if `user_id` is this `Lease`'s owner or a co-owner.
+    pub(crate) fn is_owner(&self, c: &PgConnection, user_id: i32) -> Result<bool> {
+        use crate::schema::lease_users::dsl as lu;
+
+        if self.user_id == user_id {
+            return Ok(true);
+        }
+
+        let found: Option<i32> = lu::lease_users
+            .filter(lu::lease_id.eq(self.id))
+            .filter(lu::user_id.eq(user_id))
+            .select(lu::user_id)
+            .get_result(c)
+            .optional()
+            .chain_err(|| "unable to check co-owners for lease")?;
+
+        Ok(found.is_some())
+    }
+
+    /// Delete this `Lease`, archiving a copy of it for every `Asset` it
+    /// holds.
+    ///
+    /// Returns `None` if the lease has already been deleted.
+    pub(crate) fn end(
+        &self,
+        c: &PgConnection,
+        reason: EndReason,
+    ) -> Result<Option<Vec<EndedLease>>> {
+        use crate::schema::assets::dsl as a;
+        use crate::schema::leases::dsl as l;
+
+        c.transaction::<_, Error, _>(|| {
+            let asset_ids: Vec<i32> = a::assets
+                .filter(a::lease_id.eq(self.id))
+                .select(a::id)
+                .for_update()
+                .load(c)?;
+
+            let deleted: Option<Lease> = diesel::delete(l::leases.filter(l::id.eq(self.id)))
+                .get_result(c)
+                .optional()?;
+
+            let deleted = match deleted {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            let ended = EndedLease::archive(c, &deleted, &asset_ids, reason)?;
+
+            Ok(Some(ended))
+        })
+    }
+
+    /// End this `Lease` on behalf of an administrator, recording who revoked
+    /// it and why in the archived copies.
+    ///
+    /// Returns `None` if the lease had already ended.
+    pub(crate) fn revoke(
+        &self,
+        c: &PgConnection,
+        by_user_id: i32,
+        reason: &str,
+    ) -> Result<Option<Vec<EndedLease>>> {
+        use crate::schema::ended_leases::dsl as el;
+
+        c.transaction::<_, Error, _>(|| {
+            let ended = match self.end(c, EndReason::Revoked)? {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            let ids: Vec<i32> = ended.iter().map(EndedLease::id).collect();
+
+            let revoked = diesel::update(el::ended_leases.filter(el::id.eq_any(ids)))
+                .set((el::revoked_by.eq(by_user_id), el::revoke_reason.eq(reason)))
+                .get_results(c)?;
+
+            Ok(Some(revoked))
+        })
+    }
+
+    /// Hand this `Lease` over to the `User` identified by `to_user_id`,
+    /// archiving a copy of it for the previous owner.
+    ///
+    /// Any pending transfer is cleared, and the new owner stops being a
+    /// co-owner. Returns `None` if the lease has ended, or changed owner in
+    /// the meantime. If the lease would put the new owner over their quota,
+    /// the error has `ErrorKind::QuotaExceeded`.
+    pub(crate) fn transfer(
+        &self,
+        c: &PgConnection,
+        to_user_id: i32,
+    ) -> Result<Option<(Lease, Vec<EndedLease>)>> {
+        use crate::schema::assets::dsl as a;
+        use crate::schema::lease_transfers::dsl as lt;
+        use crate::schema::lease_users::dsl as lu;
+        use crate::schema::leases::dsl as l;
+
+        c.transaction::<_, Error, _>(|| {
+            let to_update = l::leases
+                .filter(l::id.eq(self.id))
+                .filter(l::user_id.eq(self.user_id));
+
+            let updated: Option<Lease> = diesel::update(to_update)
+                .set(l::user_id.eq(to_user_id))
+                .get_result(c)
+                .optional()?;
+
+            let updated = match updated {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            lease_quota::enforce(c, &updated, &updated.type_ids(c)?)?;
+
+            let asset_ids: Vec<i32> = a::assets
+                .filter(a::lease_id.eq(self.id))
+                .select(a::id)
+                .load(c)?;
+
+            let ended = EndedLease::archive(c, self, &asset_ids, EndReason::Transferred)?;
+
+            diesel::delete(lt::lease_transfers.filter(lt::lease_id.eq(self.id))).execute(c)?;
+
+            let co_owner = lu::lease_users
+                .filter(lu::lease_id.eq(self.id))
+                .filter(lu::user_id.eq(to_user_id));
+
+            diesel::delete(co_owner).execute(c)?;
+
+            Ok(Some((updated, ended)))
+        })
+    }
+
+    /// Record that this `Lease`'s holder is still using it.
+    ///
+    /// Returns `None` if the lease has ended.
+    pub(crate) fn heartbeat(&self, c: &PgConnection) -> Result<Option<Lease>> {
+        use self::leases::dsl::*;
+
+        diesel::update(leases.filter(id.eq(self.id)))
+            .set(last_heartbeat.eq(Some(Utc::now())))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "unable to record heartbeat for lease")
+    }
+
+    /// Mark this `Lease` as overdue, unless it already was. Returns `None` if
+    /// it was, or it's gone.
+    pub(crate) fn mark_overdue(&self, c: &PgConnection) -> Result<Option<Lease>> {
+        use self::leases::dsl::*;
+
+        let to_update = leases.filter(id.eq(self.id)).filter(overdue_at.is_null());
+
+        diesel::update(to_update)
+            .set(overdue_at.eq(Some(Utc::now())))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "unable to mark lease as overdue")
+    }
+
+    /// Push the end of this `Lease` out to `end_time`, within the limits set
+    /// by `asset_type`.
+    ///
+    /// Clears `last_notified`, `overdue_at`, and the warnings sent so far, so
+    /// the sheriff warns about the new end time.
+    ///
+    /// Must be called inside a transaction, so the extension is undone if it
+    /// puts the owner over their quota. The error then has
+    /// `ErrorKind::QuotaExceeded`.
+    pub(crate) fn extend(
+        &self,
+        c: &PgConnection,
+        asset_type: &AssetType,
+        end_time: DateTime<Utc>,
+    ) -> Result<Extended> {
+        use self::leases::dsl::*;
+
+        match self.end_time {
+            Some(x) if end_time > x => (),
+            _ => return Ok(Extended::Invalid),
+        }
+
+        if let Some(max) = asset_type.max_extensions() {
+            if self.extensions >= max {
+                return Ok(Extended::TooManyExtensions);
+            }
+        }
+
+        if let Some(max) = asset_type.max_lease_seconds() {
+            if end_time - self.start_time > Duration::seconds(max.into()) {
+                return Ok(Extended::TooLong);
+            }
+        }
+
+        // Only update the lease if nobody else extended it in the meantime.
+        let to_update = leases
+            .filter(id.eq(self.id))
+            .filter(extensions.eq(self.extensions));
+
+        let result = diesel::update(to_update)
+            .set((
+                self::leases::end_time.eq(Some(end_time)),
+                last_notified.eq(None::<DateTime<Utc>>),
+                overdue_at.eq(None::<DateTime<Utc>>),
+                extensions.eq(extensions + 1),
+            ))
+            .get_result(c)
+            .optional();
+
+        match result {
+            Ok(Some(x)) => {
+                LeaseWarning::clear(c, x.id)?;
+                lease_quota::enforce(c, &x, &x.type_ids(c)?)?;
+                Ok(Extended::Extended(x))
+            }
+            Ok(None) => Ok(Extended::Conflict),
+            Err(ref e) if Reservation::is_overlap(e) => Ok(Extended::Conflict),
+            Err(e) => Err(e).chain_err(|| "unable to extend lease"),
+        }
+    }
+}
+
+/// The outcome of [`Lease::extend`].
+#[derive(Debug)]
+pub(crate) enum Extended {
+    /// The lease now ends later.
+    Extended(Lease),
+
+    /// The new end time isn't after the current one, or the lease never ends.
+    Invalid,
+
+    /// The lease would last longer than its asset type allows.
+    TooLong,
+
+    /// The lease has already been extended as many times as its asset type
+    /// allows.
+    TooManyExtensions,
+
+    /// The lease was changed concurrently, or the new end time runs into a
+    /// reservation.
+    Conflict,
 }
 
 /// Insertable companion to [`Lease`].
@@ -94,6 +430,14 @@ pub struct CreateLease {
 
     start_time: DateTime<Utc>,
     end_time: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    #[builder(default)]
+    purpose: Option<String>,
+
+    #[serde(default)]
+    #[builder(default)]
+    heartbeat_secs: Option<i32>,
 }
 
 impl CreateLease {
@@ -112,6 +456,16 @@ impl CreateLease {
         self.end_time
     }
 
+    /// Why the new `Lease` is being taken.
+    pub fn purpose(&self) -> Option<&str> {
+        self.purpose.as_ref().map(String::as_str)
+    }
+
+    /// How often the new `Lease` needs a heartbeat, in seconds.
+    pub fn heartbeat_secs(&self) -> Option<i32> {
+        self.heartbeat_secs
+    }
+
     /// Insert the `Lease` into the database and return it.
     pub fn insert(&self, c: &PubDb) -> Result<Lease> {
         use self::leases::dsl::*;
@@ -121,6 +475,314 @@ impl CreateLease {
             .get_result(c.db())
             .chain_err(|| "unable to insert lease")
     }
+
+    /// Returns `false` if the new `Lease` would end before it starts, or its
+    /// heartbeat interval isn't positive.
+    pub(crate) fn is_valid(&self) -> bool {
+        if let Some(x) = self.heartbeat_secs {
+            if x <= 0 {
+                return false;
+            }
+        }
+
+        match self.end_time {
+            Some(end_time) => end_time > self.start_time,
+            None => true,
+        }
+    }
+
+    /// Insert the `Lease` with its `fields` and reserve the `Asset` identified
+    /// by `asset_id` for it, all in one transaction.
+    ///
+    /// If the lease has already started, the asset's current lease is set
+    /// too. Otherwise the sheriff takes care of that once the lease starts.
+    pub(crate) fn reserve(
+        &self,
+        c: &PgConnection,
+        asset_id: i32,
+        fields: &[FieldValue],
+    ) -> Result<Reserved> {
+        use crate::schema::leases::dsl as l;
+
+        if !self.is_valid() {
+            return Ok(Reserved::Invalid);
+        }
+
+        let now = Utc::now();
+
+        let result = c.transaction::<_, DieselError, _>(|| {
+            let lease: Lease = diesel::insert_into(l::leases).values(self).get_result(c)?;
+            FieldValue::insert_all(c, &lease, fields)?;
+
+            if lease.start_time() > now {
+                CreateReservation::new(&lease, asset_id).insert(c)?;
+                return Ok(Reserved::Upcoming(lease));
+            }
+
+            match take(c, &lease, asset_id)? {
+                Some(asset) => Ok(Reserved::Started(lease, asset)),
+                None => Err(DieselError::RollbackTransaction),
+            }
+        });
+
+        match result {
+            Ok(x) => Ok(x),
+            Err(DieselError::RollbackTransaction) => Ok(Reserved::Conflict),
+            Err(ref e) if Reservation::is_overlap(e) => Ok(Reserved::Conflict),
+            Err(e) => Err(e).chain_err(|| "unable to reserve asset"),
+        }
+    }
+
+    /// Insert the `Lease` with its `fields` and give it any free `Asset` of
+    /// the `AssetType` identified by `type_id` that has all the `wanted` tags.
+    ///
+    /// Assets locked by concurrent claims are skipped rather than waited on.
+    /// Returns [`Reserved::Conflict`] if there are no matching free assets.
+    pub(crate) fn claim(
+        &self,
+        c: &PgConnection,
+        type_id: i32,
+        wanted: &[WantedTag],
+        fields: &[FieldValue],
+    ) -> Result<Reserved> {
+        use crate::schema::leases::dsl as l;
+
+        if !self.is_valid() {
+            return Ok(Reserved::Invalid);
+        }
+
+        let candidates = matching_free_assets(c, type_id, wanted)?;
+
+        let result = c.transaction::<_, DieselError, _>(|| {
+            let lease: Lease = diesel::insert_into(l::leases).values(self).get_result(c)?;
+            FieldValue::insert_all(c, &lease, fields)?;
+
+            match take_any(c, &lease, &candidates)? {
+                Some(asset) => Ok(Reserved::Started(lease, asset)),
+                None => Err(DieselError::RollbackTransaction),
+            }
+        });
+
+        match result {
+            Ok(x) => Ok(x),
+            Err(DieselError::RollbackTransaction) => Ok(Reserved::Conflict),
+            Err(e) => Err(e).chain_err(|| "unable to claim asset"),
+        }
+    }
+
+    /// Insert one `Lease` with its `fields` that holds an `Asset` for every
+    /// item in `items`, or don't insert anything at all.
+    ///
+    /// The lease must start right away.
+    pub(crate) fn bundle(
+        &self,
+        c: &PgConnection,
+        items: &[BundleItem],
+        fields: &[FieldValue],
+    ) -> Result<Bundled> {
+        use crate::schema::leases::dsl as l;
+
+        if !self.is_valid() || self.start_time > Utc::now() || items.is_empty() {
+            return Ok(Bundled::Invalid);
+        }
+
+        let mut seen = HashSet::new();
+        let mut wanted = Vec::with_capacity(items.len());
+
+        for item in items {
+            let choice = match item {
+                BundleItem::Asset { asset_id } => {
+                    if !seen.insert(*asset_id) {
+                        return Ok(Bundled::Invalid);
+                    }
+
+                    Choice::Exactly(*asset_id)
+                }
+                BundleItem::Claim { type_id, tags } => {
+                    Choice::AnyOf(matching_free_assets(c, *type_id, tags)?)
+                }
+            };
+
+            wanted.push(choice);
+        }
+
+        let result = c.transaction::<_, DieselError, _>(|| {
+            let lease: Lease = diesel::insert_into(l::leases).values(self).get_result(c)?;
+            FieldValue::insert_all(c, &lease, fields)?;
+
+            let mut assets = Vec::with_capacity(wanted.len());
+
+            for choice in wanted.iter() {
+                let taken = match choice {
+                    Choice::Exactly(asset_id) => take(c, &lease, *asset_id)?,
+                    Choice::AnyOf(candidates) => take_any(c, &lease, candidates)?,
+                };
+
+                match taken {
+                    Some(asset) => assets.push(asset),
+                    None => return Err(DieselError::RollbackTransaction),
+                }
+            }
+
+            Ok(Bundled::Started(lease, assets))
+        });
+
+        match result {
+            Ok(x) => Ok(x),
+            Err(DieselError::RollbackTransaction) => Ok(Bundled::Conflict),
+            Err(e) => Err(e).chain_err(|| "unable to lease bundle"),
+        }
+    }
+}
+
+/// IDs of the free, in service `Asset`s of the `AssetType` identified by
+/// `type_id` that have all the `wanted` tags.
+fn matching_free_assets(c: &PgConnection, type_id: i32, wanted: &[WantedTag]) -> Result<Vec<i32>> {
+    use crate::schema::assets::dsl as a;
+
+    let free: Vec<Asset> = a::assets
+        .filter(a::type_id.eq(type_id))
+        .filter(a::lease_id.is_null())
+        .filter(a::status.eq(AssetStatus::Available))
+        .load(c)
+        .chain_err(|| "unable to get free assets")?;
+
+    let tags = Tag::belonging_to(&free)
+        .load::<Tag>(c)
+        .chain_err(|| "unable to get tags for free assets")?
+        .grouped_by(&free);
+
+    let candidates = free
+        .iter()
+        .zip(tags)
+        .filter(|(_, tags)| WantedTag::all_satisfied_by(wanted, tags))
+        .map(|(asset, _)| asset.id())
+        .collect();
+
+    Ok(candidates)
+}
+
+/// Reserve the `Asset` identified by `asset_id` for `lease`, which has already
+/// started, and make `lease` the asset's current lease.
+///
+/// Returns `None` if the asset is already leased, is out of service, or is
+/// reserved before `lease` ends. Runs in a savepoint, so the surrounding
+/// transaction can carry on either way.
+fn take(c: &PgConnection, lease: &Lease, asset_id: i32) -> StdResult<Option<Asset>, DieselError> {
+    use crate::schema::assets::dsl as a;
+
+    let result = c.transaction::<_, DieselError, _>(|| {
+        CreateReservation::new(lease, asset_id).insert(c)?;
+
+        let to_update = a::assets
+            .filter(a::id.eq(asset_id))
+            .filter(a::lease_id.is_null())
+            .filter(a::status.eq(AssetStatus::Available));
+
+        let updated: Option<Asset> = diesel::update(to_update)
+            .set(a::lease_id.eq(Some(lease.id())))
+            .get_result(c)
+            .optional()?;
+
+        updated.ok_or(DieselError::RollbackTransaction)
+    });
+
+    match result {
+        Ok(x) => Ok(Some(x)),
+        Err(DieselError::RollbackTransaction) => Ok(None),
+        Err(ref e) if Reservation::is_overlap(e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Like `take`, but for the first of `candidates` that isn't locked by a
+/// concurrent transaction and can be taken.
+fn take_any(
+    c: &PgConnection,
+    lease: &Lease,
+    candidates: &[i32],
+) -> StdResult<Option<Asset>, DieselError> {
+    use crate::schema::assets::dsl as a;
+
+    let mut tried = vec![];
+
+    loop {
+        let next: Option<i32> = a::assets
+            .filter(a::id.eq_any(candidates))
+            .filter(a::lease_id.is_null())
+            .filter(a::status.eq(AssetStatus::Available))
+            .filter(diesel::dsl::not(a::id.eq_any(&tried)))
+            .order(a::id.asc())
+            .select(a::id)
+            .limit(1)
+            .for_update()
+            .skip_locked()
+            .get_result(c)
+            .optional()?;
+
+        let asset_id = match next {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        if let Some(asset) = take(c, lease, asset_id)? {
+            return Ok(Some(asset));
+        }
+
+        tried.push(asset_id);
+    }
+}
+
+/// The outcome of [`CreateLease::reserve`].
+#[derive(Debug)]
+pub(crate) enum Reserved {
+    /// The lease has already started, and the asset now belongs to it.
+    Started(Lease, Asset),
+
+    /// The lease starts in the future, and the asset is reserved for it.
+    Upcoming(Lease),
+
+    /// The asset is already leased, or is reserved during the requested time.
+    Conflict,
+
+    /// The lease would end before it starts.
+    Invalid,
+}
+
+/// One of the `Asset`s wanted by a bundle lease.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum BundleItem {
+    /// A specific asset.
+    Asset { asset_id: i32 },
+
+    /// Any free asset of an asset type with all of the given tags.
+    Claim {
+        type_id: i32,
+
+        #[serde(default)]
+        tags: Vec<WantedTag>,
+    },
+}
+
+/// A `BundleItem`, narrowed down to the assets that could satisfy it.
+enum Choice {
+    Exactly(i32),
+    AnyOf(Vec<i32>),
+}
+
+/// The outcome of [`CreateLease::bundle`].
+#[derive(Debug)]
+pub(crate) enum Bundled {
+    /// The lease now holds all of these assets.
+    Started(Lease, Vec<Asset>),
+
+    /// At least one of the assets couldn't be leased, so none were.
+    Conflict,
+
+    /// The lease would end before it starts, doesn't start right away, or
+    /// asks for the same asset twice.
+    Invalid,
 }
 
 #[derive(Debug, Clone, Copy, Deserialize)]
@@ -151,9 +813,191 @@ impl<'v> FromFormValue<'v> for DateField {
     }
 }
 
-#[derive(Debug, FromForm, Deserialize)]
+/// Trim `purpose`, and drop it if it's blank.
+fn clean_purpose(purpose: Option<String>) -> Option<String> {
+    purpose
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+}
+
+#[derive(Debug, Deserialize)]
 pub(crate) struct CreateLeaseForm {
+    #[serde(default)]
+    start_time: Option<DateField>,
+
+    #[serde(default)]
     end_time: Option<DateField>,
+
+    #[serde(default)]
+    purpose: Option<String>,
+
+    #[serde(default)]
+    heartbeat_secs: Option<i32>,
+
+    /// Values for the asset type's `LeaseField`s, keyed by field name.
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+/// HTML forms name the inputs for `LeaseField`s `field.<name>`.
+impl<'f> FromForm<'f> for CreateLeaseForm {
+    type Error = ();
+
+    fn from_form(items: &mut FormItems<'f>, strict: bool) -> StdResult<Self, ()> {
+        let mut form = CreateLeaseForm {
+            start_time: None,
+            end_time: None,
+            purpose: None,
+            heartbeat_secs: None,
+            fields: BTreeMap::new(),
+        };
+
+        for item in items {
+            let key = String::from_form_value(item.key).map_err(|_| ())?;
+
+            match key.as_str() {
+                "start_time" => form.start_time = DateField::from_form_value(item.value).ok(),
+                "end_time" => form.end_time = DateField::from_form_value(item.value).ok(),
+                "purpose" => {
+                    form.purpose = Some(String::from_form_value(item.value).map_err(|_| ())?);
+                }
+                "heartbeat_secs" => form.heartbeat_secs = i32::from_form_value(item.value).ok(),
+                "_method" => (),
+                x if x.starts_with("field.") => {
+                    let value = String::from_form_value(item.value).map_err(|_| ())?;
+                    form.fields.insert(x["field.".len()..].to_owned(), value);
+                }
+                _ if strict => return Err(()),
+                _ => (),
+            }
+        }
+
+        Ok(form)
+    }
+}
+
+/// Request to push out the end of a `Lease`.
+#[derive(Debug, FromForm, Deserialize)]
+pub(crate) struct ExtendLeaseForm {
+    end_time: DateField,
+}
+
+impl ExtendLeaseForm {
+    pub fn end_time(&self) -> DateTime<Utc> {
+        self.end_time.0
+    }
+}
+
+/// Request by an administrator to end someone else's `Lease`.
+#[derive(Debug, FromForm, Deserialize)]
+pub(crate) struct RevokeLeaseForm {
+    reason: String,
+}
+
+impl RevokeLeaseForm {
+    pub fn reason(&self) -> &str {
+        self.reason.trim()
+    }
+}
+
+/// Request to hand a `Lease` to another `User`.
+#[derive(Debug, FromForm, Deserialize)]
+pub(crate) struct TransferLeaseForm {
+    email: String,
+
+    #[serde(default)]
+    require_accept: bool,
+}
+
+impl TransferLeaseForm {
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// Whether the recipient has to accept the transfer before it happens.
+    pub fn require_accept(&self) -> bool {
+        self.require_accept
+    }
+}
+
+/// Request for a `Lease` on any free `Asset` of an `AssetType` with the
+/// given tags.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ClaimLeaseForm {
+    #[serde(default)]
+    end_time: Option<DateField>,
+
+    #[serde(default)]
+    tags: Vec<WantedTag>,
+
+    #[serde(default)]
+    purpose: Option<String>,
+
+    #[serde(default)]
+    heartbeat_secs: Option<i32>,
+
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+impl ClaimLeaseForm {
+    pub fn tags(&self) -> &[WantedTag] {
+        &self.tags
+    }
+
+    pub fn fields(&self) -> &BTreeMap<String, String> {
+        &self.fields
+    }
+
+    /// Claimed leases always start now.
+    pub fn to_create_lease(&self, user_id: i32) -> CreateLease {
+        CreateLease::builder()
+            .user_id(user_id)
+            .start_time(Utc::now())
+            .end_time(self.end_time.map(|x| x.0))
+            .purpose(clean_purpose(self.purpose.clone()))
+            .heartbeat_secs(self.heartbeat_secs)
+            .build()
+    }
+}
+
+/// Request for a single `Lease` on several `Asset`s at once.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateBundleForm {
+    #[serde(default)]
+    end_time: Option<DateField>,
+
+    assets: Vec<BundleItem>,
+
+    #[serde(default)]
+    purpose: Option<String>,
+
+    #[serde(default)]
+    heartbeat_secs: Option<i32>,
+
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+impl CreateBundleForm {
+    pub fn assets(&self) -> &[BundleItem] {
+        &self.assets
+    }
+
+    pub fn fields(&self) -> &BTreeMap<String, String> {
+        &self.fields
+    }
+
+    /// Bundles always start now.
+    pub fn to_create_lease(&self, user_id: i32) -> CreateLease {
+        CreateLease::builder()
+            .user_id(user_id)
+            .start_time(Utc::now())
+            .end_time(self.end_time.map(|x| x.0))
+            .purpose(clean_purpose(self.purpose.clone()))
+            .heartbeat_secs(self.heartbeat_secs)
+            .build()
+    }
 }
 
 impl CreateLeaseForm {
@@ -161,11 +1005,25 @@ impl CreateLeaseForm {
         self.end_time.map(|x| x.0)
     }
 
+    pub fn fields(&self) -> &BTreeMap<String, String> {
+        &self.fields
+    }
+
+    /// Leases without a start time, or with one in the past, start now.
     pub fn into_create_lease(self, user_id: i32) -> CreateLease {
+        let now = Utc::now();
+
+        let start_time = match self.start_time {
+            Some(x) if *x > now => *x,
+            _ => now,
+        };
+
         CreateLease::builder()
             .user_id(user_id)
-            .start_time(Utc::now())
+            .start_time(start_time)
             .end_time(self.end_time.map(|x| x.0))
+            .purpose(clean_purpose(self.purpose))
+            .heartbeat_secs(self.heartbeat_secs)
             .build()
     }
 }
@@ -0,0 +1,437 @@
+//! A `LeaseField` is something an `AssetType` asks for whenever one of its
+//! `Asset`s is leased, like a ticket id or a build number.
+
+use crate::db::Db as PubDb;
+use crate::errors::*;
+use crate::schema::{lease_field_values, lease_fields};
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use regex::Regex;
+
+use std::collections::{BTreeMap, HashMap};
+use std::result::Result as StdResult;
+
+use super::asset_type::AssetType;
+use super::lease::Lease;
+
+#[derive(Debug, Clone, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[belongs_to(AssetType)]
+pub struct LeaseField {
+    id: i32,
+    asset_type_id: i32,
+
+    name: String,
+    required: bool,
+    pattern: Option<String>,
+}
+
+impl LeaseField {
+    /// The fields asked for by any of the `AssetType`s identified by
+    /// `type_ids`.
+    pub(crate) fn for_types(c: &PgConnection, type_ids: &[i32]) -> Result<Vec<LeaseField>> {
+        use self::lease_fields::dsl::*;
+
+        lease_fields
+            .filter(asset_type_id.eq_any(type_ids))
+            .order(id.asc())
+            .load(c)
+            .chain_err(|| "unable to get lease fields for asset types")
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn asset_type_id(&self) -> i32 {
+        self.asset_type_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Leases can't be created without a value for a required field.
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    /// A regular expression that values must match in full.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_ref().map(String::as_str)
+    }
+
+    /// Returns `true` if `value` matches this field's pattern, if it has one.
+    fn accepts(&self, value: &str) -> bool {
+        match &self.pattern {
+            Some(x) => match anchored(x) {
+                Ok(re) => re.is_match(value),
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
+
+    /// Check the `values` given for a new lease against `fields`, keyed by
+    /// field name.
+    ///
+    /// Returns `None` if a required field is missing, a value doesn't match
+    /// its field's pattern, or a value doesn't belong to any field. Blank
+    /// values are treated as missing.
+    pub(crate) fn resolve(
+        fields: &[LeaseField],
+        values: &BTreeMap<String, String>,
+    ) -> Option<Vec<FieldValue>> {
+        let mut resolved = Vec::with_capacity(fields.len());
+
+        for field in fields {
+            let value = values
+                .get(field.name())
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty());
+
+            match value {
+                Some(x) if field.accepts(x) => resolved.push(FieldValue {
+                    lease_field_id: field.id,
+                    value: x.to_owned(),
+                }),
+                Some(_) => return None,
+                None if field.required => return None,
+                None => (),
+            }
+        }
+
+        let all_known = values
+            .iter()
+            .filter(|(_, v)| !v.trim().is_empty())
+            .all(|(k, _)| fields.iter().any(|f| f.name == *k));
+
+        if all_known {
+            Some(resolved)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compile `pattern` so that it has to match the whole value.
+///
+/// The pattern has to be valid on its own first, or one like `a)|(b` could
+/// close the group early and escape the anchors.
+fn anchored(pattern: &str) -> StdResult<Regex, regex::Error> {
+    Regex::new(pattern)?;
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+/// The insertable companion of `LeaseField`.
+#[derive(Debug, Deserialize, Insertable, TypedBuilder)]
+#[table_name = "lease_fields"]
+pub struct CreateLeaseField {
+    asset_type_id: i32,
+    name: String,
+
+    #[serde(default)]
+    #[builder(default)]
+    required: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pattern: Option<String>,
+}
+
+impl CreateLeaseField {
+    /// The name of the `LeaseField` to be created.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `false` if the name is blank or the pattern isn't a valid
+    /// regular expression.
+    pub fn is_valid(&self) -> bool {
+        if self.name.trim().is_empty() {
+            return false;
+        }
+
+        match &self.pattern {
+            Some(x) => anchored(x).is_ok(),
+            None => true,
+        }
+    }
+
+    /// Insert the `LeaseField` into the database and return it.
+    pub fn insert(&self, c: &PubDb) -> Result<LeaseField> {
+        use self::lease_fields::dsl::*;
+
+        diesel::insert_into(lease_fields)
+            .values(self)
+            .get_result(c.db())
+            .chain_err(|| "unable to insert lease field")
+    }
+}
+
+/// Similar to `CreateLeaseField`, but doesn't include `asset_type_id`.
+#[derive(Debug, Deserialize)]
+pub struct CreateOwnedLeaseField {
+    name: String,
+
+    #[serde(default)]
+    required: bool,
+
+    #[serde(default)]
+    pattern: Option<String>,
+}
+
+impl CreateOwnedLeaseField {
+    pub fn into_create_lease_field(self, asset_type_id: i32) -> CreateLeaseField {
+        CreateLeaseField {
+            asset_type_id,
+            name: self.name,
+            required: self.required,
+            pattern: self.pattern,
+        }
+    }
+}
+
+/// A value for a `LeaseField`, checked and ready to be stored with a new
+/// `Lease`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldValue {
+    lease_field_id: i32,
+    value: String,
+}
+
+impl FieldValue {
+    /// Store all of `values` for `lease`.
+    pub(crate) fn insert_all(
+        c: &PgConnection,
+        lease: &Lease,
+        values: &[FieldValue],
+    ) -> StdResult<(), DieselError> {
+        use self::lease_field_values::dsl::*;
+
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        let records: Vec<_> = values
+            .iter()
+            .map(|x| CreateLeaseFieldValue {
+                lease_id: lease.id(),
+                lease_field_id: x.lease_field_id,
+                value: &x.value,
+            })
+            .collect();
+
+        diesel::insert_into(lease_field_values)
+            .values(&records)
+            .execute(c)?;
+
+        Ok(())
+    }
+
+    /// The name and value of every field stored with the `Lease` identified
+    /// by `lease_id`.
+    pub(crate) fn for_lease(c: &PgConnection, by_lease_id: i32) -> Result<Vec<(String, String)>> {
+        use self::lease_field_values::dsl as lfv;
+        use self::lease_fields::dsl as lf;
+
+        lfv::lease_field_values
+            .inner_join(lf::lease_fields)
+            .filter(lfv::lease_id.eq(by_lease_id))
+            .order(lf::id.asc())
+            .select((lf::name, lfv::value))
+            .load(c)
+            .chain_err(|| "unable to get field values for lease")
+    }
+
+    /// Like `for_lease`, but for every `Lease` identified by `lease_ids` at
+    /// once, keyed by lease.
+    pub(crate) fn for_leases(
+        c: &PgConnection,
+        lease_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<(String, String)>>> {
+        use self::lease_field_values::dsl as lfv;
+        use self::lease_fields::dsl as lf;
+
+        let rows: Vec<(i32, String, String)> = lfv::lease_field_values
+            .inner_join(lf::lease_fields)
+            .filter(lfv::lease_id.eq_any(lease_ids))
+            .order(lf::id.asc())
+            .select((lfv::lease_id, lf::name, lfv::value))
+            .load(c)
+            .chain_err(|| "unable to get field values for leases")?;
+
+        let mut by_lease = HashMap::new();
+
+        for (lease_id, name, value) in rows {
+            by_lease
+                .entry(lease_id)
+                .or_insert_with(Vec::new)
+                .push((name, value));
+        }
+
+        Ok(by_lease)
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "lease_field_values"]
+struct CreateLeaseFieldValue<'a> {
+    lease_id: i32,
+    lease_field_id: i32,
+    value: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(id: i32, name: &str, required: bool, pattern: Option<&str>) -> LeaseField {
+        LeaseField {
+            id,
+            asset_type_id: 1,
+            name: name.to_owned(),
+            required,
+            pattern: pattern.map(str::to_owned),
+        }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn value(lease_field_id: i32, value: &str) -> FieldValue {
+        FieldValue {
+            lease_field_id,
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn resolve_required() {
+        let fields = [field(1, "ticket", true, None)];
+
+        let resolved = LeaseField::resolve(&fields, &values(&[("ticket", "T-1")]));
+        assert_eq!(resolved, Some(vec![value(1, "T-1")]));
+
+        assert_eq!(LeaseField::resolve(&fields, &values(&[])), None);
+    }
+
+    #[test]
+    fn resolve_optional() {
+        let fields = [field(1, "ticket", false, None)];
+
+        assert_eq!(LeaseField::resolve(&fields, &values(&[])), Some(vec![]));
+    }
+
+    #[test]
+    fn resolve_blank() {
+        let required = [field(1, "ticket", true, None)];
+        let optional = [field(1, "ticket", false, None)];
+        let blank = values(&[("ticket", "  ")]);
+
+        assert_eq!(LeaseField::resolve(&required, &blank), None);
+        assert_eq!(LeaseField::resolve(&optional, &blank), Some(vec![]));
+    }
+
+    #[test]
+    fn resolve_trims() {
+        let fields = [field(1, "ticket", true, None)];
+
+        let resolved = LeaseField::resolve(&fields, &values(&[("ticket", " T-1 ")]));
+        assert_eq!(resolved, Some(vec![value(1, "T-1")]));
+    }
+
+    #[test]
+    fn resolve_unknown_key() {
+        let fields = [field(1, "ticket", false, None)];
+
+        let unknown = values(&[("build", "42")]);
+        assert_eq!(LeaseField::resolve(&fields, &unknown), None);
+
+        // Blank values are treated as missing, even for unknown fields.
+        let blank = values(&[("build", "")]);
+        assert_eq!(LeaseField::resolve(&fields, &blank), Some(vec![]));
+    }
+
+    #[test]
+    fn resolve_pattern() {
+        let fields = [field(1, "build", true, Some("[0-9]+"))];
+
+        let resolved = LeaseField::resolve(&fields, &values(&[("build", "42")]));
+        assert_eq!(resolved, Some(vec![value(1, "42")]));
+
+        assert_eq!(
+            LeaseField::resolve(&fields, &values(&[("build", "x")])),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve_pattern_matches_whole_value() {
+        let fields = [field(1, "build", true, Some("[0-9]+"))];
+
+        assert_eq!(
+            LeaseField::resolve(&fields, &values(&[("build", "42x")])),
+            None
+        );
+        assert_eq!(
+            LeaseField::resolve(&fields, &values(&[("build", "x42")])),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve_invalid_pattern() {
+        let fields = [field(1, "build", true, Some("a)|(b"))];
+
+        assert_eq!(
+            LeaseField::resolve(&fields, &values(&[("build", "a")])),
+            None
+        );
+        assert_eq!(
+            LeaseField::resolve(&fields, &values(&[("build", "abc")])),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve_several() {
+        let fields = [
+            field(1, "ticket", true, None),
+            field(2, "build", false, Some("[0-9]+")),
+        ];
+
+        let given = values(&[("build", "7"), ("ticket", "T-1")]);
+        let resolved = LeaseField::resolve(&fields, &given);
+
+        assert_eq!(resolved, Some(vec![value(1, "T-1"), value(2, "7")]));
+    }
+
+    #[test]
+    fn anchored_rejects_escapes() {
+        assert!(anchored("a)|(b").is_err());
+        assert!(anchored("(a").is_err());
+        assert!(anchored("a|b").is_ok());
+    }
+
+    #[test]
+    fn is_valid_pattern() {
+        let create = |pattern: Option<&str>| {
+            CreateLeaseField::builder()
+                .asset_type_id(1)
+                .name("build".to_owned())
+                .pattern(pattern.map(str::to_owned))
+                .build()
+        };
+
+        assert!(create(None).is_valid());
+        assert!(create(Some("[0-9]+")).is_valid());
+        assert!(!create(Some("[0-9")).is_valid());
+        assert!(!create(Some("a)|(b")).is_valid());
+    }
+}
@@ -0,0 +1,520 @@
+//! A `LeaseQuota` limits how much of an `AssetType` one user can hold.
+//!
+//! Each asset type can have a default quota, which applies to every user, and
+//! quotas for particular users, which replace the default for them.
+//!
+//! Bellhop has no notion of groups of users, so there are no quotas shared by
+//! a group, or applying to everyone in one. A user who should get more or less
+//! than the default needs a quota of their own.
+
+use crate::errors::*;
+use crate::schema::lease_quotas;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use diesel::prelude::*;
+
+use std::fmt;
+
+use super::asset_type::AssetType;
+use super::lease::Lease;
+
+#[derive(Debug, Clone, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[belongs_to(AssetType)]
+pub struct LeaseQuota {
+    id: i32,
+    asset_type_id: i32,
+    user_id: Option<i32>,
+
+    max_leases: Option<i32>,
+    max_hours_per_week: Option<i32>,
+    allow_infinite: bool,
+}
+
+impl LeaseQuota {
+    /// Every quota for the `AssetType` identified by `type_id`, default
+    /// first.
+    pub(crate) fn for_type(c: &PgConnection, type_id: i32) -> Result<Vec<LeaseQuota>> {
+        use self::lease_quotas::dsl::*;
+
+        let mut quotas: Vec<LeaseQuota> = lease_quotas
+            .filter(asset_type_id.eq(type_id))
+            .order(id.asc())
+            .load(c)
+            .chain_err(|| "unable to get lease quotas for asset type")?;
+
+        quotas.sort_by_key(|x| x.user_id.is_some());
+
+        Ok(quotas)
+    }
+
+    /// The quota that `for_user_id` has to stay within when leasing assets of
+    /// the `AssetType` identified by `type_id`, if there is one.
+    pub(crate) fn applying_to(
+        c: &PgConnection,
+        type_id: i32,
+        for_user_id: i32,
+    ) -> Result<Option<LeaseQuota>> {
+        use self::lease_quotas::dsl::*;
+
+        let quotas: Vec<LeaseQuota> = lease_quotas
+            .filter(asset_type_id.eq(type_id))
+            .filter(user_id.eq(for_user_id).or(user_id.is_null()))
+            .load(c)
+            .chain_err(|| "unable to get lease quotas for user")?;
+
+        let (own, default): (Vec<_>, Vec<_>) =
+            quotas.into_iter().partition(|x| x.user_id.is_some());
+
+        Ok(own.into_iter().chain(default).next())
+    }
+
+    /// Delete the quota for `for_user_id`, or the default quota if it's
+    /// `None`, from the `AssetType` identified by `type_id`.
+    ///
+    /// Returns `false` if there wasn't one.
+    pub(crate) fn delete(c: &PgConnection, type_id: i32, for_user_id: Option<i32>) -> Result<bool> {
+        use self::lease_quotas::dsl::*;
+
+        let to_delete = lease_quotas.filter(asset_type_id.eq(type_id));
+
+        let count = match for_user_id {
+            Some(x) => diesel::delete(to_delete.filter(user_id.eq(x))).execute(c),
+            None => diesel::delete(to_delete.filter(user_id.is_null())).execute(c),
+        }
+        .chain_err(|| "unable to delete lease quota")?;
+
+        Ok(count == 1)
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn asset_type_id(&self) -> i32 {
+        self.asset_type_id
+    }
+
+    /// The user this quota applies to, or `None` for the default quota.
+    pub fn user_id(&self) -> Option<i32> {
+        self.user_id
+    }
+
+    /// How many leases of the asset type the user can hold at the same time.
+    pub fn max_leases(&self) -> Option<i32> {
+        self.max_leases
+    }
+
+    /// How many hours the user's leases of the asset type can add up to in
+    /// any one week, starting on Monday at midnight UTC.
+    pub fn max_hours_per_week(&self) -> Option<i32> {
+        self.max_hours_per_week
+    }
+
+    /// Whether the user can create leases that never end.
+    pub fn allow_infinite(&self) -> bool {
+        self.allow_infinite
+    }
+
+    /// Find out if `lease`, which has already been inserted, breaks this
+    /// quota.
+    pub(crate) fn check(&self, c: &PgConnection, lease: &Lease) -> Result<Option<Exceeded>> {
+        let asset_type_id = self.asset_type_id;
+
+        if lease.end_time().is_none() {
+            if !self.allow_infinite {
+                return Ok(Some(Exceeded::Infinite { asset_type_id }));
+            }
+
+            // A lease that never ends uses up every week after it starts.
+            if let Some(limit) = self.max_hours_per_week {
+                return Ok(Some(Exceeded::MaxHoursPerWeek {
+                    asset_type_id,
+                    limit,
+                }));
+            }
+        }
+
+        let held = held(c, lease.user_id(), asset_type_id)?;
+
+        if let Some(limit) = self.max_leases {
+            let mut concurrent: Vec<i32> = held
+                .iter()
+                .filter(|x| overlaps(x, lease.start_time(), lease.end_time()))
+                .map(|x| x.lease_id)
+                .collect();
+
+            concurrent.sort();
+            concurrent.dedup();
+
+            if concurrent.len() > limit as usize {
+                return Ok(Some(Exceeded::MaxLeases {
+                    asset_type_id,
+                    limit,
+                }));
+            }
+        }
+
+        if let (Some(limit), Some(end_time)) = (self.max_hours_per_week, lease.end_time()) {
+            let mut week = week_of(lease.start_time());
+
+            let mut spans = held;
+            spans.extend(ended(c, lease.user_id(), asset_type_id, week)?);
+            spans.sort();
+            spans.dedup();
+
+            while week < end_time {
+                let next = week + Duration::weeks(1);
+
+                let used: i64 = spans.iter().map(|x| x.seconds_within(week, next)).sum();
+
+                if used > i64::from(limit) * 60 * 60 {
+                    return Ok(Some(Exceeded::MaxHoursPerWeek {
+                        asset_type_id,
+                        limit,
+                    }));
+                }
+
+                week = next;
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Make sure `lease` stays within its owner's quota for each `AssetType`
+/// identified by `type_ids`.
+///
+/// Meant to be called right after `lease` is inserted, extended, or handed to
+/// a new owner, in the same transaction, so the lease counts against the
+/// quota. If a quota is exceeded the error has `ErrorKind::QuotaExceeded`.
+///
+/// Each quota stays locked until the transaction ends, so concurrent leases
+/// by the same user are counted one after the other, instead of each fitting
+/// under the limit without the other.
+pub(crate) fn enforce(c: &PgConnection, lease: &Lease, type_ids: &[i32]) -> Result<()> {
+    // Locks are always taken in the same order, so transactions can't
+    // deadlock on them.
+    let mut type_ids = type_ids.to_vec();
+    type_ids.sort();
+    type_ids.dedup();
+
+    for type_id in type_ids {
+        let quota = match LeaseQuota::applying_to(c, type_id, lease.user_id())? {
+            Some(x) => x,
+            None => continue,
+        };
+
+        lock(c, lease.user_id(), type_id)?;
+
+        if let Some(exceeded) = quota.check(c, lease)? {
+            return Err(ErrorKind::QuotaExceeded(exceeded).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Hold a lock on `user_id`'s leases of the `AssetType` identified by
+/// `type_id` until the current transaction ends.
+fn lock(c: &PgConnection, user_id: i32, type_id: i32) -> Result<()> {
+    // `pg_advisory_xact_lock` returns void, which diesel can't select.
+    let query = format!("SELECT pg_advisory_xact_lock({}, {})", user_id, type_id);
+
+    c.execute(&query)
+        .chain_err(|| "unable to lock lease quota")?;
+
+    Ok(())
+}
+
+/// A period of time that one of a user's leases held an asset.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Span {
+    lease_id: i32,
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+}
+
+impl Span {
+    /// How many seconds of this span fall between `from` and `to`.
+    fn seconds_within(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+        let start = self.start_time.max(from);
+        let end = self.end_time.unwrap_or(to).min(to);
+
+        if end > start {
+            (end - start).num_seconds()
+        } else {
+            0
+        }
+    }
+}
+
+fn overlaps(span: &Span, start_time: DateTime<Utc>, end_time: Option<DateTime<Utc>>) -> bool {
+    let starts_before_end = match end_time {
+        Some(x) => span.start_time < x,
+        None => true,
+    };
+
+    let ends_after_start = match span.end_time {
+        Some(x) => x > start_time,
+        None => true,
+    };
+
+    starts_before_end && ends_after_start
+}
+
+/// Midnight UTC on the Monday of the week that `time` falls in.
+fn week_of(time: DateTime<Utc>) -> DateTime<Utc> {
+    let date = time.date();
+    let monday = date - Duration::days(date.weekday().num_days_from_monday().into());
+
+    monday.and_hms(0, 0, 0)
+}
+
+/// Every current and upcoming lease that `user_id` owns on assets of the
+/// `AssetType` identified by `type_id`.
+fn held(c: &PgConnection, user_id: i32, type_id: i32) -> Result<Vec<Span>> {
+    use crate::schema::assets::dsl as a;
+    use crate::schema::leases::dsl as l;
+    use crate::schema::reservations::dsl as r;
+
+    let rows: Vec<(i32, DateTime<Utc>, Option<DateTime<Utc>>)> = r::reservations
+        .inner_join(l::leases)
+        .inner_join(a::assets)
+        .filter(l::user_id.eq(user_id))
+        .filter(a::type_id.eq(type_id))
+        .select((r::lease_id, r::start_time, r::end_time))
+        .load(c)
+        .chain_err(|| "unable to get reservations for quota")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(lease_id, start_time, end_time)| Span {
+            lease_id,
+            start_time,
+            end_time,
+        })
+        .collect())
+}
+
+/// Leases that `user_id` owned on assets of the `AssetType` identified by
+/// `type_id`, which ended after `since`.
+fn ended(c: &PgConnection, user_id: i32, type_id: i32, since: DateTime<Utc>) -> Result<Vec<Span>> {
+    use crate::schema::assets::dsl as a;
+    use crate::schema::ended_leases::dsl as el;
+
+    let rows: Vec<(i32, DateTime<Utc>, Option<DateTime<Utc>>, DateTime<Utc>)> = el::ended_leases
+        .inner_join(a::assets)
+        .filter(el::user_id.eq(user_id))
+        .filter(a::type_id.eq(type_id))
+        .filter(el::ended_at.gt(since))
+        .select((el::lease_id, el::start_time, el::end_time, el::ended_at))
+        .load(c)
+        .chain_err(|| "unable to get ended leases for quota")?;
+
+    // Leases that were cut short only count until they actually ended.
+    Ok(rows
+        .into_iter()
+        .map(|(lease_id, start_time, end_time, ended_at)| Span {
+            lease_id,
+            start_time,
+            end_time: Some(end_time.map_or(ended_at, |x| x.min(ended_at))),
+        })
+        .collect())
+}
+
+/// Which quota a new lease would break.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "quota", rename_all = "snake_case")]
+pub enum Exceeded {
+    /// The user would hold more than `limit` leases of the asset type at the
+    /// same time.
+    MaxLeases {
+        /// The asset type the quota belongs to.
+        asset_type_id: i32,
+
+        /// The most leases allowed.
+        limit: i32,
+    },
+
+    /// The user's leases of the asset type would add up to more than `limit`
+    /// hours in some week.
+    MaxHoursPerWeek {
+        /// The asset type the quota belongs to.
+        asset_type_id: i32,
+
+        /// The most hours allowed.
+        limit: i32,
+    },
+
+    /// The lease never ends, and the user isn't allowed infinite leases of
+    /// the asset type.
+    Infinite {
+        /// The asset type the quota belongs to.
+        asset_type_id: i32,
+    },
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Exceeded::MaxLeases { limit, .. } => write!(
+                f,
+                "You can't hold more than {} leases of this asset type at the same time.",
+                limit
+            ),
+            Exceeded::MaxHoursPerWeek { limit, .. } => write!(
+                f,
+                "You can't hold assets of this type for more than {} hours a week.",
+                limit
+            ),
+            Exceeded::Infinite { .. } => {
+                write!(f, "Leases of this asset type need an end time.")
+            }
+        }
+    }
+}
+
+/// The insertable companion of `LeaseQuota`.
+#[derive(Debug, Insertable)]
+#[table_name = "lease_quotas"]
+pub struct CreateLeaseQuota {
+    asset_type_id: i32,
+    user_id: Option<i32>,
+
+    max_leases: Option<i32>,
+    max_hours_per_week: Option<i32>,
+    allow_infinite: bool,
+}
+
+impl CreateLeaseQuota {
+    /// Returns `false` if a limit isn't positive.
+    pub fn is_valid(&self) -> bool {
+        let positive = |x: Option<i32>| x.map_or(true, |x| x > 0);
+
+        positive(self.max_leases) && positive(self.max_hours_per_week)
+    }
+
+    /// Insert the `LeaseQuota` into the database, replacing any quota for the
+    /// same asset type and user.
+    pub fn insert(&self, c: &PgConnection) -> Result<LeaseQuota> {
+        use self::lease_quotas::dsl::*;
+
+        c.transaction::<_, Error, _>(|| {
+            LeaseQuota::delete(c, self.asset_type_id, self.user_id)?;
+
+            diesel::insert_into(lease_quotas)
+                .values(self)
+                .get_result(c)
+                .chain_err(|| "unable to insert lease quota")
+        })
+    }
+}
+
+fn default_allow_infinite() -> bool {
+    true
+}
+
+/// Similar to `CreateLeaseQuota`, but doesn't include `asset_type_id` or
+/// `user_id`.
+#[derive(Debug, Deserialize)]
+pub struct CreateOwnedLeaseQuota {
+    #[serde(default)]
+    max_leases: Option<i32>,
+
+    #[serde(default)]
+    max_hours_per_week: Option<i32>,
+
+    #[serde(default = "default_allow_infinite")]
+    allow_infinite: bool,
+}
+
+impl CreateOwnedLeaseQuota {
+    pub fn into_create_lease_quota(
+        self,
+        asset_type_id: i32,
+        user_id: Option<i32>,
+    ) -> CreateLeaseQuota {
+        CreateLeaseQuota {
+            asset_type_id,
+            user_id,
+            max_leases: self.max_leases,
+            max_hours_per_week: self.max_hours_per_week,
+            allow_infinite: self.allow_infinite,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        // 2019-09-02 was a Monday.
+        Utc.ymd(2019, 9, day).and_hms(hour, 0, 0)
+    }
+
+    fn span(start_time: DateTime<Utc>, end_time: Option<DateTime<Utc>>) -> Span {
+        Span {
+            lease_id: 1,
+            start_time,
+            end_time,
+        }
+    }
+
+    #[test]
+    fn week_of_starts_on_monday() {
+        assert_eq!(week_of(at(2, 0)), at(2, 0));
+        assert_eq!(week_of(at(4, 13)), at(2, 0));
+        assert_eq!(week_of(at(8, 23)), at(2, 0));
+        assert_eq!(week_of(at(9, 0)), at(9, 0));
+    }
+
+    #[test]
+    fn seconds_within_clips_to_range() {
+        let hour = 60 * 60;
+        let x = span(at(3, 10), Some(at(3, 14)));
+
+        assert_eq!(x.seconds_within(at(2, 0), at(9, 0)), 4 * hour);
+        assert_eq!(x.seconds_within(at(3, 12), at(9, 0)), 2 * hour);
+        assert_eq!(x.seconds_within(at(2, 0), at(3, 11)), hour);
+        assert_eq!(x.seconds_within(at(4, 0), at(9, 0)), 0);
+        assert_eq!(x.seconds_within(at(2, 0), at(3, 10)), 0);
+    }
+
+    #[test]
+    fn seconds_within_infinite() {
+        let x = span(at(3, 0), None);
+
+        assert_eq!(x.seconds_within(at(2, 0), at(4, 0)), 24 * 60 * 60);
+        assert_eq!(x.seconds_within(at(9, 0), at(10, 0)), 24 * 60 * 60);
+    }
+
+    #[test]
+    fn overlaps_finite() {
+        let x = span(at(3, 10), Some(at(3, 14)));
+
+        assert!(overlaps(&x, at(3, 12), Some(at(3, 16))));
+        assert!(overlaps(&x, at(3, 8), Some(at(3, 11))));
+        assert!(overlaps(&x, at(3, 11), Some(at(3, 12))));
+
+        // Touching ends don't overlap.
+        assert!(!overlaps(&x, at(3, 14), Some(at(3, 16))));
+        assert!(!overlaps(&x, at(3, 8), Some(at(3, 10))));
+    }
+
+    #[test]
+    fn overlaps_infinite() {
+        let x = span(at(3, 10), None);
+
+        assert!(overlaps(&x, at(9, 0), Some(at(9, 1))));
+        assert!(!overlaps(&x, at(3, 8), Some(at(3, 10))));
+
+        let y = span(at(3, 10), Some(at(3, 14)));
+
+        assert!(overlaps(&y, at(3, 0), None));
+        assert!(!overlaps(&y, at(3, 14), None));
+    }
+}
@@ -0,0 +1,350 @@
+//! A `LeaseRequest` is a `Lease` on an `Asset` whose `AssetType` requires
+//! approval, waiting for one of the type's approvers to decide on it.
+
+use crate::errors::*;
+use crate::schema::{lease_request_fields, lease_requests};
+
+use super::asset::Asset;
+use super::lease::CreateLease;
+use super::user::User;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// How long a request waits for a decision before it expires.
+const TIME_TO_LIVE_HOURS: i64 = 72;
+
+/// Where a `LeaseRequest` is in the approval process.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "SmallInt"]
+#[repr(i16)]
+pub enum RequestStatus {
+    /// Waiting for an approver.
+    Pending = 0,
+
+    /// An approver approved the request, and the lease was created.
+    Approved = 1,
+
+    /// An approver turned the request down.
+    Denied = 2,
+
+    /// Nobody decided on the request in time.
+    Expired = 3,
+}
+
+impl ToSql<SmallInt, Pg> for RequestStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<SmallInt, Pg>::to_sql(&(*self as i16), out)
+    }
+}
+
+impl FromSql<SmallInt, Pg> for RequestStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(RequestStatus::Pending),
+            1 => Ok(RequestStatus::Approved),
+            2 => Ok(RequestStatus::Denied),
+            3 => Ok(RequestStatus::Expired),
+            x => Err(format!("unknown request status: {}", x).into()),
+        }
+    }
+}
+
+/// A request by a `User` for a `Lease` on an `Asset`.
+#[derive(
+    Debug, Clone, Associations, Serialize, Deserialize, Queryable, Identifiable, PartialEq, Eq,
+)]
+#[belongs_to(Asset)]
+pub struct LeaseRequest {
+    id: i32,
+    asset_id: i32,
+    user_id: i32,
+
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+    purpose: Option<String>,
+    heartbeat_secs: Option<i32>,
+
+    status: RequestStatus,
+    requested_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+
+    decided_by: Option<i32>,
+    decided_at: Option<DateTime<Utc>>,
+    decision_reason: Option<String>,
+
+    lease_id: Option<i32>,
+}
+
+impl LeaseRequest {
+    pub(crate) fn by_id(c: &PgConnection, by_id: i32) -> Result<Option<LeaseRequest>> {
+        use self::lease_requests::dsl::*;
+
+        lease_requests
+            .filter(id.eq(by_id))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to find lease request by id")
+    }
+
+    /// Like `by_id`, but locks the request until the end of the transaction,
+    /// so it can only be decided on once.
+    pub(crate) fn lock(c: &PgConnection, by_id: i32) -> Result<Option<LeaseRequest>> {
+        use self::lease_requests::dsl::*;
+
+        lease_requests
+            .for_update()
+            .filter(id.eq(by_id))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to lock lease request")
+    }
+
+    /// Every pending request, with the asset it's for and who asked, oldest
+    /// first.
+    pub(crate) fn pending(c: &PgConnection) -> Result<Vec<(LeaseRequest, Asset, User)>> {
+        use self::lease_requests::dsl as lr;
+        use crate::schema::assets::dsl as a;
+        use crate::schema::users::dsl as u;
+
+        lr::lease_requests
+            .inner_join(a::assets)
+            .inner_join(u::users.on(u::id.eq(lr::user_id)))
+            .filter(lr::status.eq(RequestStatus::Pending))
+            .order(lr::requested_at.asc())
+            .load(c)
+            .chain_err(|| "unable to get pending lease requests")
+    }
+
+    /// Pending requests that nobody decided on in time.
+    pub(crate) fn expired(c: &PgConnection) -> Result<Vec<LeaseRequest>> {
+        use self::lease_requests::dsl::*;
+
+        lease_requests
+            .filter(status.eq(RequestStatus::Pending))
+            .filter(expires_at.le(Utc::now()))
+            .load(c)
+            .chain_err(|| "unable to get expired lease requests")
+    }
+
+    /// The primary key of this `LeaseRequest`.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The primary key of the requested `Asset`.
+    pub fn asset_id(&self) -> i32 {
+        self.asset_id
+    }
+
+    /// The primary key of the `User` who asked for the lease.
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// When the requested `Lease` would start. Requests approved after this
+    /// start as soon as they're approved.
+    pub fn start_time(&self) -> DateTime<Utc> {
+        self.start_time
+    }
+
+    /// When the requested `Lease` would end.
+    pub fn end_time(&self) -> Option<DateTime<Utc>> {
+        self.end_time
+    }
+
+    /// Why the lease is wanted.
+    pub fn purpose(&self) -> Option<&str> {
+        self.purpose.as_ref().map(String::as_str)
+    }
+
+    /// How often the requested `Lease` would need a heartbeat, in seconds.
+    pub fn heartbeat_secs(&self) -> Option<i32> {
+        self.heartbeat_secs
+    }
+
+    /// Where this request is in the approval process.
+    pub fn status(&self) -> RequestStatus {
+        self.status
+    }
+
+    /// When the request was made.
+    pub fn requested_at(&self) -> DateTime<Utc> {
+        self.requested_at
+    }
+
+    /// When the request expires, if it's still pending.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    /// The primary key of the `User` who approved or denied the request.
+    pub fn decided_by(&self) -> Option<i32> {
+        self.decided_by
+    }
+
+    /// Why the request was denied, if the approver said.
+    pub fn decision_reason(&self) -> Option<&str> {
+        self.decision_reason.as_ref().map(String::as_str)
+    }
+
+    /// The primary key of the `Lease` created when the request was approved,
+    /// as long as that lease hasn't ended.
+    pub fn lease_id(&self) -> Option<i32> {
+        self.lease_id
+    }
+
+    /// The custom field values given with the request, by field name.
+    pub(crate) fn fields(&self, c: &PgConnection) -> Result<BTreeMap<String, String>> {
+        use self::lease_request_fields::dsl::*;
+
+        let found: Vec<(String, String)> = lease_request_fields
+            .filter(lease_request_id.eq(self.id))
+            .select((name, value))
+            .load(c)
+            .chain_err(|| "unable to get fields for lease request")?;
+
+        Ok(found.into_iter().collect())
+    }
+
+    /// The lease to create if the request is approved.
+    pub(crate) fn to_create_lease(&self) -> CreateLease {
+        CreateLease::builder()
+            .user_id(self.user_id)
+            .start_time(self.start_time.max(Utc::now()))
+            .end_time(self.end_time)
+            .purpose(self.purpose.clone())
+            .heartbeat_secs(self.heartbeat_secs)
+            .build()
+    }
+
+    /// Move a pending request to `status`.
+    ///
+    /// Returns `None` if the request isn't pending anymore.
+    pub(crate) fn decide(
+        &self,
+        c: &PgConnection,
+        new_status: RequestStatus,
+        by: Option<i32>,
+        reason: Option<&str>,
+        new_lease_id: Option<i32>,
+    ) -> Result<Option<LeaseRequest>> {
+        use self::lease_requests::dsl::*;
+
+        let to_update = lease_requests
+            .filter(id.eq(self.id))
+            .filter(status.eq(RequestStatus::Pending));
+
+        diesel::update(to_update)
+            .set((
+                status.eq(new_status),
+                decided_by.eq(by),
+                decided_at.eq(Some(Utc::now())),
+                decision_reason.eq(reason),
+                lease_id.eq(new_lease_id),
+            ))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "unable to update lease request")
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "lease_requests"]
+struct CreateLeaseRequest<'a> {
+    asset_id: i32,
+    user_id: i32,
+
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+    purpose: Option<&'a str>,
+    heartbeat_secs: Option<i32>,
+
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "lease_request_fields"]
+struct CreateLeaseRequestField<'a> {
+    lease_request_id: i32,
+    name: &'a str,
+    value: &'a str,
+}
+
+/// Insert a request for `create` on the `Asset` identified by `asset_id`,
+/// along with the custom field `values` given for it.
+///
+/// The request expires after a few days, or when the lease would have ended,
+/// whichever comes first.
+pub(crate) fn insert(
+    c: &PgConnection,
+    create: &CreateLease,
+    asset_id: i32,
+    values: &BTreeMap<String, String>,
+) -> Result<LeaseRequest> {
+    let mut expires_at = Utc::now() + Duration::hours(TIME_TO_LIVE_HOURS);
+    if let Some(x) = create.end_time() {
+        expires_at = expires_at.min(x);
+    }
+
+    let request = CreateLeaseRequest {
+        asset_id,
+        user_id: create.user_id(),
+        start_time: create.start_time(),
+        end_time: create.end_time(),
+        purpose: create.purpose(),
+        heartbeat_secs: create.heartbeat_secs(),
+        expires_at,
+    };
+
+    c.transaction::<_, Error, _>(|| {
+        let request: LeaseRequest = diesel::insert_into(lease_requests::table)
+            .values(&request)
+            .get_result(c)
+            .chain_err(|| "unable to insert lease request")?;
+
+        let fields: Vec<_> = values
+            .iter()
+            .filter(|(_, v)| !v.trim().is_empty())
+            .map(|(k, v)| CreateLeaseRequestField {
+                lease_request_id: request.id(),
+                name: k,
+                value: v,
+            })
+            .collect();
+
+        diesel::insert_into(lease_request_fields::table)
+            .values(&fields)
+            .execute(c)
+            .chain_err(|| "unable to insert lease request fields")?;
+
+        Ok(request)
+    })
+}
+
+/// Request to deny a `LeaseRequest`.
+#[derive(Debug, Deserialize, FromForm)]
+pub(crate) struct DenyRequestForm {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+impl DenyRequestForm {
+    /// The reason, trimmed, or `None` if it's blank.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason
+            .as_ref()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+    }
+}
@@ -0,0 +1,96 @@
+//! A `LeaseTransfer` is an offer to hand a `Lease` to another `User`.
+
+use chrono::prelude::*;
+
+use crate::errors::*;
+use crate::schema::lease_transfers;
+
+use super::lease::Lease;
+
+use diesel::prelude::*;
+
+/// A transfer of a `Lease` that is waiting for its recipient to accept it.
+#[derive(Debug, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(lease_id)]
+#[belongs_to(Lease)]
+pub struct LeaseTransfer {
+    lease_id: i32,
+    from_user_id: i32,
+    to_user_id: i32,
+
+    created_at: DateTime<Utc>,
+}
+
+impl LeaseTransfer {
+    /// The pending transfer of the `Lease` identified by `lease_id`, if any.
+    pub fn by_lease(c: &PgConnection, by_lease_id: i32) -> Result<Option<LeaseTransfer>> {
+        use self::lease_transfers::dsl::*;
+
+        lease_transfers
+            .filter(lease_id.eq(by_lease_id))
+            .first(c)
+            .optional()
+            .chain_err(|| "unable to get lease transfer")
+    }
+
+    pub fn lease_id(&self) -> i32 {
+        self.lease_id
+    }
+
+    pub fn from_user_id(&self) -> i32 {
+        self.from_user_id
+    }
+
+    pub fn to_user_id(&self) -> i32 {
+        self.to_user_id
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    /// Delete the `LeaseTransfer` from the database.
+    ///
+    /// Returns `false` if it had already been accepted or withdrawn.
+    pub fn delete(&self, c: &PgConnection) -> Result<bool> {
+        let count = diesel::delete(self)
+            .execute(c)
+            .chain_err(|| "unable to delete lease transfer")?;
+
+        Ok(count == 1)
+    }
+}
+
+/// The insertable companion of `LeaseTransfer`.
+#[derive(Debug, Insertable)]
+#[table_name = "lease_transfers"]
+pub struct CreateLeaseTransfer {
+    lease_id: i32,
+    from_user_id: i32,
+    to_user_id: i32,
+}
+
+impl CreateLeaseTransfer {
+    pub fn new(lease: &Lease, to_user_id: i32) -> Self {
+        CreateLeaseTransfer {
+            to_user_id,
+            lease_id: lease.id(),
+            from_user_id: lease.user_id(),
+        }
+    }
+
+    /// Insert the `CreateLeaseTransfer` into the database, replacing any
+    /// transfer of the same lease that is still pending.
+    pub fn insert(&self, c: &PgConnection) -> Result<LeaseTransfer> {
+        use self::lease_transfers::dsl::*;
+
+        c.transaction::<_, Error, _>(|| {
+            diesel::delete(lease_transfers.filter(lease_id.eq(self.lease_id))).execute(c)?;
+
+            diesel::insert_into(lease_transfers)
+                .values(self)
+                .get_result(c)
+                .chain_err(|| "unable to insert lease transfer")
+        })
+    }
+}
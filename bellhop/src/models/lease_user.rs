@@ -0,0 +1,51 @@
+//! A `LeaseUser` shares a `Lease` with its owner.
+
+use crate::errors::*;
+use crate::schema::lease_users;
+
+use super::lease::Lease;
+use super::user::User;
+
+use diesel::prelude::*;
+
+/// A co-owner of a `Lease`, who can do anything with it that its owner can.
+#[derive(Debug, Associations, Insertable, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(lease_id, user_id)]
+#[belongs_to(Lease)]
+#[belongs_to(User)]
+pub struct LeaseUser {
+    lease_id: i32,
+    user_id: i32,
+}
+
+impl LeaseUser {
+    pub fn new(lease_id: i32, user_id: i32) -> Self {
+        LeaseUser { lease_id, user_id }
+    }
+
+    /// Insert the `LeaseUser` into the database.
+    ///
+    /// Returns `false` if the user was already a co-owner.
+    pub fn insert(&self, c: &PgConnection) -> Result<bool> {
+        use self::lease_users::dsl::*;
+
+        let count = diesel::insert_into(lease_users)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(c)
+            .chain_err(|| "unable to insert lease user")?;
+
+        Ok(count == 1)
+    }
+
+    /// Delete the `LeaseUser` from the database.
+    ///
+    /// Returns `false` if the user wasn't a co-owner.
+    pub fn delete(&self, c: &PgConnection) -> Result<bool> {
+        let count = diesel::delete(self)
+            .execute(c)
+            .chain_err(|| "unable to delete lease user")?;
+
+        Ok(count == 1)
+    }
+}
@@ -0,0 +1,72 @@
+//! A `LeaseWarning` records that the owners of a `Lease` were warned that it
+//! is about to end.
+
+use crate::errors::*;
+use crate::schema::lease_warnings;
+
+use super::lease::Lease;
+
+use chrono::prelude::*;
+
+use diesel::prelude::*;
+
+/// A warning stage that was sent for a `Lease`.
+#[derive(Debug, Associations, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(lease_id, before_secs)]
+#[belongs_to(Lease)]
+pub struct LeaseWarning {
+    lease_id: i32,
+    before_secs: i32,
+    warned_at: DateTime<Utc>,
+}
+
+impl LeaseWarning {
+    /// How long before the end of the lease this warning was due, in
+    /// seconds.
+    pub fn before_secs(&self) -> i32 {
+        self.before_secs
+    }
+
+    /// Returns `true` if this warning makes the stage due `before_secs`
+    /// before the end of the lease unnecessary, because it was due at the
+    /// same time or later.
+    pub fn covers(&self, before_secs: i64) -> bool {
+        i64::from(self.before_secs) <= before_secs
+    }
+
+    /// Record that the stage due `before_secs` before the end of the `Lease`
+    /// identified by `lease_id` was sent.
+    pub fn insert(c: &PgConnection, lease_id: i32, before_secs: i32) -> Result<()> {
+        let create = CreateLeaseWarning {
+            lease_id,
+            before_secs,
+        };
+
+        diesel::insert_into(lease_warnings::table)
+            .values(&create)
+            .on_conflict_do_nothing()
+            .execute(c)
+            .chain_err(|| "unable to insert lease warning")?;
+
+        Ok(())
+    }
+
+    /// Forget the warnings sent for the `Lease` identified by `lease_id`, so
+    /// they're sent again for its new end time.
+    pub fn clear(c: &PgConnection, by_lease_id: i32) -> Result<()> {
+        use self::lease_warnings::dsl::*;
+
+        diesel::delete(lease_warnings.filter(lease_id.eq(by_lease_id)))
+            .execute(c)
+            .chain_err(|| "unable to delete lease warnings")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "lease_warnings"]
+struct CreateLeaseWarning {
+    lease_id: i32,
+    before_secs: i32,
+}
@@ -1,9 +1,24 @@
 //! Rust representations of Bellhop's database models.
 
+pub(crate) mod approver;
 pub mod asset;
 pub mod asset_type;
+pub mod ended_lease;
+pub(crate) mod feed_token;
+pub(crate) mod hook_delivery;
 pub mod lease;
+pub(crate) mod lease_field;
+pub(crate) mod lease_quota;
+pub mod lease_request;
+pub(crate) mod lease_transfer;
+pub(crate) mod lease_user;
+pub(crate) mod lease_warning;
+pub(crate) mod probe;
+pub(crate) mod prober;
+pub(crate) mod recurring_reservation;
+pub(crate) mod reservation;
 pub(crate) mod sheriff;
 pub(crate) mod tag;
 pub(crate) mod tag_type;
 pub mod user;
+pub(crate) mod waitlist;
@@ -0,0 +1,546 @@
+//! A `Probe` is a health check that the prober runs against every `Asset` of
+//! an `AssetType`, and a `ProbeResult` is how it went last time.
+
+use crate::db::Db as PubDb;
+use crate::errors::*;
+use crate::schema::{probe_results, probes};
+
+use chrono::prelude::*;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
+use super::asset::Asset;
+use super::asset_type::AssetType;
+
+/// How a `Probe` checks on an asset.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "SmallInt"]
+#[repr(i16)]
+pub enum ProbeKind {
+    /// Open a TCP connection to the `host:port` in the target.
+    Tcp = 0,
+
+    /// Send a GET request to the URL in the target, and expect a 2xx.
+    Http = 1,
+
+    /// Run the command the target names, from the `probe_commands` table in
+    /// `Rocket.toml`, on the Bellhop server, and expect it to exit
+    /// successfully. No shell is involved.
+    Command = 2,
+}
+
+impl ToSql<SmallInt, Pg> for ProbeKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<SmallInt, Pg>::to_sql(&(*self as i16), out)
+    }
+}
+
+impl FromSql<SmallInt, Pg> for ProbeKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(ProbeKind::Tcp),
+            1 => Ok(ProbeKind::Http),
+            2 => Ok(ProbeKind::Command),
+            x => Err(format!("unknown probe kind: {}", x).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[belongs_to(AssetType)]
+pub struct Probe {
+    id: i32,
+    asset_type_id: i32,
+
+    name: String,
+    kind: ProbeKind,
+    target: String,
+    timeout_ms: i32,
+
+    auto_maintenance: bool,
+}
+
+impl Probe {
+    /// Every probe of every asset type.
+    pub(crate) fn all(c: &PgConnection) -> Result<Vec<Probe>> {
+        use self::probes::dsl::*;
+
+        probes
+            .order(id.asc())
+            .load(c)
+            .chain_err(|| "unable to get probes")
+    }
+
+    /// The probes run against assets of the `AssetType` identified by
+    /// `type_id`.
+    pub(crate) fn for_type(c: &PgConnection, type_id: i32) -> Result<Vec<Probe>> {
+        use self::probes::dsl::*;
+
+        probes
+            .filter(asset_type_id.eq(type_id))
+            .order(id.asc())
+            .load(c)
+            .chain_err(|| "unable to get probes for asset type")
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn asset_type_id(&self) -> i32 {
+        self.asset_type_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> ProbeKind {
+        self.kind
+    }
+
+    /// What to check, with `{name}` standing in for the value of the asset's
+    /// tag called `name`.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// How long to wait before giving up on an asset.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms as u64)
+    }
+
+    /// Whether assets that fail this probe are moved into maintenance.
+    pub fn auto_maintenance(&self) -> bool {
+        self.auto_maintenance
+    }
+
+    /// The target for an asset with `tags`, keyed by tag type name, or `None`
+    /// if the asset is missing a tag the target asks for.
+    pub(crate) fn target_for(&self, tags: &HashMap<String, String>) -> Option<String> {
+        fill(&self.target, tags)
+    }
+
+    /// The program and arguments of the command named by the target, with
+    /// tags filled into each argument separately, so tag values can't add
+    /// arguments. Returns `None` if there's no such command, or the asset is
+    /// missing a tag the command asks for.
+    pub(crate) fn command_for(
+        &self,
+        commands: &ProbeCommands,
+        tags: &HashMap<String, String>,
+    ) -> Option<Vec<String>> {
+        commands
+            .get(&self.target)?
+            .iter()
+            .map(|x| fill(x, tags))
+            .collect()
+    }
+}
+
+/// The commands that `Command` probes can run, keyed by name, from the
+/// `probe_commands` table in `Rocket.toml`:
+///
+/// ```toml
+/// [global.probe_commands]
+/// ping = ["ping", "-c", "1", "{hostname}"]
+/// ```
+///
+/// Probes only ever refer to a command by name, so anyone who can write
+/// probes can't run anything the operator didn't list.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProbeCommands(HashMap<String, Vec<String>>);
+
+impl ProbeCommands {
+    pub fn new(commands: HashMap<String, Vec<String>>) -> Self {
+        ProbeCommands(commands)
+    }
+
+    /// The program and arguments of the command called `name`, before any
+    /// tags are filled in.
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.0.get(name).map(Vec::as_slice)
+    }
+}
+
+/// Returns `false` if `template` has an unclosed `{`, or an empty `{}`.
+fn is_template(template: &str) -> bool {
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        match rest[open..].find('}') {
+            Some(1) | None => return false,
+            Some(x) => rest = &rest[open + x + 1..],
+        }
+    }
+
+    true
+}
+
+/// Replace every `{name}` in `template` with the value of `tags[name]`.
+fn fill(template: &str, tags: &HashMap<String, String>) -> Option<String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        let close = open + rest[open..].find('}')?;
+
+        filled.push_str(&rest[..open]);
+        filled.push_str(tags.get(&rest[open + 1..close])?);
+
+        rest = &rest[close + 1..];
+    }
+
+    filled.push_str(rest);
+
+    Some(filled)
+}
+
+/// The insertable companion of `Probe`.
+#[derive(Debug, Deserialize, Insertable, TypedBuilder)]
+#[table_name = "probes"]
+pub struct CreateProbe {
+    asset_type_id: i32,
+
+    name: String,
+    kind: ProbeKind,
+    target: String,
+
+    #[serde(default = "default_timeout_ms")]
+    #[builder(default = "default_timeout_ms()")]
+    timeout_ms: i32,
+
+    #[serde(default)]
+    #[builder(default)]
+    auto_maintenance: bool,
+}
+
+fn default_timeout_ms() -> i32 {
+    5000
+}
+
+impl CreateProbe {
+    /// The name of the `Probe` to be created.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `false` if the name or target is blank, the target has an
+    /// unclosed `{`, a command probe's target isn't one of `commands`, or the
+    /// timeout isn't positive.
+    pub(crate) fn is_valid(&self, commands: &ProbeCommands) -> bool {
+        if self.name.trim().is_empty() || self.target.trim().is_empty() {
+            return false;
+        }
+
+        let target_ok = match self.kind {
+            ProbeKind::Tcp | ProbeKind::Http => is_template(&self.target),
+            ProbeKind::Command => commands.get(&self.target).is_some(),
+        };
+
+        target_ok && self.timeout_ms > 0
+    }
+
+    /// Insert the `Probe` into the database and return it.
+    pub fn insert(&self, c: &PubDb) -> Result<Probe> {
+        use self::probes::dsl::*;
+
+        diesel::insert_into(probes)
+            .values(self)
+            .get_result(c.db())
+            .chain_err(|| "unable to insert probe")
+    }
+}
+
+/// Similar to `CreateProbe`, but doesn't include `asset_type_id`.
+#[derive(Debug, Deserialize)]
+pub struct CreateOwnedProbe {
+    name: String,
+    kind: ProbeKind,
+    target: String,
+
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: i32,
+
+    #[serde(default)]
+    auto_maintenance: bool,
+}
+
+impl CreateOwnedProbe {
+    pub fn into_create_probe(self, asset_type_id: i32) -> CreateProbe {
+        CreateProbe {
+            asset_type_id,
+            name: self.name,
+            kind: self.kind,
+            target: self.target,
+            timeout_ms: self.timeout_ms,
+            auto_maintenance: self.auto_maintenance,
+        }
+    }
+}
+
+/// The last time a `Probe` checked on an `Asset`.
+#[derive(Debug, Clone, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(probe_id, asset_id)]
+#[belongs_to(Probe)]
+#[belongs_to(Asset)]
+pub struct ProbeResult {
+    probe_id: i32,
+    asset_id: i32,
+
+    ok: bool,
+    latency_ms: Option<i32>,
+    message: Option<String>,
+    checked_at: DateTime<Utc>,
+}
+
+impl ProbeResult {
+    /// The latest results for the `Asset` identified by `by_asset_id`, and
+    /// the probes they came from.
+    pub(crate) fn for_asset(
+        c: &PgConnection,
+        by_asset_id: i32,
+    ) -> Result<Vec<(Probe, ProbeResult)>> {
+        use self::probe_results::dsl as pr;
+        use self::probes::dsl as p;
+
+        p::probes
+            .inner_join(pr::probe_results)
+            .filter(pr::asset_id.eq(by_asset_id))
+            .order(p::id.asc())
+            .load(c)
+            .chain_err(|| "unable to get probe results for asset")
+    }
+
+    /// The latest results for every asset of the `AssetType` identified by
+    /// `type_id`.
+    pub(crate) fn for_type(c: &PgConnection, type_id: i32) -> Result<Vec<ProbeResult>> {
+        use self::probe_results::dsl as pr;
+        use self::probes::dsl as p;
+
+        pr::probe_results
+            .inner_join(p::probes)
+            .filter(p::asset_type_id.eq(type_id))
+            .order((pr::asset_id.asc(), p::id.asc()))
+            .select(probe_results::all_columns)
+            .load(c)
+            .chain_err(|| "unable to get probe results for asset type")
+    }
+
+    pub fn probe_id(&self) -> i32 {
+        self.probe_id
+    }
+
+    pub fn asset_id(&self) -> i32 {
+        self.asset_id
+    }
+
+    /// Whether the asset passed.
+    pub fn ok(&self) -> bool {
+        self.ok
+    }
+
+    /// How long the check took, if it finished before the timeout.
+    pub fn latency_ms(&self) -> Option<i32> {
+        self.latency_ms
+    }
+
+    /// What went wrong, or other details about the check.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(String::as_str)
+    }
+
+    pub fn checked_at(&self) -> DateTime<Utc> {
+        self.checked_at
+    }
+}
+
+/// The insertable companion of `ProbeResult`.
+#[derive(Debug, Insertable, AsChangeset)]
+#[table_name = "probe_results"]
+#[changeset_options(treat_none_as_null = "true")]
+pub(crate) struct CreateProbeResult {
+    probe_id: i32,
+    asset_id: i32,
+
+    ok: bool,
+    latency_ms: Option<i32>,
+    message: Option<String>,
+    checked_at: DateTime<Utc>,
+}
+
+impl CreateProbeResult {
+    pub fn new(
+        probe: &Probe,
+        asset: &Asset,
+        ok: bool,
+        latency: Option<Duration>,
+        message: Option<String>,
+    ) -> Self {
+        CreateProbeResult {
+            ok,
+            message,
+            probe_id: probe.id(),
+            asset_id: asset.id(),
+            latency_ms: latency.map(|x| x.as_millis() as i32),
+            checked_at: Utc::now(),
+        }
+    }
+
+    /// Whether the asset passed.
+    pub fn ok(&self) -> bool {
+        self.ok
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(String::as_str)
+    }
+
+    /// Insert the `ProbeResult` into the database, replacing the previous
+    /// result for the same probe and asset.
+    pub fn upsert(&self, c: &PgConnection) -> Result<ProbeResult> {
+        use self::probe_results::dsl::*;
+
+        diesel::insert_into(probe_results)
+            .values(self)
+            .on_conflict((probe_id, asset_id))
+            .do_update()
+            .set(self)
+            .get_result(c)
+            .chain_err(|| "unable to record probe result")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> HashMap<String, String> {
+        let mut tags = HashMap::new();
+        tags.insert("hostname".to_owned(), "alpha.example.com".to_owned());
+        tags.insert("port".to_owned(), "22".to_owned());
+        tags.insert("label".to_owned(), "two words".to_owned());
+        tags
+    }
+
+    fn commands() -> ProbeCommands {
+        let mut commands = HashMap::new();
+        commands.insert(
+            "ping".to_owned(),
+            vec!["ping".into(), "-c".into(), "1".into(), "{hostname}".into()],
+        );
+        commands.insert(
+            "echo".to_owned(),
+            vec!["echo".into(), "label={label}".into()],
+        );
+        ProbeCommands::new(commands)
+    }
+
+    fn probe(kind: ProbeKind, target: &str) -> CreateProbe {
+        CreateProbe::builder()
+            .asset_type_id(1)
+            .name("check".to_owned())
+            .kind(kind)
+            .target(target.to_owned())
+            .build()
+    }
+
+    fn saved(kind: ProbeKind, target: &str) -> Probe {
+        Probe {
+            id: 1,
+            asset_type_id: 1,
+            name: "check".into(),
+            kind,
+            target: target.into(),
+            timeout_ms: 5000,
+            auto_maintenance: false,
+        }
+    }
+
+    #[test]
+    fn fill_replaces_tags() {
+        assert_eq!(
+            fill("{hostname}:{port}", &tags()).unwrap(),
+            "alpha.example.com:22"
+        );
+        assert_eq!(fill("no tags", &tags()).unwrap(), "no tags");
+        assert_eq!(fill("", &tags()).unwrap(), "");
+    }
+
+    #[test]
+    fn fill_missing_tag() {
+        assert_eq!(fill("{hostname}:{missing}", &tags()), None);
+    }
+
+    #[test]
+    fn fill_unclosed_brace() {
+        assert_eq!(fill("{hostname", &tags()), None);
+    }
+
+    #[test]
+    fn command_for_fills_each_argument() {
+        let argv = saved(ProbeKind::Command, "echo")
+            .command_for(&commands(), &tags())
+            .unwrap();
+
+        // A tag with whitespace stays a single argument.
+        assert_eq!(argv, vec!["echo", "label=two words"]);
+    }
+
+    #[test]
+    fn command_for_unknown_command() {
+        let probe = saved(ProbeKind::Command, "/bin/sh -c reboot");
+
+        assert_eq!(probe.command_for(&commands(), &tags()), None);
+    }
+
+    #[test]
+    fn command_for_missing_tag() {
+        let probe = saved(ProbeKind::Command, "ping");
+
+        assert_eq!(probe.command_for(&commands(), &HashMap::new()), None);
+    }
+
+    #[test]
+    fn is_valid_targets() {
+        let commands = commands();
+
+        assert!(probe(ProbeKind::Tcp, "{hostname}:22").is_valid(&commands));
+        assert!(probe(ProbeKind::Http, "http://{hostname}/").is_valid(&commands));
+        assert!(probe(ProbeKind::Command, "ping").is_valid(&commands));
+
+        assert!(!probe(ProbeKind::Tcp, "{hostname:22").is_valid(&commands));
+        assert!(!probe(ProbeKind::Tcp, "{}:22").is_valid(&commands));
+        assert!(!probe(ProbeKind::Http, "  ").is_valid(&commands));
+    }
+
+    #[test]
+    fn is_valid_only_allowed_commands() {
+        let commands = commands();
+
+        assert!(!probe(ProbeKind::Command, "/usr/bin/ping").is_valid(&commands));
+        assert!(!probe(ProbeKind::Command, "ping {hostname}").is_valid(&commands));
+        assert!(!probe(ProbeKind::Command, "ping").is_valid(&ProbeCommands::default()));
+    }
+
+    #[test]
+    fn is_valid_name_and_timeout() {
+        let commands = commands();
+
+        let mut blank = probe(ProbeKind::Tcp, "{hostname}:22");
+        blank.name = " ".into();
+        assert!(!blank.is_valid(&commands));
+
+        let mut timeout = probe(ProbeKind::Tcp, "{hostname}:22");
+        timeout.timeout_ms = 0;
+        assert!(!timeout.is_valid(&commands));
+    }
+}
@@ -0,0 +1,32 @@
+use chrono::prelude::*;
+
+use crate::errors::*;
+use crate::schema::prober;
+
+use diesel::prelude::*;
+
+use std::time::Duration;
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "prober"]
+pub struct Prober {
+    primary_key: bool,
+    last_checked: Option<DateTime<Utc>>,
+}
+
+impl Prober {
+    pub fn should_run(c: &PgConnection, period: Duration) -> Result<bool> {
+        use self::prober::dsl::*;
+
+        // https://github.com/diesel-rs/diesel/issues/1514
+        let fragment = format!("now() - interval '{} milliseconds'", period.as_millis());
+        let target = prober.filter(last_checked.lt(diesel::dsl::sql(&fragment)));
+
+        let count = diesel::update(target)
+            .set(last_checked.eq(diesel::dsl::now))
+            .execute(c)
+            .chain_err(|| "unable to update last_checked time for prober")?;
+
+        Ok(count == 1)
+    }
+}
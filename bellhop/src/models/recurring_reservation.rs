@@ -0,0 +1,851 @@
+//! A `RecurringReservation` is a schedule that the sheriff turns into
+//! upcoming `Lease`s, a little ahead of time.
+
+use crate::errors::*;
+use crate::schema::{recurring_leases, recurring_reservation_tags, recurring_reservations};
+
+use super::asset::{Asset, AssetStatus};
+use super::asset_type::AssetType;
+use super::lease::{CreateLease, Lease};
+use super::tag::{Tag, WantedTag};
+use super::tag_type::TagType;
+use super::user::User;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{Bool, Nullable, SmallInt, Text, Time, Timestamptz};
+
+use std::io::Write;
+
+/// How often a `RecurringReservation` comes around.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "SmallInt"]
+#[repr(i16)]
+pub enum Frequency {
+    /// Every day.
+    Daily = 0,
+
+    /// Monday to Friday.
+    Weekdays = 1,
+
+    /// Once a week, on `day_of_week`.
+    Weekly = 2,
+}
+
+impl Frequency {
+    /// The longest a single occurrence can last without running into the
+    /// next one.
+    fn max_duration_mins(self) -> i32 {
+        match self {
+            Frequency::Daily | Frequency::Weekdays => 24 * 60,
+            Frequency::Weekly => 7 * 24 * 60,
+        }
+    }
+}
+
+impl ToSql<SmallInt, Pg> for Frequency {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<SmallInt, Pg>::to_sql(&(*self as i16), out)
+    }
+}
+
+impl FromSql<SmallInt, Pg> for Frequency {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(Frequency::Daily),
+            1 => Ok(Frequency::Weekdays),
+            2 => Ok(Frequency::Weekly),
+            x => Err(format!("unknown frequency: {}", x).into()),
+        }
+    }
+}
+
+/// Start times of a rule's occurrences in `($3, $4]`, worked out in its own
+/// time zone so daylight saving time is handled by the database.
+const OCCURRENCES: &str = "
+    SELECT starts FROM (
+        SELECT
+            (day::date + $1) AT TIME ZONE $2 AS starts,
+            extract(isodow FROM day) AS dow
+        FROM
+            generate_series(
+                ($3 AT TIME ZONE $2)::date::timestamp,
+                ($4 AT TIME ZONE $2)::date::timestamp,
+                interval '1 day'
+            ) AS day
+    ) AS occurrences
+    WHERE
+        starts > $3
+        AND starts <= $4
+        AND (
+            $5 = 0
+            OR ($5 = 1 AND dow < 6)
+            OR ($5 = 2 AND dow = $6)
+        )
+    ORDER BY starts
+";
+
+#[derive(QueryableByName)]
+struct Occurrence {
+    #[sql_type = "Timestamptz"]
+    starts: DateTime<Utc>,
+}
+
+#[derive(QueryableByName)]
+struct Found {
+    #[sql_type = "Bool"]
+    found: bool,
+}
+
+/// Returns `true` if the database knows the time zone called `name`.
+fn is_timezone(c: &PgConnection, name: &str) -> Result<bool> {
+    let result: Found = diesel::sql_query(
+        "SELECT EXISTS(SELECT 1 FROM pg_timezone_names WHERE name = $1) AS found",
+    )
+    .bind::<Text, _>(name)
+    .get_result(c)
+    .chain_err(|| "unable to check time zone")?;
+
+    Ok(result.found)
+}
+
+/// A schedule for leasing an `Asset`, or any `Asset` of an `AssetType` with
+/// some tags, over and over.
+#[derive(Debug, Clone, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[belongs_to(AssetType)]
+#[belongs_to(User)]
+pub struct RecurringReservation {
+    id: i32,
+    asset_type_id: i32,
+    user_id: i32,
+    asset_id: Option<i32>,
+
+    frequency: Frequency,
+    day_of_week: Option<i16>,
+    start_time: NaiveTime,
+    duration_mins: i32,
+    timezone: String,
+
+    purpose: Option<String>,
+
+    scheduled_until: Option<DateTime<Utc>>,
+
+    skipped_at: Option<DateTime<Utc>>,
+    skipped_reason: Option<String>,
+}
+
+impl RecurringReservation {
+    pub fn by_id(c: &PgConnection, by_id: i32) -> Result<Option<RecurringReservation>> {
+        use self::recurring_reservations::dsl::*;
+
+        recurring_reservations
+            .filter(id.eq(by_id))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to find recurring reservation by id")
+    }
+
+    /// Every recurring reservation, of every asset type.
+    pub fn all(c: &PgConnection) -> Result<Vec<RecurringReservation>> {
+        use self::recurring_reservations::dsl::*;
+
+        recurring_reservations
+            .order(id.asc())
+            .load(c)
+            .chain_err(|| "failed to get recurring reservations")
+    }
+
+    /// The recurring reservations for an asset type, and their owners.
+    pub fn for_type(c: &PgConnection, type_id: i32) -> Result<Vec<(RecurringReservation, User)>> {
+        use self::recurring_reservations::dsl::*;
+        use crate::schema::users::dsl as u;
+
+        recurring_reservations
+            .inner_join(u::users)
+            .filter(asset_type_id.eq(type_id))
+            .order((start_time.asc(), id.asc()))
+            .load(c)
+            .chain_err(|| "failed to get recurring reservations for asset type")
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn asset_type_id(&self) -> i32 {
+        self.asset_type_id
+    }
+
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// The `Asset` to reserve, or `None` for any asset with the wanted tags.
+    pub fn asset_id(&self) -> Option<i32> {
+        self.asset_id
+    }
+
+    pub fn frequency(&self) -> Frequency {
+        self.frequency
+    }
+
+    /// For weekly reservations, the day they happen on, from 1 for Monday to
+    /// 7 for Sunday.
+    pub fn day_of_week(&self) -> Option<i16> {
+        self.day_of_week
+    }
+
+    /// When each occurrence starts, in `timezone`.
+    pub fn start_time(&self) -> NaiveTime {
+        self.start_time
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::minutes(self.duration_mins.into())
+    }
+
+    pub fn timezone(&self) -> &str {
+        &self.timezone
+    }
+
+    /// A short description of when the reservation happens, like
+    /// "Weekdays at 09:00 for 60 minutes (Europe/Berlin)".
+    pub fn describe(&self) -> String {
+        const DAYS: [&str; 7] = [
+            "Mondays",
+            "Tuesdays",
+            "Wednesdays",
+            "Thursdays",
+            "Fridays",
+            "Saturdays",
+            "Sundays",
+        ];
+
+        let days = match (self.frequency, self.day_of_week) {
+            (Frequency::Daily, _) => "Daily",
+            (Frequency::Weekdays, _) => "Weekdays",
+            (Frequency::Weekly, Some(x)) => DAYS[(x - 1) as usize],
+            (Frequency::Weekly, None) => "Weekly",
+        };
+
+        format!(
+            "{} at {} for {} minutes ({})",
+            days,
+            self.start_time.format("%H:%M"),
+            self.duration_mins,
+            self.timezone
+        )
+    }
+
+    /// Start times of the occurrences after `from`, up to and including
+    /// `until`.
+    pub fn occurrences(
+        &self,
+        c: &PgConnection,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>> {
+        let found: Vec<Occurrence> = diesel::sql_query(OCCURRENCES)
+            .bind::<Time, _>(self.start_time)
+            .bind::<Text, _>(&self.timezone)
+            .bind::<Timestamptz, _>(from)
+            .bind::<Timestamptz, _>(until)
+            .bind::<SmallInt, _>(self.frequency as i16)
+            .bind::<Nullable<SmallInt>, _>(self.day_of_week)
+            .load(c)
+            .chain_err(|| "unable to get occurrences of recurring reservation")?;
+
+        Ok(found.into_iter().map(|x| x.starts).collect())
+    }
+
+    /// Where the sheriff should pick up from: occurrences that start at or
+    /// before this have already been handled.
+    pub fn scheduled_until(&self) -> Option<DateTime<Utc>> {
+        self.scheduled_until
+    }
+
+    /// Record that every occurrence up to `until` has been handled.
+    pub fn mark_scheduled(&self, c: &PgConnection, until: DateTime<Utc>) -> Result<()> {
+        use self::recurring_reservations::dsl::*;
+
+        diesel::update(self)
+            .set(scheduled_until.eq(Some(until)))
+            .execute(c)
+            .chain_err(|| "unable to update recurring reservation")?;
+
+        Ok(())
+    }
+
+    /// When the last occurrence that couldn't be leased would have started.
+    pub fn skipped_at(&self) -> Option<DateTime<Utc>> {
+        self.skipped_at
+    }
+
+    /// Why the occurrence at `skipped_at` couldn't be leased.
+    pub fn skipped_reason(&self) -> Option<&str> {
+        self.skipped_reason.as_ref().map(String::as_str)
+    }
+
+    /// Record that the occurrence starting at `start` couldn't be leased,
+    /// and why, so the owner can find out.
+    pub fn mark_skipped(&self, c: &PgConnection, start: DateTime<Utc>, reason: &str) -> Result<()> {
+        use self::recurring_reservations::dsl::*;
+
+        diesel::update(self)
+            .set((skipped_at.eq(Some(start)), skipped_reason.eq(Some(reason))))
+            .execute(c)
+            .chain_err(|| "unable to mark recurring reservation as skipped")?;
+
+        Ok(())
+    }
+
+    /// The lease for the occurrence starting at `start`.
+    pub fn to_create_lease(&self, start: DateTime<Utc>) -> CreateLease {
+        CreateLease::builder()
+            .user_id(self.user_id)
+            .start_time(start)
+            .end_time(Some(start + self.duration()))
+            .purpose(self.purpose.clone())
+            .build()
+    }
+
+    /// The assets that an occurrence could reserve, whether or not they're
+    /// free at the moment. Retired assets are never reserved.
+    pub fn candidates(&self, c: &PgConnection, wanted: &[WantedTag]) -> Result<Vec<Asset>> {
+        use crate::schema::assets::dsl as a;
+
+        let mut query = a::assets
+            .filter(a::type_id.eq(self.asset_type_id))
+            .filter(a::status.ne(AssetStatus::Retired))
+            .into_boxed();
+
+        if let Some(x) = self.asset_id {
+            query = query.filter(a::id.eq(x));
+        }
+
+        let assets: Vec<Asset> = query
+            .order(a::id.asc())
+            .load(c)
+            .chain_err(|| "unable to get assets for recurring reservation")?;
+
+        let tags = Tag::belonging_to(&assets)
+            .load::<Tag>(c)
+            .chain_err(|| "unable to get tags for recurring reservation")?
+            .grouped_by(&assets);
+
+        let candidates = assets
+            .into_iter()
+            .zip(tags)
+            .filter(|(_, tags)| WantedTag::all_satisfied_by(wanted, tags))
+            .map(|(asset, _)| asset)
+            .collect();
+
+        Ok(candidates)
+    }
+
+    /// Remember that `lease` was created for one of this rule's occurrences.
+    pub fn link(&self, c: &PgConnection, lease: &Lease) -> Result<()> {
+        let link = CreateRecurringLease {
+            recurring_reservation_id: self.id,
+            lease_id: lease.id(),
+        };
+
+        diesel::insert_into(recurring_leases::table)
+            .values(&link)
+            .execute(c)
+            .chain_err(|| "unable to link lease to recurring reservation")?;
+
+        Ok(())
+    }
+
+    /// Delete the leases created for occurrences that haven't started yet.
+    /// Leases that have started are left for their owner to release.
+    pub fn delete_upcoming(&self, c: &PgConnection) -> Result<usize> {
+        use crate::schema::leases::dsl as l;
+        use crate::schema::recurring_leases::dsl as rl;
+
+        let linked = rl::recurring_leases
+            .filter(rl::recurring_reservation_id.eq(self.id))
+            .select(rl::lease_id);
+
+        let to_delete = l::leases
+            .filter(l::id.eq_any(linked))
+            .filter(l::start_time.gt(Utc::now()));
+
+        diesel::delete(to_delete)
+            .execute(c)
+            .chain_err(|| "unable to delete upcoming recurring leases")
+    }
+
+    /// Replace this rule's schedule and tags with the ones in `form`, and
+    /// delete the leases created under the old schedule that haven't started
+    /// yet. The sheriff creates new ones on its next round, and forgets
+    /// about any occurrence it skipped.
+    pub fn update(
+        &self,
+        c: &PgConnection,
+        form: &CreateRecurringReservationForm,
+    ) -> Result<(RecurringReservation, Vec<RecurringReservationTag>)> {
+        use self::recurring_reservation_tags::dsl as rrt;
+        use self::recurring_reservations::dsl::*;
+
+        c.transaction::<_, Error, _>(|| {
+            self.delete_upcoming(c)?;
+
+            let updated: RecurringReservation = diesel::update(self)
+                .set((
+                    asset_id.eq(form.asset_id),
+                    frequency.eq(form.frequency),
+                    day_of_week.eq(form.day_of_week),
+                    start_time.eq(form.start_time),
+                    duration_mins.eq(form.duration_mins),
+                    timezone.eq(&form.timezone),
+                    purpose.eq(form.purpose()),
+                    scheduled_until.eq(None::<DateTime<Utc>>),
+                    skipped_at.eq(None::<DateTime<Utc>>),
+                    skipped_reason.eq(None::<String>),
+                ))
+                .get_result(c)?;
+
+            let old_tags =
+                rrt::recurring_reservation_tags.filter(rrt::recurring_reservation_id.eq(self.id));
+
+            diesel::delete(old_tags).execute(c)?;
+
+            let tags = form.insert_tags(c, &updated)?;
+
+            Ok((updated, tags))
+        })
+    }
+}
+
+/// A tag value that an `Asset` must have before a `RecurringReservation`
+/// reserves it.
+#[derive(Debug, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(recurring_reservation_id, tag_type_id)]
+#[belongs_to(RecurringReservation)]
+#[belongs_to(TagType)]
+pub struct RecurringReservationTag {
+    #[serde(skip)]
+    recurring_reservation_id: i32,
+    tag_type_id: i32,
+
+    value: String,
+}
+
+impl RecurringReservationTag {
+    pub fn tag_type_id(&self) -> i32 {
+        self.tag_type_id
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn to_wanted_tag(&self) -> WantedTag {
+        WantedTag::new(self.tag_type_id, self.value.clone())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "recurring_reservations"]
+struct CreateRecurringReservation<'a> {
+    asset_type_id: i32,
+    user_id: i32,
+    asset_id: Option<i32>,
+
+    frequency: Frequency,
+    day_of_week: Option<i16>,
+    start_time: NaiveTime,
+    duration_mins: i32,
+    timezone: &'a str,
+
+    purpose: Option<&'a str>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "recurring_reservation_tags"]
+struct CreateRecurringReservationTag<'a> {
+    recurring_reservation_id: i32,
+    tag_type_id: i32,
+
+    value: &'a str,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "recurring_leases"]
+struct CreateRecurringLease {
+    recurring_reservation_id: i32,
+    lease_id: i32,
+}
+
+/// Request to create or replace a `RecurringReservation`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateRecurringReservationForm {
+    #[serde(default)]
+    asset_id: Option<i32>,
+
+    frequency: Frequency,
+
+    #[serde(default)]
+    day_of_week: Option<i16>,
+
+    start_time: NaiveTime,
+    duration_mins: i32,
+    timezone: String,
+
+    #[serde(default)]
+    purpose: Option<String>,
+
+    #[serde(default)]
+    tags: Vec<WantedTag>,
+}
+
+impl CreateRecurringReservationForm {
+    /// The purpose, trimmed, or `None` if it's blank.
+    fn purpose(&self) -> Option<&str> {
+        self.purpose
+            .as_ref()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+    }
+
+    /// Returns `true` if the day of the week is given for weekly rules and
+    /// only for them, occurrences can't overlap, tags are only given when no
+    /// asset is, and every wanted tag belongs to one of `tag_types`, at most
+    /// once.
+    ///
+    /// Doesn't check the time zone, or that the asset has the right type.
+    pub fn is_valid(&self, tag_types: &[TagType]) -> bool {
+        match (self.frequency, self.day_of_week) {
+            (Frequency::Weekly, Some(x)) if x >= 1 && x <= 7 => (),
+            (Frequency::Weekly, _) | (_, Some(_)) => return false,
+            _ => (),
+        }
+
+        if self.duration_mins <= 0 || self.duration_mins > self.frequency.max_duration_mins() {
+            return false;
+        }
+
+        if self.asset_id.is_some() && !self.tags.is_empty() {
+            return false;
+        }
+
+        WantedTag::all_valid(&self.tags, tag_types)
+    }
+
+    /// Returns `true` if the request `is_valid` for the tag types of
+    /// `asset_type`, the time zone exists, and the asset, if any, belongs to
+    /// `asset_type`. Asset types that require approval can't have recurring
+    /// reservations.
+    pub fn is_valid_for(&self, c: &PgConnection, asset_type: &AssetType) -> Result<bool> {
+        if asset_type.requires_approval() {
+            return Ok(false);
+        }
+
+        let tag_types: Vec<TagType> = TagType::belonging_to(asset_type)
+            .load(c)
+            .chain_err(|| "unable to get tag types belonging to an asset type")?;
+
+        if !self.is_valid(&tag_types) || !is_timezone(c, &self.timezone)? {
+            return Ok(false);
+        }
+
+        if let Some(x) = self.asset_id {
+            match Asset::by_id(c, x)? {
+                Some(ref asset) if asset.type_id() == asset_type.id() => (),
+                _ => return Ok(false),
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn insert_tags(
+        &self,
+        c: &PgConnection,
+        rule: &RecurringReservation,
+    ) -> QueryResult<Vec<RecurringReservationTag>> {
+        let tags: Vec<_> = self
+            .tags
+            .iter()
+            .map(|w| CreateRecurringReservationTag {
+                recurring_reservation_id: rule.id(),
+                tag_type_id: w.tag_type_id(),
+                value: w.value(),
+            })
+            .collect();
+
+        diesel::insert_into(recurring_reservation_tags::table)
+            .values(&tags)
+            .get_results(c)
+    }
+
+    /// Insert the `RecurringReservation` and its tags into the database.
+    pub fn insert(
+        &self,
+        c: &PgConnection,
+        asset_type_id: i32,
+        user_id: i32,
+    ) -> Result<(RecurringReservation, Vec<RecurringReservationTag>)> {
+        let create = CreateRecurringReservation {
+            asset_type_id,
+            user_id,
+            asset_id: self.asset_id,
+            frequency: self.frequency,
+            day_of_week: self.day_of_week,
+            start_time: self.start_time,
+            duration_mins: self.duration_mins,
+            timezone: &self.timezone,
+            purpose: self.purpose(),
+        };
+
+        c.transaction(|| {
+            let rule: RecurringReservation = diesel::insert_into(recurring_reservations::table)
+                .values(&create)
+                .get_result(c)?;
+
+            let tags = self.insert_tags(c, &rule)?;
+
+            Ok((rule, tags))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(
+        frequency: Frequency,
+        day_of_week: Option<i16>,
+        duration_mins: i32,
+    ) -> CreateRecurringReservationForm {
+        CreateRecurringReservationForm {
+            asset_id: None,
+            frequency,
+            day_of_week,
+            start_time: NaiveTime::from_hms(9, 0, 0),
+            duration_mins,
+            timezone: "Europe/Berlin".to_owned(),
+            purpose: None,
+            tags: vec![],
+        }
+    }
+
+    fn rule(
+        frequency: Frequency,
+        day_of_week: Option<i16>,
+        start_time: NaiveTime,
+        timezone: &str,
+    ) -> RecurringReservation {
+        RecurringReservation {
+            id: 1,
+            asset_type_id: 1,
+            user_id: 1,
+            asset_id: None,
+            frequency,
+            day_of_week,
+            start_time,
+            duration_mins: 60,
+            timezone: timezone.to_owned(),
+            purpose: None,
+            scheduled_until: None,
+            skipped_at: None,
+            skipped_reason: None,
+        }
+    }
+
+    fn utc(month: u32, day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, month, day).and_hms(hour, min, 0)
+    }
+
+    /// The occurrence tests need a database, so run them with
+    /// `cargo test -- --ignored` and `DATABASE_URL` set.
+    fn connect() -> PgConnection {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        PgConnection::establish(&url).unwrap()
+    }
+
+    #[test]
+    fn is_valid_daily() {
+        assert!(form(Frequency::Daily, None, 60).is_valid(&[]));
+        assert!(form(Frequency::Weekdays, None, 60).is_valid(&[]));
+    }
+
+    #[test]
+    fn is_valid_day_of_week_only_for_weekly() {
+        assert!(!form(Frequency::Daily, Some(1), 60).is_valid(&[]));
+        assert!(!form(Frequency::Weekdays, Some(1), 60).is_valid(&[]));
+        assert!(!form(Frequency::Weekly, None, 60).is_valid(&[]));
+        assert!(form(Frequency::Weekly, Some(1), 60).is_valid(&[]));
+        assert!(form(Frequency::Weekly, Some(7), 60).is_valid(&[]));
+    }
+
+    #[test]
+    fn is_valid_day_of_week_in_range() {
+        assert!(!form(Frequency::Weekly, Some(0), 60).is_valid(&[]));
+        assert!(!form(Frequency::Weekly, Some(8), 60).is_valid(&[]));
+    }
+
+    #[test]
+    fn is_valid_duration() {
+        assert!(!form(Frequency::Daily, None, 0).is_valid(&[]));
+        assert!(!form(Frequency::Daily, None, -60).is_valid(&[]));
+        assert!(form(Frequency::Daily, None, 24 * 60).is_valid(&[]));
+        assert!(!form(Frequency::Daily, None, 24 * 60 + 1).is_valid(&[]));
+        assert!(!form(Frequency::Weekdays, None, 24 * 60 + 1).is_valid(&[]));
+        assert!(form(Frequency::Weekly, Some(1), 7 * 24 * 60).is_valid(&[]));
+        assert!(!form(Frequency::Weekly, Some(1), 7 * 24 * 60 + 1).is_valid(&[]));
+    }
+
+    #[test]
+    fn is_valid_asset_or_tags() {
+        let mut with_asset = form(Frequency::Daily, None, 60);
+        with_asset.asset_id = Some(1);
+        assert!(with_asset.is_valid(&[]));
+
+        with_asset.tags = vec![WantedTag::new(1, "x".to_owned())];
+        assert!(!with_asset.is_valid(&[]));
+    }
+
+    #[test]
+    fn is_valid_unknown_tag_type() {
+        let mut with_tags = form(Frequency::Daily, None, 60);
+        with_tags.tags = vec![WantedTag::new(1, "x".to_owned())];
+        assert!(!with_tags.is_valid(&[]));
+    }
+
+    #[test]
+    #[ignore]
+    fn occurrences_across_spring_forward() {
+        let c = connect();
+        let rule = rule(
+            Frequency::Daily,
+            None,
+            NaiveTime::from_hms(9, 0, 0),
+            "Europe/Berlin",
+        );
+
+        let found = rule
+            .occurrences(&c, utc(3, 30, 0, 0), utc(4, 1, 12, 0))
+            .unwrap();
+
+        assert_eq!(
+            found,
+            vec![utc(3, 30, 8, 0), utc(3, 31, 7, 0), utc(4, 1, 7, 0)]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn occurrences_across_fall_back() {
+        let c = connect();
+        let rule = rule(
+            Frequency::Daily,
+            None,
+            NaiveTime::from_hms(9, 0, 0),
+            "Europe/Berlin",
+        );
+
+        let found = rule
+            .occurrences(&c, utc(10, 26, 0, 0), utc(10, 28, 12, 0))
+            .unwrap();
+
+        assert_eq!(
+            found,
+            vec![utc(10, 26, 7, 0), utc(10, 27, 8, 0), utc(10, 28, 8, 0)]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn occurrences_skip_weekends() {
+        let c = connect();
+        let rule = rule(
+            Frequency::Weekdays,
+            None,
+            NaiveTime::from_hms(9, 0, 0),
+            "Europe/Berlin",
+        );
+
+        // From a Friday to the next Monday.
+        let found = rule
+            .occurrences(&c, utc(9, 6, 0, 0), utc(9, 9, 23, 0))
+            .unwrap();
+
+        assert_eq!(found, vec![utc(9, 6, 7, 0), utc(9, 9, 7, 0)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn occurrences_weekly() {
+        let c = connect();
+        let rule = rule(
+            Frequency::Weekly,
+            Some(3),
+            NaiveTime::from_hms(9, 0, 0),
+            "Europe/Berlin",
+        );
+
+        let found = rule
+            .occurrences(&c, utc(9, 1, 0, 0), utc(9, 15, 0, 0))
+            .unwrap();
+
+        assert_eq!(found, vec![utc(9, 4, 7, 0), utc(9, 11, 7, 0)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn occurrences_exclude_from_include_until() {
+        let c = connect();
+        let rule = rule(
+            Frequency::Daily,
+            None,
+            NaiveTime::from_hms(9, 0, 0),
+            "Europe/Berlin",
+        );
+
+        let found = rule
+            .occurrences(&c, utc(9, 2, 7, 0), utc(9, 3, 7, 0))
+            .unwrap();
+
+        assert_eq!(found, vec![utc(9, 3, 7, 0)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn occurrences_on_other_utc_day() {
+        let c = connect();
+
+        // Shortly after midnight in Berlin is the day before in UTC.
+        let ahead = rule(
+            Frequency::Daily,
+            None,
+            NaiveTime::from_hms(0, 30, 0),
+            "Europe/Berlin",
+        );
+        let found = ahead
+            .occurrences(&c, utc(9, 2, 0, 0), utc(9, 3, 0, 0))
+            .unwrap();
+        assert_eq!(found, vec![utc(9, 2, 22, 30)]);
+
+        // Late evening in New York is the day after in UTC.
+        let behind = rule(
+            Frequency::Daily,
+            None,
+            NaiveTime::from_hms(23, 0, 0),
+            "America/New_York",
+        );
+        let found = behind
+            .occurrences(&c, utc(9, 2, 0, 0), utc(9, 3, 0, 0))
+            .unwrap();
+        assert_eq!(found, vec![utc(9, 2, 3, 0)]);
+    }
+}
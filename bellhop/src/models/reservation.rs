@@ -0,0 +1,158 @@
+//! A `Reservation` ties a `Lease` to one of the `Asset`s it covers.
+//!
+//! The database guarantees that reservations for the same `Asset` never
+//! overlap in time.
+
+use crate::errors::*;
+use crate::schema::reservations;
+
+use super::asset::Asset;
+use super::lease::Lease;
+use super::user::User;
+
+use chrono::prelude::*;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use std::result::Result as StdResult;
+
+/// Name of the exclusion constraint that prevents overlapping reservations.
+const NO_OVERLAP: &str = "reservations_no_overlap";
+
+#[derive(Debug, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(lease_id, asset_id)]
+#[belongs_to(Lease)]
+#[belongs_to(Asset)]
+pub struct Reservation {
+    lease_id: i32,
+    asset_id: i32,
+
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+}
+
+impl Reservation {
+    pub fn lease_id(&self) -> i32 {
+        self.lease_id
+    }
+
+    pub fn asset_id(&self) -> i32 {
+        self.asset_id
+    }
+
+    /// Returns `true` if `error` was caused by a reservation overlapping
+    /// another reservation for the same asset.
+    pub fn is_overlap(error: &DieselError) -> bool {
+        match error {
+            DieselError::DatabaseError(_, info) => info.constraint_name() == Some(NO_OVERLAP),
+            _ => false,
+        }
+    }
+
+    /// Leases for `asset_id` that haven't started yet, and their owners,
+    /// ordered by when they start.
+    pub fn upcoming(c: &PgConnection, by_asset_id: i32) -> Result<Vec<(Lease, User)>> {
+        use crate::schema::leases::dsl as l;
+        use crate::schema::reservations::dsl as r;
+        use crate::schema::users::dsl as u;
+
+        r::reservations
+            .inner_join(l::leases.inner_join(u::users))
+            .filter(r::asset_id.eq(by_asset_id))
+            .filter(r::start_time.gt(Utc::now()))
+            .order(r::start_time.asc())
+            .select((
+                crate::schema::leases::all_columns,
+                crate::schema::users::all_columns,
+            ))
+            .load(c)
+            .chain_err(|| "unable to get upcoming reservations for asset")
+    }
+
+    /// Leases in `calendar` that haven't ended yet, with the asset each
+    /// one holds and its owner, ordered by when they start.
+    ///
+    /// A lease that holds several assets appears once for each of them.
+    pub fn for_calendar(c: &PgConnection, calendar: Calendar) -> Result<Vec<(Lease, Asset, User)>> {
+        use crate::schema::assets::dsl as a;
+        use crate::schema::lease_users::dsl as lu;
+        use crate::schema::leases::dsl as l;
+        use crate::schema::reservations::dsl as r;
+        use crate::schema::users::dsl as u;
+
+        let mut query = r::reservations
+            .inner_join(l::leases.inner_join(u::users))
+            .inner_join(a::assets)
+            .filter(r::end_time.is_null().or(r::end_time.gt(Utc::now())))
+            .select((
+                crate::schema::leases::all_columns,
+                crate::schema::assets::all_columns,
+                crate::schema::users::all_columns,
+            ))
+            .into_boxed();
+
+        query = match calendar {
+            Calendar::Asset(x) => query.filter(r::asset_id.eq(x)),
+            Calendar::AssetType(x) => query.filter(a::type_id.eq(x)),
+            Calendar::User(x) => {
+                let shared = lu::lease_users
+                    .filter(lu::user_id.eq(x))
+                    .select(lu::lease_id);
+
+                query.filter(l::user_id.eq(x).or(l::id.eq_any(shared)))
+            }
+        };
+
+        query
+            .order((r::start_time.asc(), r::lease_id.asc(), a::name.asc()))
+            .load(c)
+            .chain_err(|| "unable to get reservations for calendar")
+    }
+}
+
+/// Which leases to show in a calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Calendar {
+    /// Leases holding the `Asset` with this id.
+    Asset(i32),
+
+    /// Leases holding any `Asset` of the `AssetType` with this id.
+    AssetType(i32),
+
+    /// Leases that the `User` with this id owns or shares.
+    User(i32),
+}
+
+/// The insertable companion of `Reservation`.
+#[derive(Debug, Insertable)]
+#[table_name = "reservations"]
+pub struct CreateReservation {
+    lease_id: i32,
+    asset_id: i32,
+
+    start_time: DateTime<Utc>,
+    end_time: Option<DateTime<Utc>>,
+}
+
+impl CreateReservation {
+    /// Reserve `asset_id` for the duration of `lease`.
+    pub fn new(lease: &Lease, asset_id: i32) -> Self {
+        CreateReservation {
+            asset_id,
+            lease_id: lease.id(),
+            start_time: lease.start_time(),
+            end_time: lease.end_time(),
+        }
+    }
+
+    /// Insert the `Reservation` into the database.
+    ///
+    /// The raw diesel error is returned so callers can check
+    /// [`Reservation::is_overlap`].
+    pub fn insert(&self, c: &PgConnection) -> StdResult<Reservation, DieselError> {
+        use self::reservations::dsl::*;
+
+        diesel::insert_into(reservations).values(self).get_result(c)
+    }
+}
@@ -1,20 +1,72 @@
 use chrono::prelude::*;
 
 use crate::errors::*;
-use crate::schema::sheriff;
+use crate::schema::{sheriff, sheriff_failures, sheriff_runs};
 
 use diesel::prelude::*;
+use diesel::sql_types::Bool;
 
 use std::time::Duration;
 
+/// Advisory locks that keep sheriffs sharing a database out of each other's
+/// way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SheriffLock {
+    /// Held by the one sheriff that runs the tasks on schedule, when several
+    /// replicas share a database.
+    Leader,
+
+    /// Held while the tasks are running, whether on schedule or not.
+    Run,
+}
+
+impl SheriffLock {
+    fn key(self) -> i64 {
+        // "bellhop" in ASCII, then one more for each lock.
+        match self {
+            SheriffLock::Leader => 0x0062_656c_6c68_6f70,
+            SheriffLock::Run => 0x0062_656c_6c68_6f71,
+        }
+    }
+}
+
 #[derive(Debug, Queryable, Insertable)]
 #[table_name = "sheriff"]
 pub struct Sheriff {
     primary_key: bool,
-    last_checked: Option<DateTime<Utc>>,
+    last_checked: DateTime<Utc>,
+    next_run: Option<DateTime<Utc>>,
 }
 
 impl Sheriff {
+    pub fn get(c: &PgConnection) -> Result<Sheriff> {
+        use self::sheriff::dsl::*;
+
+        sheriff.get_result(c).chain_err(|| "failed to get sheriff")
+    }
+
+    /// When the sheriff last started a run on schedule.
+    pub fn last_checked(&self) -> DateTime<Utc> {
+        self.last_checked
+    }
+
+    /// When the leader plans to run next.
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        self.next_run
+    }
+
+    /// Record when the leader plans to run next.
+    pub fn schedule(c: &PgConnection, at: DateTime<Utc>) -> Result<()> {
+        use self::sheriff::dsl::*;
+
+        diesel::update(sheriff)
+            .set(next_run.eq(Some(at)))
+            .execute(c)
+            .chain_err(|| "unable to update next_run time for sheriff")?;
+
+        Ok(())
+    }
+
     pub fn should_run(c: &PgConnection, period: Duration) -> Result<bool> {
         use self::sheriff::dsl::*;
 
@@ -29,4 +81,194 @@ impl Sheriff {
 
         Ok(count == 1)
     }
+
+    /// Try to take `lock`, which only one connection to the database can
+    /// hold at a time. It's held until `unlock` is called on the same
+    /// connection, or the connection is closed.
+    pub fn try_lock(c: &PgConnection, lock: SheriffLock) -> Result<bool> {
+        // https://github.com/diesel-rs/diesel/issues/1514
+        let fragment = format!("pg_try_advisory_lock({})", lock.key());
+
+        diesel::select(diesel::dsl::sql::<Bool>(&fragment))
+            .get_result(c)
+            .chain_err(|| "unable to try sheriff lock")
+    }
+
+    /// Release `lock`, so another connection can take it.
+    pub fn unlock(c: &PgConnection, lock: SheriffLock) -> Result<()> {
+        let fragment = format!("pg_advisory_unlock({})", lock.key());
+
+        diesel::select(diesel::dsl::sql::<Bool>(&fragment))
+            .execute(c)
+            .chain_err(|| "unable to release sheriff lock")?;
+
+        Ok(())
+    }
+
+    /// Returns an error if the connection holding a lock was lost.
+    pub fn check_lock(c: &PgConnection) -> Result<()> {
+        c.execute("SELECT 1")
+            .chain_err(|| "lost the connection holding the sheriff lock")?;
+
+        Ok(())
+    }
+}
+
+/// An error the sheriff ran into while doing one of its tasks.
+#[derive(Debug, Serialize, Queryable, Identifiable)]
+pub struct SheriffFailure {
+    id: i32,
+
+    task: String,
+    lease_id: Option<i32>,
+
+    message: String,
+
+    failed_at: DateTime<Utc>,
+}
+
+impl SheriffFailure {
+    /// Record that `task` failed with `error`, optionally while working on
+    /// the lease identified by `lease_id`.
+    pub fn record(
+        c: &PgConnection,
+        task: &str,
+        lease_id: Option<i32>,
+        error: &Error,
+    ) -> Result<()> {
+        let message = error.display_chain().to_string();
+
+        let create = CreateSheriffFailure {
+            task,
+            lease_id,
+            message: &message,
+        };
+
+        diesel::insert_into(sheriff_failures::table)
+            .values(&create)
+            .execute(c)
+            .chain_err(|| "unable to insert sheriff failure")?;
+
+        Ok(())
+    }
+
+    /// The `limit` most recent failures, newest first.
+    pub fn recent(c: &PgConnection, limit: i64) -> Result<Vec<SheriffFailure>> {
+        use self::sheriff_failures::dsl::*;
+
+        sheriff_failures
+            .order(failed_at.desc())
+            .limit(limit)
+            .load(c)
+            .chain_err(|| "failed to get sheriff failures")
+    }
+
+    /// How many failures were recorded since `since`.
+    pub fn count_since(c: &PgConnection, since: DateTime<Utc>) -> Result<i64> {
+        use self::sheriff_failures::dsl::*;
+
+        sheriff_failures
+            .filter(failed_at.ge(since))
+            .count()
+            .get_result(c)
+            .chain_err(|| "failed to count sheriff failures")
+    }
+
+    /// Forget failures from before `before`.
+    pub fn prune(c: &PgConnection, before: DateTime<Utc>) -> Result<()> {
+        use self::sheriff_failures::dsl::*;
+
+        diesel::delete(sheriff_failures.filter(failed_at.lt(before)))
+            .execute(c)
+            .chain_err(|| "unable to delete old sheriff failures")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "sheriff_failures"]
+struct CreateSheriffFailure<'a> {
+    task: &'a str,
+    lease_id: Option<i32>,
+    message: &'a str,
+}
+
+/// One time the sheriff ran its tasks, and what came of them.
+#[derive(Debug, Clone, Serialize, Queryable, Identifiable)]
+pub struct SheriffRun {
+    id: i32,
+
+    manual: bool,
+
+    started_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+
+    evicted: i32,
+    scheduled: i32,
+    expired: i32,
+    activated: i32,
+    warned: i32,
+    failures: i32,
+    overdue: i32,
+}
+
+/// How many things each of the sheriff's tasks did in a run.
+#[derive(Debug, Default, AsChangeset)]
+#[table_name = "sheriff_runs"]
+pub struct RunCounts {
+    pub evicted: i32,
+    pub scheduled: i32,
+    pub expired: i32,
+    pub activated: i32,
+    pub overdue: i32,
+    pub warned: i32,
+}
+
+impl SheriffRun {
+    /// Log the start of a run. `manual` runs were started by an admin.
+    pub fn start(c: &PgConnection, manual: bool) -> Result<SheriffRun> {
+        diesel::insert_into(sheriff_runs::table)
+            .values(sheriff_runs::manual.eq(manual))
+            .get_result(c)
+            .chain_err(|| "unable to insert sheriff run")
+    }
+
+    /// Log the end of this run, and what it did.
+    pub fn finish(&self, c: &PgConnection, counts: &RunCounts) -> Result<SheriffRun> {
+        use self::sheriff_runs::dsl::*;
+
+        let num_failures = SheriffFailure::count_since(c, self.started_at)?;
+
+        diesel::update(self)
+            .set((
+                counts,
+                finished_at.eq(Some(Utc::now())),
+                failures.eq(num_failures as i32),
+            ))
+            .get_result(c)
+            .chain_err(|| "unable to update sheriff run")
+    }
+
+    /// The `limit` most recent runs, newest first.
+    pub fn recent(c: &PgConnection, limit: i64) -> Result<Vec<SheriffRun>> {
+        use self::sheriff_runs::dsl::*;
+
+        sheriff_runs
+            .order(started_at.desc())
+            .limit(limit)
+            .load(c)
+            .chain_err(|| "failed to get sheriff runs")
+    }
+
+    /// Forget runs that started before `before`.
+    pub fn prune(c: &PgConnection, before: DateTime<Utc>) -> Result<()> {
+        use self::sheriff_runs::dsl::*;
+
+        diesel::delete(sheriff_runs.filter(started_at.lt(before)))
+            .execute(c)
+            .chain_err(|| "unable to delete old sheriff runs")?;
+
+        Ok(())
+    }
 }
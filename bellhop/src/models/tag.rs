@@ -4,6 +4,8 @@ use crate::schema::tags;
 
 use diesel::prelude::*;
 
+use std::collections::HashSet;
+
 use super::asset::Asset;
 use super::tag_type::TagType;
 
@@ -97,3 +99,43 @@ impl CreateOwnedTag {
         }
     }
 }
+
+/// A tag value that an `Asset` must have, when asking for any matching asset
+/// rather than a specific one.
+#[derive(Debug, Deserialize)]
+pub(crate) struct WantedTag {
+    tag_type_id: i32,
+    value: String,
+}
+
+impl WantedTag {
+    pub fn new(tag_type_id: i32, value: String) -> Self {
+        WantedTag { tag_type_id, value }
+    }
+
+    pub fn tag_type_id(&self) -> i32 {
+        self.tag_type_id
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns `true` if every wanted tag belongs to one of `tag_types`, and
+    /// no tag type is wanted twice.
+    pub fn all_valid(wanted: &[WantedTag], tag_types: &[TagType]) -> bool {
+        let mut seen = HashSet::new();
+
+        wanted.iter().all(|w| {
+            seen.insert(w.tag_type_id) && tag_types.iter().any(|tt| tt.id() == w.tag_type_id)
+        })
+    }
+
+    /// Returns `true` if `tags` contains every wanted tag value.
+    pub fn all_satisfied_by(wanted: &[WantedTag], tags: &[Tag]) -> bool {
+        wanted.iter().all(|w| {
+            tags.iter()
+                .any(|t| t.tag_type_id == w.tag_type_id && t.value == w.value)
+        })
+    }
+}
@@ -16,7 +16,7 @@ use rocket::request::{self, FromRequest, Request, State};
 use rocket::Outcome;
 
 /// A `User` is Bellhop's representation of a person or API client.
-#[derive(Debug, Serialize, Queryable, Identifiable, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, PartialEq)]
 pub struct User {
     id: i32,
     email: String,
@@ -0,0 +1,238 @@
+//! A `WaitlistEntry` is a place in line for the next free `Asset` of an
+//! `AssetType`.
+
+use crate::errors::*;
+use crate::schema::{waitlist_entries, waitlist_tags};
+
+use super::asset_type::AssetType;
+use super::lease::CreateLease;
+use super::tag::{Tag, WantedTag};
+use super::tag_type::TagType;
+use super::user::User;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use diesel::prelude::*;
+
+/// A `User` waiting for an `Asset` of a particular `AssetType`.
+///
+/// Entries are served first come, first served.
+#[derive(Debug, Clone, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[table_name = "waitlist_entries"]
+#[belongs_to(AssetType)]
+#[belongs_to(User)]
+pub struct WaitlistEntry {
+    id: i32,
+    asset_type_id: i32,
+    user_id: i32,
+
+    created_at: DateTime<Utc>,
+    lease_seconds: Option<i32>,
+}
+
+impl WaitlistEntry {
+    pub fn by_id(c: &PgConnection, by_id: i32) -> Result<Option<WaitlistEntry>> {
+        use self::waitlist_entries::dsl::*;
+
+        waitlist_entries
+            .filter(id.eq(by_id))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "failed to find waitlist entry by id")
+    }
+
+    /// Everyone waiting for an asset of the given type, in the order they'll
+    /// be served.
+    pub fn queue(c: &PgConnection, type_id: i32) -> Result<Vec<(WaitlistEntry, User)>> {
+        use self::waitlist_entries::dsl::*;
+        use crate::schema::users::dsl as u;
+
+        waitlist_entries
+            .inner_join(u::users)
+            .filter(asset_type_id.eq(type_id))
+            .order((created_at.asc(), id.asc()))
+            .load(c)
+            .chain_err(|| "failed to get waitlist for asset type")
+    }
+
+    /// Like `queue`, but locks the entries until the end of the current
+    /// transaction. Entries already locked by someone else are skipped.
+    pub fn lock_queue(c: &PgConnection, type_id: i32) -> Result<Vec<WaitlistEntry>> {
+        use self::waitlist_entries::dsl::*;
+
+        waitlist_entries
+            .filter(asset_type_id.eq(type_id))
+            .order((created_at.asc(), id.asc()))
+            .for_update()
+            .skip_locked()
+            .load(c)
+            .chain_err(|| "failed to lock waitlist for asset type")
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn asset_type_id(&self) -> i32 {
+        self.asset_type_id
+    }
+
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Returns `true` if an asset with the given tags satisfies every tag
+    /// this entry is waiting for.
+    pub fn is_satisfied_by(wanted: &[WaitlistTag], tags: &[Tag]) -> bool {
+        let wanted: Vec<_> = wanted.iter().map(WaitlistTag::to_wanted_tag).collect();
+
+        WantedTag::all_satisfied_by(&wanted, tags)
+    }
+
+    /// Build the lease this entry's user gets when an asset is handed to them.
+    pub fn to_create_lease(&self, start_time: DateTime<Utc>) -> CreateLease {
+        let end_time = self
+            .lease_seconds
+            .map(|x| start_time + Duration::seconds(x.into()));
+
+        CreateLease::builder()
+            .user_id(self.user_id)
+            .start_time(start_time)
+            .end_time(end_time)
+            .build()
+    }
+}
+
+/// A tag value that an `Asset` must have before it is handed to a
+/// `WaitlistEntry`.
+#[derive(Debug, Associations, Serialize, Queryable, Identifiable, PartialEq, Eq)]
+#[primary_key(waitlist_entry_id, tag_type_id)]
+#[belongs_to(WaitlistEntry)]
+#[belongs_to(TagType)]
+pub struct WaitlistTag {
+    #[serde(skip)]
+    waitlist_entry_id: i32,
+    tag_type_id: i32,
+
+    value: String,
+}
+
+impl WaitlistTag {
+    pub fn tag_type_id(&self) -> i32 {
+        self.tag_type_id
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn to_wanted_tag(&self) -> WantedTag {
+        WantedTag::new(self.tag_type_id, self.value.clone())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "waitlist_entries"]
+struct CreateWaitlistEntry {
+    asset_type_id: i32,
+    user_id: i32,
+
+    lease_seconds: Option<i32>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "waitlist_tags"]
+struct CreateWaitlistTag<'a> {
+    waitlist_entry_id: i32,
+    tag_type_id: i32,
+
+    value: &'a str,
+}
+
+/// HTML form version of `CreateWaitlistEntryForm`, which only supports
+/// waiting for a single tag value.
+#[derive(Debug, FromForm)]
+pub(crate) struct JoinWaitlistForm {
+    lease_hours: Option<i32>,
+    tag_type_id: Option<i32>,
+    value: Option<String>,
+}
+
+impl JoinWaitlistForm {
+    pub fn into_create_waitlist_entry(self) -> CreateWaitlistEntryForm {
+        let lease_seconds = self.lease_hours.map(|x| x.saturating_mul(60 * 60));
+
+        let tags = match (self.tag_type_id, self.value) {
+            (Some(tag_type_id), Some(value)) if !value.is_empty() => {
+                vec![WantedTag::new(tag_type_id, value)]
+            }
+            _ => vec![],
+        };
+
+        CreateWaitlistEntryForm {
+            lease_seconds,
+            tags,
+        }
+    }
+}
+
+/// Request to join the waitlist for an `AssetType`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateWaitlistEntryForm {
+    #[serde(default)]
+    lease_seconds: Option<i32>,
+
+    #[serde(default)]
+    tags: Vec<WantedTag>,
+}
+
+impl CreateWaitlistEntryForm {
+    /// Returns `true` if the requested lease has a sensible duration, and
+    /// every wanted tag belongs to one of `tag_types`, at most once.
+    pub fn is_valid(&self, tag_types: &[TagType]) -> bool {
+        if let Some(x) = self.lease_seconds {
+            if x <= 0 {
+                return false;
+            }
+        }
+
+        WantedTag::all_valid(&self.tags, tag_types)
+    }
+
+    /// Insert the `WaitlistEntry` and its tags into the database.
+    pub fn insert(
+        &self,
+        c: &PgConnection,
+        asset_type_id: i32,
+        user_id: i32,
+    ) -> Result<(WaitlistEntry, Vec<WaitlistTag>)> {
+        let entry = CreateWaitlistEntry {
+            asset_type_id,
+            user_id,
+            lease_seconds: self.lease_seconds,
+        };
+
+        c.transaction(|| {
+            let entry: WaitlistEntry = diesel::insert_into(waitlist_entries::table)
+                .values(&entry)
+                .get_result(c)?;
+
+            let tags: Vec<_> = self
+                .tags
+                .iter()
+                .map(|w| CreateWaitlistTag {
+                    waitlist_entry_id: entry.id(),
+                    tag_type_id: w.tag_type_id(),
+                    value: w.value(),
+                })
+                .collect();
+
+            let tags = diesel::insert_into(waitlist_tags::table)
+                .values(&tags)
+                .get_results(c)?;
+
+            Ok((entry, tags))
+        })
+    }
+}
@@ -0,0 +1,327 @@
+use chrono::prelude::*;
+
+use crate::errors::*;
+use crate::internal::db::DbPool;
+use crate::internal::hooks::Hooks;
+use crate::models::hook_delivery::HookDelivery;
+
+use diesel::prelude::*;
+
+use error_chain::ChainedError;
+
+use rocket::config::ConfigError;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How hook events in the outbox are delivered, from the `outbox` table in
+/// `Rocket.toml`. Every setting is optional:
+///
+/// ```toml
+/// [global.outbox]
+/// poll_secs = 5
+/// max_attempts = 10
+/// retry_secs = 30
+/// max_retry_secs = 3600
+/// keep_days = 7
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+struct Config {
+    /// Time between looking for events that are due, in seconds.
+    poll_secs: u64,
+
+    /// Give up on an event, marking it as dead, after this many failed
+    /// attempts.
+    max_attempts: u32,
+
+    /// Wait this many seconds before the first retry, doubling each time.
+    retry_secs: u64,
+
+    /// Never wait longer than this many seconds between retries.
+    max_retry_secs: u64,
+
+    /// Keep delivered events around for this many days.
+    keep_days: i64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            poll_secs: 5,
+            max_attempts: 10,
+            retry_secs: 30,
+            max_retry_secs: 60 * 60,
+            keep_days: 7,
+        }
+    }
+}
+
+impl Config {
+    fn poll(&self) -> Duration {
+        Duration::from_secs(self.poll_secs)
+    }
+
+    /// How many seconds to wait after the `attempt`th attempt failed,
+    /// counting from zero, or `None` to give up.
+    fn retry_secs(&self, attempt: i32) -> Option<u64> {
+        if i64::from(attempt) + 1 >= i64::from(self.max_attempts) {
+            return None;
+        }
+
+        let factor = 1u64.checked_shl(attempt as u32).unwrap_or(u64::max_value());
+        let delay = self
+            .retry_secs
+            .saturating_mul(factor)
+            .min(self.max_retry_secs);
+
+        Some(delay)
+    }
+
+    /// When to try again after the `attempt`th attempt failed, or `None` to
+    /// give up.
+    fn retry_at(&self, attempt: i32) -> Option<DateTime<Utc>> {
+        self.retry_secs(attempt)
+            .map(|x| Utc::now() + chrono::Duration::seconds(x as i64))
+    }
+}
+
+struct Courier {
+    running: Arc<AtomicBool>,
+    db_pool: DbPool,
+    deadline: Instant,
+    hooks: Hooks,
+    config: Config,
+}
+
+impl Courier {
+    fn new(db_pool: DbPool, running: Arc<AtomicBool>, hooks: Hooks, config: Config) -> Self {
+        Courier {
+            running,
+            db_pool,
+            hooks,
+            deadline: Instant::now() + config.poll(),
+            config,
+        }
+    }
+
+    fn wait(&self) -> bool {
+        while self.running.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= self.deadline {
+                return true;
+            }
+
+            let timeout = self.deadline - now;
+
+            thread::park_timeout(timeout);
+        }
+
+        false
+    }
+
+    /// Deliver the event that has been due the longest, if any. Returns
+    /// `false` if there was nothing to deliver.
+    ///
+    /// The event stays locked while its hook runs, so couriers sharing a
+    /// database never deliver the same event at once.
+    fn deliver_one(&self, c: &PgConnection) -> Result<bool> {
+        c.transaction::<_, Error, _>(|| {
+            let delivery = match HookDelivery::next_due(c)? {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+
+            // Whatever the hook did to the database is rolled back if it
+            // fails, but the failure is still recorded.
+            let ran = c.transaction::<_, Error, _>(|| {
+                let ran =
+                    panic::catch_unwind(AssertUnwindSafe(|| self.hooks.deliver(c, &delivery)));
+
+                match ran {
+                    Ok(x) => x,
+                    Err(_) => Err("hook panicked".into()),
+                }
+            });
+
+            let e = match ran {
+                Ok(()) => {
+                    delivery.delivered(c)?;
+                    return Ok(true);
+                }
+                Err(e) => e,
+            };
+
+            let retry_at = self.config.retry_at(delivery.attempts());
+
+            if retry_at.is_none() {
+                eprintln!(
+                    "outbox gave up on delivery id {} to {}: {}",
+                    delivery.id(),
+                    delivery.hook(),
+                    e.display_chain()
+                );
+            }
+
+            delivery.failed(c, &e.display_chain().to_string(), retry_at)?;
+
+            Ok(true)
+        })
+    }
+
+    fn run_one(&mut self) -> Result<()> {
+        let conn = self
+            .db_pool
+            .get()
+            .chain_err(|| "couldn't get database connection")?;
+
+        while self.running.load(Ordering::SeqCst) && self.deliver_one(&conn)? {}
+
+        let before = Utc::now() - chrono::Duration::days(self.config.keep_days);
+        HookDelivery::prune(&conn, before)?;
+
+        Ok(())
+    }
+
+    fn run(mut self) {
+        while self.wait() {
+            // Events stay in the outbox, so they're picked up again next time.
+            match panic::catch_unwind(AssertUnwindSafe(|| self.run_one())) {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => eprintln!("outbox unable to deliver: {}", e.display_chain()),
+                Err(_) => eprintln!("outbox panicked; trying again later"),
+            }
+
+            self.deadline += self.config.poll();
+
+            // Don't try to catch up on polls missed during slow hooks.
+            let now = Instant::now();
+            if self.deadline < now {
+                self.deadline = now + self.config.poll();
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Outbox {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        let handle = self.handle.take().expect("outbox has no courier");
+
+        handle.thread().unpark();
+
+        handle.join().expect("courier thread panicked");
+    }
+}
+
+impl Outbox {
+    fn new(db_pool: DbPool, hooks: Hooks, config: Config) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let courier = Courier::new(db_pool, running.clone(), hooks, config);
+
+        let handle = thread::Builder::new()
+            .name("outbox".into())
+            .spawn(move || courier.run())
+            .expect("unable to start outbox thread");
+
+        Outbox {
+            running,
+            handle: Some(handle),
+        }
+    }
+
+    /// Returns a fairing that delivers hook events from the outbox in the
+    /// background. Must be called after attaching the database fairing.
+    pub fn fairing() -> impl ::rocket::fairing::Fairing {
+        ::rocket::fairing::AdHoc::on_attach("Outbox", |rocket| {
+            let pool = match rocket.state::<DbPool>() {
+                Some(p) => p,
+                None => return Err(rocket),
+            };
+
+            let hooks = match rocket.state::<Hooks>() {
+                Some(h) => h,
+                None => return Err(rocket),
+            };
+
+            let config = match rocket.config().get_extra("outbox") {
+                Ok(x) => match x.clone().try_into() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        eprintln!("invalid outbox configuration: {}", e);
+                        return Err(rocket);
+                    }
+                },
+                Err(ConfigError::Missing(_)) => Config::default(),
+                Err(e) => {
+                    eprintln!("invalid outbox configuration: {}", e);
+                    return Err(rocket);
+                }
+            };
+
+            let outbox = Self::new(pool.clone(), hooks.clone(), config);
+
+            Ok(rocket.manage(outbox))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_secs_doubles() {
+        let config = Config::default();
+
+        assert_eq!(config.retry_secs(0), Some(30));
+        assert_eq!(config.retry_secs(1), Some(60));
+        assert_eq!(config.retry_secs(2), Some(120));
+        assert_eq!(config.retry_secs(6), Some(1920));
+    }
+
+    #[test]
+    fn retry_secs_capped() {
+        let config = Config::default();
+
+        assert_eq!(config.retry_secs(7), Some(3600));
+        assert_eq!(config.retry_secs(8), Some(3600));
+    }
+
+    #[test]
+    fn retry_secs_gives_up() {
+        let config = Config::default();
+
+        assert_eq!(config.retry_secs(9), None);
+        assert_eq!(config.retry_secs(10), None);
+
+        let once = Config {
+            max_attempts: 1,
+            ..Config::default()
+        };
+
+        assert_eq!(once.retry_secs(0), None);
+    }
+
+    #[test]
+    fn retry_secs_huge_attempts() {
+        let config = Config {
+            max_attempts: u32::max_value(),
+            ..Config::default()
+        };
+
+        assert_eq!(config.retry_secs(63), Some(3600));
+        assert_eq!(config.retry_secs(64), Some(3600));
+        assert_eq!(config.retry_secs(1000), Some(3600));
+    }
+}
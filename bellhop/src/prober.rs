@@ -0,0 +1,389 @@
+use crate::errors::*;
+use crate::internal::db::DbPool;
+use crate::internal::hooks::Hooks;
+use crate::models::asset::{Asset, AssetStatus, SetAssetStatusForm};
+use crate::models::asset_type::AssetType;
+use crate::models::probe::{CreateProbeResult, Probe, ProbeCommands, ProbeKind};
+use crate::models::prober::Prober as ProberModel;
+
+use diesel::prelude::*;
+
+use error_chain::ChainedError;
+
+use rocket::config::ConfigError;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const PERIOD: Duration = Duration::from_secs(60);
+
+/// How often to check whether a probe command has exited.
+const POLL: Duration = Duration::from_millis(50);
+
+struct Inspector {
+    running: Arc<AtomicBool>,
+    db_pool: DbPool,
+    deadline: Instant,
+    hooks: Hooks,
+    commands: ProbeCommands,
+}
+
+impl Inspector {
+    fn new(
+        db_pool: DbPool,
+        running: Arc<AtomicBool>,
+        hooks: Hooks,
+        commands: ProbeCommands,
+    ) -> Self {
+        Inspector {
+            running,
+            db_pool,
+            hooks,
+            commands,
+            deadline: Instant::now() + PERIOD,
+        }
+    }
+
+    fn wait(&self) -> bool {
+        while self.running.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= self.deadline {
+                return true;
+            }
+
+            let timeout = self.deadline - now;
+
+            thread::park_timeout(timeout);
+        }
+
+        false
+    }
+
+    fn run_one(&mut self) -> Result<()> {
+        use crate::schema::assets::dsl as a;
+
+        let conn = self
+            .db_pool
+            .get()
+            .chain_err(|| "couldn't get database connection")?;
+
+        if !ProberModel::should_run(&conn, PERIOD)? {
+            return Ok(());
+        }
+
+        let mut by_type: BTreeMap<i32, Vec<Probe>> = BTreeMap::new();
+        for probe in Probe::all(&conn)? {
+            by_type
+                .entry(probe.asset_type_id())
+                .or_default()
+                .push(probe);
+        }
+
+        for (type_id, probes) in by_type {
+            let asset_type =
+                AssetType::by_id(&conn, type_id)?.chain_err(|| "missing asset_type")?;
+
+            let assets: Vec<Asset> = Asset::belonging_to(&asset_type)
+                .filter(a::status.ne(AssetStatus::Retired))
+                .order(a::id.asc())
+                .load(&*conn)
+                .chain_err(|| "prober was unable to get assets")?;
+
+            let mut tags = tags_by_asset(&conn, &assets)?;
+
+            for asset in assets {
+                let tags = tags.remove(&asset.id()).unwrap_or_default();
+
+                for probe in probes.iter() {
+                    // Checks can be slow, so don't hold up shutting down.
+                    if !self.running.load(Ordering::SeqCst) {
+                        return Ok(());
+                    }
+
+                    // Assets without the tags the probe needs aren't checked.
+                    let result = match check(probe, &self.commands, &asset, &tags) {
+                        Some(x) => x,
+                        None => continue,
+                    };
+
+                    result.upsert(&conn)?;
+
+                    if !result.ok() && probe.auto_maintenance() {
+                        quarantine(&conn, &self.hooks, probe, &asset, &asset_type, &result)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run(mut self) {
+        while self.wait() {
+            // Unlike the sheriff, a broken prober doesn't stop leases from
+            // ending, so try again next time instead of exiting.
+            if let Err(e) = self.run_one() {
+                eprintln!("prober unable to run: {}", e.display_chain());
+            }
+
+            self.deadline += PERIOD;
+
+            // Don't try to catch up on runs missed during slow checks.
+            let now = Instant::now();
+            if self.deadline < now {
+                self.deadline = now + PERIOD;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Prober {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Prober {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        let handle = self.handle.take().expect("prober has no inspector");
+
+        handle.thread().unpark();
+
+        handle.join().expect("inspector thread panicked");
+    }
+}
+
+impl Prober {
+    fn new(db_pool: DbPool, hooks: Hooks, commands: ProbeCommands) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let inspector = Inspector::new(db_pool, running.clone(), hooks, commands);
+
+        let handle = thread::Builder::new()
+            .name("prober".into())
+            .spawn(move || inspector.run())
+            .expect("unable to start prober thread");
+
+        Prober {
+            running,
+            handle: Some(handle),
+        }
+    }
+
+    /// Returns a fairing that handles periodically running health checks
+    /// against assets. Must be called after attaching the database fairing.
+    pub fn fairing() -> impl ::rocket::fairing::Fairing {
+        ::rocket::fairing::AdHoc::on_attach("Prober", |rocket| {
+            let pool = match rocket.state::<DbPool>() {
+                Some(p) => p,
+                None => return Err(rocket),
+            };
+
+            let hooks = match rocket.state::<Hooks>() {
+                Some(h) => h,
+                None => return Err(rocket),
+            };
+
+            let commands = match rocket.config().get_extra("probe_commands") {
+                Ok(x) => match x.clone().try_into() {
+                    Ok(x) => ProbeCommands::new(x),
+                    Err(e) => {
+                        eprintln!("invalid probe_commands configuration: {}", e);
+                        return Err(rocket);
+                    }
+                },
+                Err(ConfigError::Missing(_)) => ProbeCommands::default(),
+                Err(e) => {
+                    eprintln!("invalid probe_commands configuration: {}", e);
+                    return Err(rocket);
+                }
+            };
+
+            let prober = Self::new(pool.clone(), hooks.clone(), commands.clone());
+
+            Ok(rocket.manage(prober).manage(commands))
+        })
+    }
+}
+
+/// The values of each asset's tags, keyed by asset id and then tag type name.
+fn tags_by_asset(
+    c: &PgConnection,
+    assets: &[Asset],
+) -> Result<HashMap<i32, HashMap<String, String>>> {
+    use crate::schema::tag_types::dsl as tt;
+    use crate::schema::tags::dsl as t;
+
+    let ids = assets.iter().map(Asset::id).collect::<Vec<_>>();
+
+    let rows: Vec<(i32, String, String)> = t::tags
+        .inner_join(tt::tag_types)
+        .filter(t::asset_id.eq_any(ids))
+        .select((t::asset_id, tt::name, t::value))
+        .load(c)
+        .chain_err(|| "prober was unable to get tags")?;
+
+    let mut tags: HashMap<i32, HashMap<String, String>> = HashMap::new();
+    for (asset_id, name, value) in rows {
+        tags.entry(asset_id).or_default().insert(name, value);
+    }
+
+    Ok(tags)
+}
+
+/// Move an asset that failed a probe into maintenance, unless it's already
+/// out of service.
+fn quarantine(
+    c: &PgConnection,
+    hooks: &Hooks,
+    probe: &Probe,
+    asset: &Asset,
+    asset_type: &AssetType,
+    result: &CreateProbeResult,
+) -> Result<()> {
+    c.transaction::<_, Error, _>(|| {
+        // An earlier probe, or an admin, might have already moved it.
+        let asset = match Asset::by_id(c, asset.id())? {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        if !asset.is_available() {
+            return Ok(());
+        }
+
+        let reason = match result.message() {
+            Some(msg) => format!("Health check '{}' failed: {}", probe.name(), msg),
+            None => format!("Health check '{}' failed", probe.name()),
+        };
+
+        let updated = asset.set_status(c, &SetAssetStatusForm::maintenance(reason))?;
+
+        hooks
+            .entered_maintenance(c, &updated, asset_type)
+            .chain_err(|| "prober encountered an error while sending hooks")?;
+
+        println!(
+            "The prober moved asset id {} into maintenance after failing probe id {}.",
+            updated.id(),
+            probe.id()
+        );
+
+        Ok(())
+    })
+}
+
+enum Outcome {
+    Passed(Option<String>),
+    Failed(String),
+    TimedOut,
+}
+
+/// Run `probe` against `asset`, or return `None` if the asset doesn't have
+/// the tags the probe needs.
+fn check(
+    probe: &Probe,
+    commands: &ProbeCommands,
+    asset: &Asset,
+    tags: &HashMap<String, String>,
+) -> Option<CreateProbeResult> {
+    let timeout = probe.timeout();
+    let start = Instant::now();
+
+    let outcome = match probe.kind() {
+        ProbeKind::Tcp => check_tcp(&probe.target_for(tags)?, timeout),
+        ProbeKind::Http => check_http(&probe.target_for(tags)?, timeout),
+        // The operator might have taken the command out of `Rocket.toml`.
+        ProbeKind::Command if commands.get(probe.target()).is_none() => {
+            Outcome::Failed(format!("no probe command named {}", probe.target()))
+        }
+        ProbeKind::Command => check_command(&probe.command_for(commands, tags)?, timeout),
+    };
+
+    let latency = start.elapsed();
+
+    let result = match outcome {
+        Outcome::Passed(msg) => CreateProbeResult::new(probe, asset, true, Some(latency), msg),
+        Outcome::Failed(msg) => {
+            CreateProbeResult::new(probe, asset, false, Some(latency), Some(msg))
+        }
+        Outcome::TimedOut => {
+            let msg = format!("timed out after {} ms", timeout.as_millis());
+            CreateProbeResult::new(probe, asset, false, None, Some(msg))
+        }
+    };
+
+    Some(result)
+}
+
+fn check_tcp(target: &str, timeout: Duration) -> Outcome {
+    let addr = match target.to_socket_addrs().map(|mut x| x.next()) {
+        Ok(Some(x)) => x,
+        Ok(None) => return Outcome::Failed(format!("{} has no addresses", target)),
+        Err(e) => return Outcome::Failed(format!("unable to resolve {}: {}", target, e)),
+    };
+
+    match TcpStream::connect_timeout(&addr, timeout) {
+        Ok(_) => Outcome::Passed(None),
+        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => Outcome::TimedOut,
+        Err(e) => Outcome::Failed(format!("unable to connect to {}: {}", addr, e)),
+    }
+}
+
+fn check_http(target: &str, timeout: Duration) -> Outcome {
+    let client = match reqwest::Client::builder().timeout(timeout).build() {
+        Ok(x) => x,
+        Err(e) => return Outcome::Failed(format!("unable to build http client: {}", e)),
+    };
+
+    match client.get(target).send() {
+        Ok(ref res) if res.status().is_success() => Outcome::Passed(Some(res.status().to_string())),
+        Ok(res) => Outcome::Failed(res.status().to_string()),
+        Err(ref e) if e.is_timeout() => Outcome::TimedOut,
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+fn check_command(argv: &[String], timeout: Duration) -> Outcome {
+    let (program, args) = match argv.split_first() {
+        Some(x) => x,
+        None => return Outcome::Failed("empty command".into()),
+    };
+
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(x) => x,
+        Err(e) => return Outcome::Failed(format!("unable to run {}: {}", program, e)),
+    };
+
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Outcome::Passed(None),
+            Ok(Some(status)) => return Outcome::Failed(status.to_string()),
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL),
+            Ok(None) => {
+                child.kill().ok();
+                child.wait().ok();
+                return Outcome::TimedOut;
+            }
+            Err(e) => {
+                child.kill().ok();
+                return Outcome::Failed(format!("unable to wait for {}: {}", program, e));
+            }
+        }
+    }
+}
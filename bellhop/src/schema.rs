@@ -1,9 +1,19 @@
+table! {
+    approvers (asset_type_id, user_id) {
+        asset_type_id -> Int4,
+        user_id -> Int4,
+    }
+}
+
 table! {
     assets (id) {
         id -> Int4,
         type_id -> Int4,
         lease_id -> Nullable<Int4>,
         name -> Varchar,
+        status -> Int2,
+        status_reason -> Nullable<Text>,
+        expected_back -> Nullable<Timestamptz>,
     }
 }
 
@@ -12,6 +22,133 @@ table! {
         id -> Int4,
         name -> Varchar,
         plural_name -> Varchar,
+        max_lease_seconds -> Nullable<Int4>,
+        max_extensions -> Nullable<Int4>,
+        requires_approval -> Bool,
+        warn_before_secs -> Nullable<Array<Int4>>,
+        warn_percent -> Nullable<Int4>,
+        grace_secs -> Int4,
+        cooldown_secs -> Int4,
+    }
+}
+
+table! {
+    ended_leases (id) {
+        id -> Int4,
+        lease_id -> Int4,
+        asset_id -> Int4,
+        user_id -> Int4,
+        start_time -> Timestamptz,
+        end_time -> Nullable<Timestamptz>,
+        extensions -> Int4,
+        ended_at -> Timestamptz,
+        end_reason -> Int2,
+        revoked_by -> Nullable<Int4>,
+        revoke_reason -> Nullable<Text>,
+    }
+}
+
+table! {
+    feed_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    hook_deliveries (id) {
+        id -> Int4,
+        hook -> Varchar,
+        event -> Int2,
+        payload -> Jsonb,
+        status -> Int2,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        next_attempt_at -> Timestamptz,
+        delivered_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    lease_field_values (lease_id, lease_field_id) {
+        lease_id -> Int4,
+        lease_field_id -> Int4,
+        value -> Text,
+    }
+}
+
+table! {
+    lease_fields (id) {
+        id -> Int4,
+        asset_type_id -> Int4,
+        name -> Text,
+        required -> Bool,
+        pattern -> Nullable<Text>,
+    }
+}
+
+table! {
+    lease_quotas (id) {
+        id -> Int4,
+        asset_type_id -> Int4,
+        user_id -> Nullable<Int4>,
+        max_leases -> Nullable<Int4>,
+        max_hours_per_week -> Nullable<Int4>,
+        allow_infinite -> Bool,
+    }
+}
+
+table! {
+    lease_request_fields (lease_request_id, name) {
+        lease_request_id -> Int4,
+        name -> Text,
+        value -> Text,
+    }
+}
+
+table! {
+    lease_requests (id) {
+        id -> Int4,
+        asset_id -> Int4,
+        user_id -> Int4,
+        start_time -> Timestamptz,
+        end_time -> Nullable<Timestamptz>,
+        purpose -> Nullable<Text>,
+        heartbeat_secs -> Nullable<Int4>,
+        status -> Int2,
+        requested_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        decided_by -> Nullable<Int4>,
+        decided_at -> Nullable<Timestamptz>,
+        decision_reason -> Nullable<Text>,
+        lease_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    lease_transfers (lease_id) {
+        lease_id -> Int4,
+        from_user_id -> Int4,
+        to_user_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    lease_users (lease_id, user_id) {
+        lease_id -> Int4,
+        user_id -> Int4,
+    }
+}
+
+table! {
+    lease_warnings (lease_id, before_secs) {
+        lease_id -> Int4,
+        before_secs -> Int4,
+        warned_at -> Timestamptz,
     }
 }
 
@@ -22,6 +159,83 @@ table! {
         last_notified -> Nullable<Timestamptz>,
         start_time -> Timestamptz,
         end_time -> Nullable<Timestamptz>,
+        extensions -> Int4,
+        purpose -> Nullable<Text>,
+        heartbeat_secs -> Nullable<Int4>,
+        last_heartbeat -> Nullable<Timestamptz>,
+        overdue_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    probe_results (probe_id, asset_id) {
+        probe_id -> Int4,
+        asset_id -> Int4,
+        ok -> Bool,
+        latency_ms -> Nullable<Int4>,
+        message -> Nullable<Text>,
+        checked_at -> Timestamptz,
+    }
+}
+
+table! {
+    prober (primary_key) {
+        primary_key -> Bool,
+        last_checked -> Timestamptz,
+    }
+}
+
+table! {
+    probes (id) {
+        id -> Int4,
+        asset_type_id -> Int4,
+        name -> Text,
+        kind -> Int2,
+        target -> Text,
+        timeout_ms -> Int4,
+        auto_maintenance -> Bool,
+    }
+}
+
+table! {
+    recurring_leases (recurring_reservation_id, lease_id) {
+        recurring_reservation_id -> Int4,
+        lease_id -> Int4,
+    }
+}
+
+table! {
+    recurring_reservation_tags (recurring_reservation_id, tag_type_id) {
+        recurring_reservation_id -> Int4,
+        tag_type_id -> Int4,
+        value -> Varchar,
+    }
+}
+
+table! {
+    recurring_reservations (id) {
+        id -> Int4,
+        asset_type_id -> Int4,
+        user_id -> Int4,
+        asset_id -> Nullable<Int4>,
+        frequency -> Int2,
+        day_of_week -> Nullable<Int2>,
+        start_time -> Time,
+        duration_mins -> Int4,
+        timezone -> Text,
+        purpose -> Nullable<Text>,
+        scheduled_until -> Nullable<Timestamptz>,
+        skipped_at -> Nullable<Timestamptz>,
+        skipped_reason -> Nullable<Text>,
+    }
+}
+
+table! {
+    reservations (lease_id, asset_id) {
+        lease_id -> Int4,
+        asset_id -> Int4,
+        start_time -> Timestamptz,
+        end_time -> Nullable<Timestamptz>,
     }
 }
 
@@ -29,6 +243,33 @@ table! {
     sheriff (primary_key) {
         primary_key -> Bool,
         last_checked -> Timestamptz,
+        next_run -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    sheriff_failures (id) {
+        id -> Int4,
+        task -> Varchar,
+        lease_id -> Nullable<Int4>,
+        message -> Text,
+        failed_at -> Timestamptz,
+    }
+}
+
+table! {
+    sheriff_runs (id) {
+        id -> Int4,
+        manual -> Bool,
+        started_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+        evicted -> Int4,
+        scheduled -> Int4,
+        expired -> Int4,
+        activated -> Int4,
+        warned -> Int4,
+        failures -> Int4,
+        overdue -> Int4,
     }
 }
 
@@ -58,19 +299,93 @@ table! {
     }
 }
 
+table! {
+    waitlist_entries (id) {
+        id -> Int4,
+        asset_type_id -> Int4,
+        user_id -> Int4,
+        created_at -> Timestamptz,
+        lease_seconds -> Nullable<Int4>,
+    }
+}
+
+table! {
+    waitlist_tags (waitlist_entry_id, tag_type_id) {
+        waitlist_entry_id -> Int4,
+        tag_type_id -> Int4,
+        value -> Varchar,
+    }
+}
+
+joinable!(approvers -> asset_types (asset_type_id));
+joinable!(approvers -> users (user_id));
 joinable!(assets -> asset_types (type_id));
 joinable!(assets -> leases (lease_id));
+joinable!(ended_leases -> assets (asset_id));
+joinable!(ended_leases -> users (user_id));
+joinable!(feed_tokens -> users (user_id));
+joinable!(lease_field_values -> lease_fields (lease_field_id));
+joinable!(lease_field_values -> leases (lease_id));
+joinable!(lease_fields -> asset_types (asset_type_id));
+joinable!(lease_quotas -> asset_types (asset_type_id));
+joinable!(lease_quotas -> users (user_id));
+joinable!(lease_request_fields -> lease_requests (lease_request_id));
+joinable!(lease_requests -> assets (asset_id));
+joinable!(lease_requests -> leases (lease_id));
+joinable!(lease_transfers -> leases (lease_id));
+joinable!(lease_users -> leases (lease_id));
+joinable!(lease_users -> users (user_id));
+joinable!(lease_warnings -> leases (lease_id));
 joinable!(leases -> users (user_id));
+joinable!(probe_results -> assets (asset_id));
+joinable!(probe_results -> probes (probe_id));
+joinable!(probes -> asset_types (asset_type_id));
+joinable!(recurring_leases -> leases (lease_id));
+joinable!(recurring_leases -> recurring_reservations (recurring_reservation_id));
+joinable!(recurring_reservation_tags -> recurring_reservations (recurring_reservation_id));
+joinable!(recurring_reservation_tags -> tag_types (tag_type_id));
+joinable!(recurring_reservations -> asset_types (asset_type_id));
+joinable!(recurring_reservations -> assets (asset_id));
+joinable!(recurring_reservations -> users (user_id));
+joinable!(reservations -> assets (asset_id));
+joinable!(reservations -> leases (lease_id));
 joinable!(tag_types -> asset_types (asset_type_id));
 joinable!(tags -> assets (asset_id));
 joinable!(tags -> tag_types (tag_type_id));
+joinable!(waitlist_entries -> asset_types (asset_type_id));
+joinable!(waitlist_entries -> users (user_id));
+joinable!(waitlist_tags -> tag_types (tag_type_id));
+joinable!(waitlist_tags -> waitlist_entries (waitlist_entry_id));
 
 allow_tables_to_appear_in_same_query!(
+    approvers,
     assets,
     asset_types,
+    ended_leases,
+    feed_tokens,
+    hook_deliveries,
+    lease_field_values,
+    lease_fields,
+    lease_quotas,
+    lease_request_fields,
+    lease_requests,
+    lease_transfers,
+    lease_users,
+    lease_warnings,
     leases,
+    probe_results,
+    prober,
+    probes,
+    recurring_leases,
+    recurring_reservation_tags,
+    recurring_reservations,
+    reservations,
     sheriff,
+    sheriff_failures,
+    sheriff_runs,
     tags,
     tag_types,
     users,
+    waitlist_entries,
+    waitlist_tags,
 );
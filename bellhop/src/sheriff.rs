@@ -1,51 +1,226 @@
 use chrono::prelude::*;
 
+use crate::approvals;
+use crate::db::Db as PubDb;
 use crate::errors::*;
-use crate::hooks::Data as HookData;
-use crate::internal::db::DbPool;
+use crate::hooks::{Data as HookData, Warning};
+use crate::internal::db::{Db, DbPool};
 use crate::internal::hooks::Hooks;
-use crate::models::asset::Asset;
+use crate::models::asset::{Asset, AssetStatus, SetAssetStatusForm};
 use crate::models::asset_type::AssetType;
-use crate::models::lease::Lease;
-use crate::models::sheriff::Sheriff as SheriffModel;
+use crate::models::ended_lease::EndReason;
+use crate::models::lease::{Lease, Reserved};
+use crate::models::lease_field::{FieldValue, LeaseField};
+use crate::models::lease_quota;
+use crate::models::lease_warning::LeaseWarning;
+use crate::models::recurring_reservation::{RecurringReservation, RecurringReservationTag};
+use crate::models::reservation::Reservation;
+use crate::models::sheriff::{
+    RunCounts, Sheriff as SheriffModel, SheriffFailure, SheriffLock, SheriffRun,
+};
+use crate::waitlist;
 
 use diesel;
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 
 use error_chain::ChainedError;
 
+use rand::Rng;
+
+use rocket::config::ConfigError;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
-const PERIOD: Duration = Duration::from_secs(5 * 60);
+/// How far ahead recurring reservations are turned into leases.
+const HORIZON_DAYS: i64 = 7;
 
-struct Deputy {
+/// How long recorded runs and failures are kept.
+const KEEP_LOG_DAYS: i64 = 30;
+
+const EVICT: &str = "evict leases";
+const COOL_DOWN: &str = "end cool-downs";
+const SCHEDULE: &str = "schedule recurring reservations";
+pub(crate) const EXPIRE: &str = "expire lease requests";
+const ACTIVATE: &str = "activate reservations";
+const OVERDUE: &str = "mark overdue leases";
+const WARN: &str = "send warnings";
+const PRUNE: &str = "prune the log";
+
+/// How the sheriff runs, from the `sheriff` table in `Rocket.toml`. Every
+/// setting is optional:
+///
+/// ```toml
+/// [global.sheriff]
+/// period_secs = 300
+/// jitter_secs = 0
+/// warn_before_secs = []
+/// warn_percent = 5
+/// retries = 5
+/// retry_secs = 1
+/// ```
+///
+/// Asset types can override the warning settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    /// Time between runs, in seconds.
+    period_secs: u64,
+
+    /// Up to this many seconds are added to each period at random, so that
+    /// several servers sharing a database don't all wake up at once.
+    jitter_secs: u64,
+
+    /// Warn owners this many seconds before their lease ends, once for each
+    /// entry.
+    warn_before_secs: Vec<u32>,
+
+    /// Warn owners once this percentage of their lease is left. Zero turns
+    /// this warning off.
+    warn_percent: u32,
+
+    /// Try a task this many more times if it fails because of the database.
+    retries: u32,
+
+    /// Wait this many seconds before the first retry, doubling each time, up
+    /// to the period.
+    retry_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            period_secs: 5 * 60,
+            jitter_secs: 0,
+            warn_before_secs: vec![],
+            warn_percent: 5,
+            retries: 5,
+            retry_secs: 1,
+        }
+    }
+}
+
+impl Config {
+    fn period(&self) -> Duration {
+        Duration::from_secs(self.period_secs)
+    }
+
+    /// The time until the next run: the period, plus some jitter.
+    fn next_delay(&self) -> Duration {
+        let jitter_ms = rand::thread_rng().gen_range(0, self.jitter_secs * 1000 + 1);
+
+        self.period() + Duration::from_millis(jitter_ms)
+    }
+
+    /// How long before `lease` ends to warn its owners, in seconds, given the
+    /// types of the assets it holds. Ordered from the earliest warning to the
+    /// last.
+    ///
+    /// No stage is shorter than the longest possible time between runs, so
+    /// the last warning always goes out at least a run before the lease is
+    /// evicted.
+    fn warning_stages<'a, I>(&self, lease: &Lease, types: I) -> Vec<i64>
+    where
+        I: IntoIterator<Item = &'a AssetType>,
+    {
+        let length = match lease.end_time() {
+            Some(x) => x - lease.start_time(),
+            None => return vec![],
+        };
+
+        let overrides = types
+            .into_iter()
+            .map(|x| (x.warn_before_secs(), x.warn_percent()));
+
+        self.stages_for(length, overrides)
+    }
+
+    /// Like `warning_stages`, for a lease lasting `length` that holds assets
+    /// of types with the given `warn_before_secs` and `warn_percent`
+    /// overrides.
+    fn stages_for<'a, I>(&self, length: chrono::Duration, overrides: I) -> Vec<i64>
+    where
+        I: IntoIterator<Item = (Option<&'a [i32]>, Option<i32>)>,
+    {
+        let longest_delay = (self.period_secs + self.jitter_secs) as i64;
+        let mut stages = vec![];
+
+        for (warn_before_secs, warn_percent) in overrides {
+            match warn_before_secs {
+                Some(x) => stages.extend(x.iter().map(|y| i64::from(*y))),
+                None => stages.extend(self.warn_before_secs.iter().map(|y| i64::from(*y))),
+            }
+
+            let percent = warn_percent.unwrap_or(self.warn_percent as i32);
+
+            if percent > 0 {
+                stages.push((length * percent / 100).num_seconds());
+            }
+        }
+
+        let mut stages: Vec<i64> = stages.into_iter().map(|x| x.max(longest_delay)).collect();
+
+        if stages.is_empty() {
+            stages.push(longest_delay);
+        }
+
+        stages.sort_unstable_by(|a, b| b.cmp(a));
+        stages.dedup();
+
+        stages
+    }
+}
+
+/// A connection holding one of the sheriff's locks, which is released when
+/// it's dropped.
+struct Held {
+    conn: Db,
+    lock: SheriffLock,
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        if let Err(e) = SheriffModel::unlock(&*self.conn, self.lock) {
+            eprintln!("sheriff unable to release lock: {}", e.display_chain());
+        }
+    }
+}
+
+/// Runs the sheriff's tasks, whether on schedule or because an admin asked.
+#[derive(Clone)]
+struct Tasks {
     running: Arc<AtomicBool>,
     db_pool: DbPool,
-    deadline: Instant,
     hooks: Hooks,
+    config: Config,
 }
 
-impl Deputy {
-    fn new(db_pool: DbPool, running: Arc<AtomicBool>, hooks: Hooks) -> Self {
-        Deputy {
-            running,
-            db_pool,
-            hooks,
-            deadline: Instant::now() + PERIOD, // TODO: Add jitter.
-        }
+impl fmt::Debug for Tasks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tasks")
+            .field("config", &self.config)
+            .finish()
     }
+}
 
-    fn wait(&self) -> bool {
+impl Tasks {
+    /// Returns `false` if the sheriff was stopped before `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> bool {
         while self.running.load(Ordering::SeqCst) {
             let now = Instant::now();
-            if now >= self.deadline {
+            if now >= deadline {
                 return true;
             }
 
-            let timeout = self.deadline - now;
+            let timeout = deadline - now;
 
             thread::park_timeout(timeout);
         }
@@ -53,36 +228,296 @@ impl Deputy {
         false
     }
 
-    fn run_one(&mut self) -> Result<()> {
-        let conn = self
-            .db_pool
+    fn connect(&self) -> Result<Db> {
+        self.db_pool
             .get()
-            .chain_err(|| "couldn't get database connection")?;
+            .ok_or_else(|| ErrorKind::Unavailable.into())
+    }
+
+    /// Take `lock` on a connection of its own, unless someone else has it.
+    fn try_lock(&self, lock: SheriffLock) -> Result<Option<Held>> {
+        let conn = self.connect()?;
+
+        if SheriffModel::try_lock(&*conn, lock)? {
+            Ok(Some(Held { conn, lock }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Call `f` with a fresh connection, trying again with exponential
+    /// backoff while it fails because of the database.
+    fn retry<T, F>(&self, f: F) -> Result<T>
+    where
+        F: Fn(&PgConnection) -> Result<T>,
+    {
+        let mut delay = Duration::from_secs(self.config.retry_secs);
+        let mut attempt = 0;
+
+        loop {
+            let e = match self.connect().and_then(|c| f(&*c)) {
+                Ok(x) => return Ok(x),
+                Err(e) => e,
+            };
+
+            if attempt >= self.config.retries || !is_transient(&e) {
+                return Err(e);
+            }
+
+            eprintln!(
+                "deputy will try again in {:?}: {}",
+                delay,
+                e.display_chain()
+            );
+
+            if !self.sleep_until(Instant::now() + delay) {
+                return Err(e);
+            }
+
+            attempt += 1;
+            delay = (delay * 2).min(self.config.period());
+        }
+    }
+
+    /// Run `task`, and record its failure, if any, so the other tasks still
+    /// run. Returns how many things the task did.
+    fn run_task<F>(&self, name: &'static str, task: F) -> i32
+    where
+        F: Fn(&PgConnection, &Failures) -> Result<usize>,
+    {
+        let failures = Failures::default();
+
+        let done = match self.retry(|c| task(c, &failures)) {
+            Ok(x) => x as i32,
+            Err(e) => {
+                failures.report(name, None, e);
+                0
+            }
+        };
+
+        self.record(failures);
+
+        done
+    }
+
+    /// Record `failures` once their task is done, on a connection of their
+    /// own, so rolling back the task's transactions doesn't take them along.
+    fn record(&self, failures: Failures) {
+        let failures = failures.0.into_inner();
+
+        if failures.is_empty() {
+            return;
+        }
+
+        let recorded = self.retry(|c| {
+            c.transaction::<_, Error, _>(|| {
+                for (task, lease_id, e) in failures.iter() {
+                    SheriffFailure::record(c, task, *lease_id, e)?;
+                }
+
+                Ok(())
+            })
+        });
 
-        if SheriffModel::should_run(&conn, PERIOD)? {
-            evict(&conn, &self.hooks)?;
-            send_eviction_notices(&conn, &self.hooks)?;
+        if let Err(e) = recorded {
+            eprintln!("sheriff unable to record failures: {}", e.display_chain());
         }
+    }
+
+    /// Run every task once, and log the run. Returns `None`, without doing
+    /// anything, if another run is still going.
+    fn run(&self, manual: bool) -> Result<Option<SheriffRun>> {
+        let _held = match self.try_lock(SheriffLock::Run)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let run = self.retry(|c| SheriffRun::start(c, manual))?;
 
-        Ok(())
+        let evicted = self.run_task(EVICT, |c, f| evict(c, &self.hooks, f));
+        self.run_task(COOL_DOWN, |c, f| end_cool_downs(c, &self.hooks, f));
+
+        let counts = RunCounts {
+            evicted,
+            scheduled: self.run_task(SCHEDULE, |c, f| schedule_recurring(c, &self.hooks, f)),
+            expired: self.run_task(EXPIRE, |c, f| approvals::expire(c, &self.hooks, f)),
+            activated: self.run_task(ACTIVATE, |c, f| activate(c, &self.hooks, f)),
+            overdue: self.run_task(OVERDUE, |c, f| mark_overdue(c, &self.hooks, f)),
+            warned: self.run_task(WARN, |c, f| {
+                send_eviction_notices(c, &self.hooks, &self.config, f)
+            }),
+        };
+
+        self.run_task(PRUNE, |c, _| {
+            let before = Utc::now() - chrono::Duration::days(KEEP_LOG_DAYS);
+
+            SheriffFailure::prune(c, before)?;
+            SheriffRun::prune(c, before)?;
+
+            Ok(0)
+        });
+
+        let finished = self.retry(|c| run.finish(c, &counts))?;
+
+        Ok(Some(finished))
+    }
+}
+
+/// Only the deputy holding the lead runs the tasks on schedule. The others
+/// stand by, trying to take over every period, which they can do as soon as
+/// the leader's connection to the database is closed.
+struct Deputy {
+    tasks: Tasks,
+    deadline: Instant,
+    leadership: Option<Held>,
+}
+
+impl Deputy {
+    fn new(tasks: Tasks) -> Self {
+        Deputy {
+            deadline: Instant::now() + tasks.config.next_delay(),
+            tasks,
+            leadership: None,
+        }
+    }
+
+    fn wait(&self) -> bool {
+        self.tasks.sleep_until(self.deadline)
+    }
+
+    /// Returns `true` if this deputy is the leader, taking the lead if nobody
+    /// else has it.
+    fn lead(&mut self) -> Result<bool> {
+        if let Some(ref x) = self.leadership {
+            match SheriffModel::check_lock(&*x.conn) {
+                Ok(()) => return Ok(true),
+                Err(e) => eprintln!("deputy lost the lead: {}", e.display_chain()),
+            }
+        }
+
+        self.leadership = None;
+
+        let held = match self.tasks.try_lock(SheriffLock::Leader)? {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+
+        println!("This sheriff is now the leader.");
+        self.leadership = Some(held);
+
+        Ok(true)
+    }
+
+    fn run_one(&mut self) {
+        match self.lead() {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => {
+                eprintln!("deputy unable to take the lead: {}", e.display_chain());
+                return;
+            }
+        }
+
+        let period = self.tasks.config.period();
+        let should_run = self.tasks.retry(|c| SheriffModel::should_run(c, period));
+
+        match should_run {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => {
+                eprintln!("deputy unable to run: {}", e.display_chain());
+                return;
+            }
+        }
+
+        match self.tasks.run(false) {
+            Ok(Some(_)) => (),
+            Ok(None) => println!("The sheriff skipped a run, since another is still going."),
+            Err(e) => eprintln!("deputy unable to run: {}", e.display_chain()),
+        }
+    }
+
+    /// Let everyone know when the leader will run next.
+    fn publish_deadline(&self) {
+        let now = Instant::now();
+        let delay = if self.deadline > now {
+            self.deadline - now
+        } else {
+            Duration::from_secs(0)
+        };
+
+        let next_run = Utc::now() + chrono::Duration::milliseconds(delay.as_millis() as i64);
+
+        if let Err(e) = self.tasks.retry(|c| SheriffModel::schedule(c, next_run)) {
+            eprintln!("deputy unable to publish next run: {}", e.display_chain());
+        }
     }
 
     fn run(mut self) {
         while self.wait() {
-            if let Err(e) = self.run_one() {
-                eprintln!("deputy unable to run: {}", e.display_chain());
-                std::process::exit(1);
+            // Whatever happens, the web server keeps going, and the deputy
+            // tries again next period.
+            let ran = panic::catch_unwind(AssertUnwindSafe(|| self.run_one()));
+
+            if ran.is_err() {
+                eprintln!("deputy panicked; trying again next period");
+            }
+
+            self.deadline += self.tasks.config.next_delay();
+
+            // Don't try to catch up on runs that were missed while retrying.
+            let now = Instant::now();
+            if self.deadline < now {
+                self.deadline = now + self.tasks.config.next_delay();
             }
 
-            self.deadline += PERIOD;
+            if self.leadership.is_some() {
+                self.publish_deadline();
+            }
         }
     }
 }
 
+/// What a run would do right now, without doing it.
+#[derive(Debug, Serialize)]
+pub struct DryRun {
+    evict: Vec<WouldEvict>,
+    overdue: Vec<WouldMarkOverdue>,
+    warn: Vec<WouldWarn>,
+}
+
+/// A lease that a run would end.
+#[derive(Debug, Serialize)]
+pub struct WouldEvict {
+    lease_id: i32,
+    asset_ids: Vec<i32>,
+    reason: EndReason,
+}
+
+/// A lease that a run would mark as overdue.
+#[derive(Debug, Serialize)]
+pub struct WouldMarkOverdue {
+    lease_id: i32,
+    asset_ids: Vec<i32>,
+    evict_at: DateTime<Utc>,
+}
+
+/// A warning that a run would send.
+#[derive(Debug, Serialize)]
+pub struct WouldWarn {
+    lease_id: i32,
+    asset_ids: Vec<i32>,
+    stage: usize,
+    stages: usize,
+    before_secs: i64,
+    remaining_secs: i64,
+}
+
 #[derive(Debug)]
 pub(crate) struct Sheriff {
     running: Arc<AtomicBool>,
     handle: Option<JoinHandle<()>>,
+    tasks: Tasks,
 }
 
 impl Drop for Sheriff {
@@ -97,10 +532,17 @@ impl Drop for Sheriff {
 }
 
 impl Sheriff {
-    fn new(db_pool: DbPool, hooks: Hooks) -> Self {
+    fn new(db_pool: DbPool, hooks: Hooks, config: Config) -> Self {
         let running = Arc::new(AtomicBool::new(true));
 
-        let deputy = Deputy::new(db_pool, running.clone(), hooks);
+        let tasks = Tasks {
+            running: running.clone(),
+            db_pool,
+            hooks,
+            config,
+        };
+
+        let deputy = Deputy::new(tasks.clone());
 
         let handle = thread::Builder::new()
             .name("sheriff".into())
@@ -110,9 +552,62 @@ impl Sheriff {
         Sheriff {
             running,
             handle: Some(handle),
+            tasks,
         }
     }
 
+    /// Time between scheduled runs, not counting jitter.
+    pub(crate) fn period(&self) -> Duration {
+        self.tasks.config.period()
+    }
+
+    /// Run every task right away, whether this replica is the leader or not.
+    /// Returns `None` if another run is still going.
+    pub(crate) fn run_now(&self) -> Result<Option<SheriffRun>> {
+        self.tasks.run(true)
+    }
+
+    /// Work out what a run would do right now, without doing it.
+    pub(crate) fn dry_run(&self, c: &PgConnection) -> Result<DryRun> {
+        let now = Utc::now();
+
+        let evict = due_evictions(c, now)?
+            .into_iter()
+            .map(|x| WouldEvict {
+                lease_id: x.lease.id(),
+                asset_ids: x.asset_ids(),
+                reason: x.what,
+            })
+            .collect();
+
+        let overdue = due_overdue(c, now)?
+            .into_iter()
+            .map(|x| WouldMarkOverdue {
+                lease_id: x.lease.id(),
+                asset_ids: x.asset_ids(),
+                evict_at: x.what,
+            })
+            .collect();
+
+        let warn = due_warnings(c, &self.tasks.config, now)?
+            .into_iter()
+            .map(|x| WouldWarn {
+                lease_id: x.lease.id(),
+                asset_ids: x.asset_ids(),
+                stage: x.what.stage(),
+                stages: x.what.stages(),
+                before_secs: x.what.before().num_seconds(),
+                remaining_secs: x.what.remaining().num_seconds(),
+            })
+            .collect();
+
+        Ok(DryRun {
+            evict,
+            overdue,
+            warn,
+        })
+    }
+
     /// Returns a fairing that handles periodically evicting expired leases.
     /// Must be called after attaching the database fairing.
     pub fn fairing() -> impl ::rocket::fairing::Fairing {
@@ -127,58 +622,281 @@ impl Sheriff {
                 None => return Err(rocket),
             };
 
-            let sheriff = Self::new(pool.clone(), hooks.clone());
+            let config = match rocket.config().get_extra("sheriff") {
+                Ok(x) => match x.clone().try_into() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        eprintln!("invalid sheriff configuration: {}", e);
+                        return Err(rocket);
+                    }
+                },
+                Err(ConfigError::Missing(_)) => Config::default(),
+                Err(e) => {
+                    eprintln!("invalid sheriff configuration: {}", e);
+                    return Err(rocket);
+                }
+            };
+
+            let sheriff = Self::new(pool.clone(), hooks.clone(), config);
 
             Ok(rocket.manage(sheriff))
         })
     }
 }
 
-/// NB: Not really safe to run two copies of this at the same time.
-fn send_eviction_notices(c: &PgConnection, hooks: &Hooks) -> Result<()> {
+/// Returns `true` if `e` was caused by losing the database, or by racing
+/// another transaction, so trying again might work. Anything else, like a
+/// failed hook or a broken constraint, would only fail the same way again.
+fn is_transient(e: &Error) -> bool {
+    let mut cause: Option<&(dyn StdError + 'static)> = Some(e);
+
+    while let Some(x) = cause {
+        if x.is::<crate::hooks::Error>() {
+            return false;
+        }
+
+        if let Some(x) = x.downcast_ref::<DieselError>() {
+            return is_transient_diesel(x);
+        }
+
+        if let Some(x) = x.downcast_ref::<Error>() {
+            match x.kind() {
+                ErrorKind::Diesel(x) => return is_transient_diesel(x),
+                ErrorKind::Unavailable => return true,
+                _ => (),
+            }
+        }
+
+        cause = x.source();
+    }
+
+    false
+}
+
+fn is_transient_diesel(e: &DieselError) -> bool {
+    match e {
+        DieselError::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _)
+        | DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => true,
+
+        // Diesel doesn't have kinds for deadlocks or lost connections, so
+        // they can only be told apart by what the server said.
+        DieselError::DatabaseError(_, info) => {
+            let message = info.message();
+
+            message.contains("deadlock detected")
+                || message.contains("server closed the connection")
+                || message.contains("no connection to the server")
+                || message.contains("terminating connection")
+                || message.contains("could not receive data from server")
+        }
+
+        _ => false,
+    }
+}
+
+/// What went wrong while running a task, to be recorded as `SheriffFailure`s
+/// once it's done.
+#[derive(Default)]
+pub(crate) struct Failures(RefCell<Vec<(&'static str, Option<i32>, Error)>>);
+
+impl Failures {
+    /// Log `e`, and keep it to record later.
+    fn report(&self, task: &'static str, lease_id: Option<i32>, e: Error) {
+        eprintln!("sheriff failed to {}: {}", task, e.display_chain());
+
+        self.0.borrow_mut().push((task, lease_id, e));
+    }
+}
+
+/// Add `result`'s error, if any, to `failures`, so the rest of `task` can
+/// carry on without it. Transient errors are passed on instead, so the whole
+/// task is tried again.
+///
+/// `result` should come from a transaction or savepoint of its own, so the
+/// failure is rolled back before the task carries on.
+pub(crate) fn isolate<T>(
+    failures: &Failures,
+    task: &'static str,
+    lease_id: Option<i32>,
+    result: Result<T>,
+) -> Result<Option<T>> {
+    match result {
+        Ok(x) => Ok(Some(x)),
+        Err(e) if is_transient(&e) => Err(e),
+        Err(e) => {
+            failures.report(task, lease_id, e);
+            Ok(None)
+        }
+    }
+}
+
+/// A lease the sheriff has something to do about, the assets it holds, and
+/// `what` to do.
+struct Due<T> {
+    lease: Lease,
+    assets: Vec<(Asset, AssetType)>,
+    what: T,
+}
+
+impl<T> Due<T> {
+    fn asset_ids(&self) -> Vec<i32> {
+        self.assets.iter().map(|(x, _)| x.id()).collect()
+    }
+}
+
+/// When a lease that ends at `end_time`, holding `assets`, should be evicted:
+/// once the longest grace period of their types has run out.
+fn evict_at(end_time: DateTime<Utc>, assets: &[(Asset, AssetType)]) -> DateTime<Utc> {
+    let grace_secs = assets
+        .iter()
+        .map(|(_, x)| x.grace_secs())
+        .max()
+        .unwrap_or(0);
+
+    end_time + chrono::Duration::seconds(grace_secs.into())
+}
+
+/// Leases with a warning stage that's due, and hasn't been sent yet.
+///
+/// If several stages are due at once, only the last of them is returned.
+fn due_warnings(
+    c: &PgConnection,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<Vec<Due<Warning>>> {
     use crate::schema::asset_types::dsl as at;
     use crate::schema::leases::dsl as l;
 
-    let now = Utc::now();
-
     let all_leases: Vec<Lease> = l::leases
-        .filter(l::last_notified.is_null())
         .filter(l::end_time.is_not_null())
         .load::<Lease>(c)
         .chain_err(|| "failed to get leases for eviction notices")?;
 
-    for lease in all_leases {
-        let end_time = lease.end_time().unwrap();
-        let time_left = end_time - lease.start_time();
-        let margin = time_left / 20;
+    let assets: Vec<Vec<(Asset, AssetType)>> = Asset::belonging_to(&all_leases)
+        .inner_join(at::asset_types)
+        .load::<(Asset, AssetType)>(c)
+        .chain_err(|| "unable to get assets and asset types for leases")?
+        .grouped_by(&all_leases);
+
+    let warnings: Vec<Vec<LeaseWarning>> = LeaseWarning::belonging_to(&all_leases)
+        .load::<LeaseWarning>(c)
+        .chain_err(|| "unable to get warnings for leases")?
+        .grouped_by(&all_leases);
 
-        if now > (end_time - margin) {
-            // TODO: This is an N+1 queries bug
-            let (asset, asset_type): (Asset, AssetType) = Asset::belonging_to(&lease)
-                .inner_join(at::asset_types)
-                .get_result(c)
-                .chain_err(|| "unable to get asset and asset type for lease")?;
+    let mut due = vec![];
+
+    for ((lease, found), warnings) in all_leases.into_iter().zip(assets).zip(warnings) {
+        // Reservations that haven't been activated yet don't own an asset.
+        if found.is_empty() {
+            continue;
+        }
+
+        let remaining = lease.end_time().unwrap() - now;
+        let stages = config.warning_stages(&lease, found.iter().map(|x| &x.1));
+
+        // The stages are ordered, so the ones that are due come first.
+        let stage = match stages
+            .iter()
+            .rposition(|x| remaining < chrono::Duration::seconds(*x))
+        {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let before = stages[stage];
+
+        if warnings.iter().any(|x| x.covers(before)) {
+            continue;
+        }
+
+        let warning = Warning::new(
+            stage,
+            stages.len(),
+            chrono::Duration::seconds(before),
+            remaining,
+        );
+
+        due.push(Due {
+            lease,
+            assets: found,
+            what: warning,
+        });
+    }
+
+    Ok(due)
+}
+
+/// Warn the owners of leases that are about to end, once for each warning
+/// stage that's due.
+///
+/// NB: Not safe to run two copies of this at the same time, which is why it's
+/// only run while holding the run lock.
+fn send_eviction_notices(
+    c: &PgConnection,
+    hooks: &Hooks,
+    config: &Config,
+    failures: &Failures,
+) -> Result<usize> {
+    use crate::schema::leases::dsl as l;
+
+    let now = Utc::now();
+
+    let due = due_warnings(c, config, now)?;
+    let num_warned = due.len();
+
+    for Due {
+        lease,
+        assets,
+        what: warning,
+    } in due
+    {
+        let fields = FieldValue::for_lease(c, lease.id())?;
 
-            let data = HookData::new(&lease, &asset, &asset_type);
+        // The warning is queued in the same transaction that marks it as
+        // sent, so it goes out exactly once.
+        c.transaction::<_, Error, _>(|| {
+            // A failed hook doesn't stop the warning from being marked as
+            // sent, since the other hooks have already been called.
+            for (asset, asset_type) in assets.iter() {
+                let data = HookData::new(&lease, asset, asset_type)
+                    .with_fields(&fields)
+                    .with_warning(&warning);
 
-            hooks.warned(c, data)?;
+                let warned = c.transaction(|| hooks.warned(c, data));
+                isolate(failures, WARN, Some(lease.id()), warned)?;
+            }
+
+            LeaseWarning::insert(c, lease.id(), warning.before().num_seconds() as i32)?;
 
             diesel::update(&lease)
                 .set(l::last_notified.eq(Some(now)))
                 .execute(c)
                 .chain_err(|| "unable to set last notified for lease")?;
-        }
+
+            Ok(())
+        })?;
     }
-    Ok(())
+
+    Ok(num_warned)
 }
 
-fn evict(c: &PgConnection, hooks: &Hooks) -> Result<()> {
+/// Leases whose grace period ran out, or whose holders stopped sending
+/// heartbeats, and why they should end.
+fn due_evictions(c: &PgConnection, now: DateTime<Utc>) -> Result<Vec<Due<EndReason>>> {
     use crate::schema::asset_types::dsl as at;
     use crate::schema::leases::dsl as l;
 
+    // https://github.com/diesel-rs/diesel/issues/1514
+    let abandoned = diesel::dsl::sql::<diesel::sql_types::Bool>(
+        "COALESCE(last_heartbeat, start_time) + heartbeat_secs * interval '1 second' < now()",
+    );
+
     let to_delete: Vec<Lease> = l::leases
-        .for_update()
-        .filter(l::end_time.lt(Utc::now()))
+        .filter(
+            l::end_time
+                .lt(now)
+                .or(l::heartbeat_secs.is_not_null().and(abandoned)),
+        )
         .load(c)
         .chain_err(|| "sheriff was unable to get leases")?;
 
@@ -188,27 +906,531 @@ fn evict(c: &PgConnection, hooks: &Hooks) -> Result<()> {
         .chain_err(|| "sheriff was unable to get asset and type information")?
         .grouped_by(&to_delete);
 
-    let to_delete_ids: Vec<_> = to_delete.iter().map(|x| x.id()).collect();
+    let due = to_delete
+        .into_iter()
+        .zip(assets)
+        .filter_map(|(lease, assets)| {
+            let expired = lease
+                .end_time()
+                .map_or(false, |x| evict_at(x, &assets) < now);
+            let abandoned = lease.heartbeat_due().map_or(false, |x| x < now);
+
+            // Leases that ran out of time are evicted, even if they also
+            // stopped sending heartbeats. Leases in their grace period are
+            // only overdue, unless they were abandoned.
+            let reason = if expired {
+                EndReason::Evicted
+            } else if abandoned {
+                EndReason::Abandoned
+            } else {
+                return None;
+            };
+
+            Some(Due {
+                lease,
+                assets,
+                what: reason,
+            })
+        })
+        .collect();
+
+    Ok(due)
+}
+
+fn evict(c: &PgConnection, hooks: &Hooks, failures: &Failures) -> Result<usize> {
+    let mut num_evicted = 0;
+
+    for due in due_evictions(c, Utc::now())? {
+        let Due {
+            lease,
+            assets: held,
+            what: reason,
+        } = due;
+
+        // Field values and co-owners are deleted along with the lease.
+        let fields = FieldValue::for_lease(c, lease.id())?;
+        let owners = lease.owners(&PubDb::from(c))?;
+
+        // Assets start cooling down in the same transaction, so nobody can
+        // lease them in between. Hooks are queued in it too.
+        let ended = c.transaction::<_, Error, _>(|| {
+            let ended = match lease.end(c, reason)? {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            let mut assets = Vec::with_capacity(held.len());
+
+            for (asset, asset_type) in held.iter() {
+                let cooldown = chrono::Duration::seconds(asset_type.cooldown_secs().into());
+
+                // Someone may have changed the status since the assets were
+                // loaded, like taking the asset out for maintenance, so only
+                // start cooling down if it's still available.
+                let asset = Asset::lock(c, asset.id())?.chain_err(|| "missing asset")?;
 
-    let num_deleted_rows = diesel::delete(l::leases)
-        .filter(l::id.eq_any(&to_delete_ids))
-        .execute(c)
-        .chain_err(|| "sheriff was unable to delete leases")?;
+                let asset = if asset.is_available() && cooldown > chrono::Duration::zero() {
+                    let form = SetAssetStatusForm::cool_down(Utc::now() + cooldown);
+                    asset.set_status(c, &form)?
+                } else {
+                    asset
+                };
+
+                assets.push(asset);
+            }
+
+            // The lease is over either way, so a failed hook shouldn't stop
+            // the others.
+            for (asset, (_, asset_type)) in assets.iter().zip(held.iter()) {
+                let mut data = HookData::new(&lease, asset, asset_type)
+                    .with_fields(&fields)
+                    .with_owners(&owners);
+                if let Some(x) = ended.iter().find(|x| x.asset_id() == asset.id()) {
+                    data = data.with_ended(x);
+                }
+
+                let notified = c
+                    .transaction(|| hooks.evicted(c, data))
+                    .chain_err(|| "sheriff encountered an error while sending hooks");
+                isolate(failures, EVICT, Some(lease.id()), notified)?;
+            }
+
+            Ok(Some(assets))
+        });
+
+        let assets = match ended.chain_err(|| "sheriff was unable to end lease")? {
+            Some(x) => x,
+            None => continue,
+        };
+
+        num_evicted += 1;
+
+        for asset in assets.into_iter() {
+            // Assets that are cooling down are handed off once they're done.
+            if !asset.is_available() {
+                continue;
+            }
+
+            let handed_off = waitlist::hand_off(c, hooks, &asset)
+                .chain_err(|| "sheriff was unable to hand off asset");
+            isolate(failures, EVICT, Some(lease.id()), handed_off)?;
+        }
+    }
 
     println!(
         "The sheriff successfully evicted {:?} occupants.",
-        num_deleted_rows,
+        num_evicted
+    );
+
+    Ok(num_evicted)
+}
+
+/// Leases past their end time that are still in their grace period, and
+/// haven't been marked as overdue yet, with when they'll be evicted.
+fn due_overdue(c: &PgConnection, now: DateTime<Utc>) -> Result<Vec<Due<DateTime<Utc>>>> {
+    use crate::schema::asset_types::dsl as at;
+    use crate::schema::leases::dsl as l;
+
+    let expired: Vec<Lease> = l::leases
+        .filter(l::end_time.lt(now))
+        .filter(l::overdue_at.is_null())
+        .load(c)
+        .chain_err(|| "sheriff was unable to get overdue leases")?;
+
+    let assets: Vec<Vec<(Asset, AssetType)>> = Asset::belonging_to(&expired)
+        .inner_join(at::asset_types)
+        .load::<(Asset, AssetType)>(c)
+        .chain_err(|| "sheriff was unable to get asset and type information")?
+        .grouped_by(&expired);
+
+    let due = expired
+        .into_iter()
+        .zip(assets)
+        // Reservations that never got their asset aren't holding anything up.
+        .filter(|(_, assets)| !assets.is_empty())
+        .map(|(lease, assets)| {
+            let at = evict_at(lease.end_time().unwrap(), &assets);
+
+            Due {
+                lease,
+                assets,
+                what: at,
+            }
+        })
+        .filter(|x| x.what >= now)
+        .collect();
+
+    Ok(due)
+}
+
+/// Tell the owners of leases that are past their end time, but still in their
+/// grace period, that they're overdue.
+fn mark_overdue(c: &PgConnection, hooks: &Hooks, failures: &Failures) -> Result<usize> {
+    let mut num_overdue = 0;
+
+    for due in due_overdue(c, Utc::now())? {
+        let fields = FieldValue::for_lease(c, due.lease.id())?;
+
+        // Hooks are queued in the same transaction that marks the lease, so
+        // they hear about it exactly once.
+        let marked = c.transaction::<_, Error, _>(|| {
+            let lease = match due.lease.mark_overdue(c)? {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+
+            for (asset, asset_type) in due.assets.iter() {
+                let data = HookData::new(&lease, asset, asset_type)
+                    .with_fields(&fields)
+                    .with_evict_at(due.what);
+
+                let notified = c.transaction(|| hooks.overdue(c, data));
+                isolate(failures, OVERDUE, Some(lease.id()), notified)?;
+            }
+
+            Ok(true)
+        })?;
+
+        if marked {
+            num_overdue += 1;
+        }
+    }
+
+    Ok(num_overdue)
+}
+
+/// Put assets back into service once they're done cooling down, and hand them
+/// to anyone waiting for one.
+fn end_cool_downs(c: &PgConnection, hooks: &Hooks, failures: &Failures) -> Result<usize> {
+    use crate::schema::assets::dsl as a;
+
+    let now = Utc::now();
+
+    let rested: Vec<Asset> = a::assets
+        .filter(a::status.eq(AssetStatus::CoolingDown))
+        .filter(a::expected_back.is_null().or(a::expected_back.le(now)))
+        .load(c)
+        .chain_err(|| "sheriff was unable to get assets that are cooling down")?;
+
+    let mut num_rested = 0;
+
+    for asset in rested {
+        // Unless an admin changed the status in the meantime.
+        let to_update = a::assets
+            .filter(a::id.eq(asset.id()))
+            .filter(a::status.eq(AssetStatus::CoolingDown));
+
+        let updated: Option<Asset> = diesel::update(to_update)
+            .set((
+                a::status.eq(AssetStatus::Available),
+                a::status_reason.eq(None::<String>),
+                a::expected_back.eq(None::<DateTime<Utc>>),
+            ))
+            .get_result(c)
+            .optional()
+            .chain_err(|| "sheriff was unable to end cool-down")?;
+
+        let updated = match updated {
+            Some(x) => x,
+            None => continue,
+        };
+
+        num_rested += 1;
+
+        let handed_off = waitlist::hand_off(c, hooks, &updated)
+            .chain_err(|| "sheriff was unable to hand off asset");
+        isolate(failures, COOL_DOWN, None, handed_off)?;
+    }
+
+    Ok(num_rested)
+}
+
+/// Give assets to reservations that have started.
+///
+/// Assets that are out of service are left alone, and their reservations
+/// wait for them to come back until they expire.
+fn activate(c: &PgConnection, hooks: &Hooks, failures: &Failures) -> Result<usize> {
+    use crate::schema::assets::dsl as a;
+    use crate::schema::reservations::dsl as r;
+
+    let now = Utc::now();
+
+    let starting: Vec<Reservation> = r::reservations
+        .inner_join(a::assets)
+        .filter(a::lease_id.is_null())
+        .filter(a::status.eq(AssetStatus::Available))
+        .filter(r::start_time.le(now))
+        .filter(r::end_time.is_null().or(r::end_time.gt(now)))
+        .select(crate::schema::reservations::all_columns)
+        .load(c)
+        .chain_err(|| "sheriff was unable to get starting reservations")?;
+
+    let mut num_activated = 0;
+
+    for reservation in starting {
+        // Hooks are queued in the same transaction that hands over the asset.
+        let activated = c.transaction::<_, Error, _>(|| {
+            let to_update = a::assets
+                .filter(a::id.eq(reservation.asset_id()))
+                .filter(a::lease_id.is_null())
+                .filter(a::status.eq(AssetStatus::Available));
+
+            let updated: Option<Asset> = diesel::update(to_update)
+                .set(a::lease_id.eq(Some(reservation.lease_id())))
+                .get_result(c)
+                .optional()
+                .chain_err(|| "sheriff was unable to activate reservation")?;
+
+            let asset = match updated {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+
+            // TODO: This is an N+1 queries bug
+            let lease = Lease::by_id(c, reservation.lease_id())?.chain_err(|| "missing lease")?;
+            let asset_type =
+                AssetType::by_id(c, asset.type_id())?.chain_err(|| "missing asset_type")?;
+
+            let fields = FieldValue::for_lease(c, lease.id())?;
+
+            let data = HookData::new(&lease, &asset, &asset_type).with_fields(&fields);
+
+            let notified = c
+                .transaction(|| hooks.leased(c, data))
+                .chain_err(|| "sheriff encountered an error while sending hooks");
+            isolate(failures, ACTIVATE, Some(lease.id()), notified)?;
+
+            Ok(true)
+        })?;
+
+        if activated {
+            num_activated += 1;
+        }
+    }
+
+    Ok(num_activated)
+}
+
+/// Turn the occurrences of each recurring reservation that start within the
+/// next week into upcoming leases.
+///
+/// Occurrences that can't be scheduled, because no candidate asset is free
+/// or a quota or hook refuses them, or the asset type requires lease fields or
+/// approval, are skipped rather than retried. The last one skipped is
+/// recorded on the rule, so its owner can find out.
+fn schedule_recurring(c: &PgConnection, hooks: &Hooks, failures: &Failures) -> Result<usize> {
+    let now = Utc::now();
+    let until = now + chrono::Duration::days(HORIZON_DAYS);
+
+    let mut num_scheduled = 0;
+
+    for rule in RecurringReservation::all(c)? {
+        let from = rule.scheduled_until().map_or(now, |x| x.max(now));
+
+        let wanted: Vec<_> = RecurringReservationTag::belonging_to(&rule)
+            .load::<RecurringReservationTag>(c)
+            .chain_err(|| "sheriff was unable to get recurring reservation tags")?
+            .iter()
+            .map(RecurringReservationTag::to_wanted_tag)
+            .collect();
+
+        let candidates = rule.candidates(c, &wanted)?;
+
+        let asset_type =
+            AssetType::by_id(c, rule.asset_type_id())?.chain_err(|| "missing asset_type")?;
+
+        // Recurring reservations have no way to fill in lease fields, so
+        // types that require any can't be scheduled.
+        let lease_fields = LeaseField::for_types(c, &[asset_type.id()])?;
+        let fields = LeaseField::resolve(&lease_fields, &BTreeMap::new());
+
+        for start in rule.occurrences(c, from, until)? {
+            // The type may have started to require approval after the rule
+            // was made, and nobody approved its leases.
+            if asset_type.requires_approval() {
+                skip(c, &rule, start, "asset type requires approval")?;
+                continue;
+            }
+
+            let fields = match fields {
+                Some(ref x) => x,
+                None => {
+                    skip(c, &rule, start, "asset type requires lease fields")?;
+                    continue;
+                }
+            };
+
+            let scheduled =
+                schedule_occurrence(c, hooks, &rule, &asset_type, &candidates, fields, start);
+
+            if let Some(true) = isolate(failures, SCHEDULE, None, scheduled)? {
+                num_scheduled += 1;
+            }
+        }
+
+        rule.mark_scheduled(c, until)?;
+    }
+
+    println!(
+        "The sheriff successfully scheduled {:?} recurring reservations.",
+        num_scheduled
+    );
+
+    Ok(num_scheduled)
+}
+
+/// Lease the first of `candidates` that's free for the occurrence of `rule`
+/// starting at `start`. Returns `false` if none of them could be leased.
+fn schedule_occurrence(
+    c: &PgConnection,
+    hooks: &Hooks,
+    rule: &RecurringReservation,
+    asset_type: &AssetType,
+    candidates: &[Asset],
+    fields: &[FieldValue],
+    start: DateTime<Utc>,
+) -> Result<bool> {
+    let create = rule.to_create_lease(start);
+    let mut refusal = None;
+
+    for asset in candidates {
+        // The hooks run inside the transaction, so they can cancel the lease.
+        let result = c.transaction::<_, Error, _>(|| {
+            let (lease, started) = match create.reserve(c, asset.id(), fields)? {
+                Reserved::Started(lease, asset) => (lease, Some(asset)),
+                Reserved::Upcoming(lease) => (lease, None),
+                Reserved::Conflict | Reserved::Invalid => return Ok(false),
+            };
+
+            rule.link(c, &lease)?;
+
+            lease_quota::enforce(c, &lease, &[asset_type.id()])?;
+
+            let fields = FieldValue::for_lease(c, lease.id())?;
+
+            let held = started.as_ref().unwrap_or(asset);
+            let data = HookData::new(&lease, held, asset_type).with_fields(&fields);
+
+            hooks.before_lease(c, data.clone())?;
+
+            if started.is_some() {
+                hooks.leased(c, data)?;
+            }
+
+            Ok(true)
+        });
+
+        match result {
+            Ok(true) => return Ok(true),
+            Ok(false) => continue,
+            Err(e) => match e.kind() {
+                ErrorKind::Rejected(_) | ErrorKind::QuotaExceeded(_) => refusal = Some(e),
+                _ => return Err(e),
+            },
+        }
+    }
+
+    match refusal {
+        Some(e) => skip(c, rule, start, &e.to_string())?,
+        None => skip(c, rule, start, "no assets are free")?,
+    }
+
+    Ok(false)
+}
+
+/// Log that the occurrence of `rule` starting at `start` was skipped, and
+/// record it on the rule.
+fn skip(
+    c: &PgConnection,
+    rule: &RecurringReservation,
+    start: DateTime<Utc>,
+    reason: &str,
+) -> Result<()> {
+    println!(
+        "The sheriff skipped recurring reservation id {} at {}: {}.",
+        rule.id(),
+        start,
+        reason
     );
 
-    for (lease, assets) in to_delete.into_iter().zip(assets) {
-        for (asset, asset_type) in assets.into_iter() {
-            let data = HookData::new(&lease, &asset, &asset_type);
+    rule.mark_skipped(c, start, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            hooks
-                .evicted(c, data)
-                .chain_err(|| "sheriff encountered an error while sending hooks")?;
+    fn config(warn_before_secs: Vec<u32>, warn_percent: u32) -> Config {
+        Config {
+            period_secs: 300,
+            jitter_secs: 30,
+            warn_before_secs,
+            warn_percent,
+            ..Config::default()
         }
     }
 
-    Ok(())
+    fn hours(x: i64) -> chrono::Duration {
+        chrono::Duration::hours(x)
+    }
+
+    #[test]
+    fn stages_from_config() {
+        let config = config(vec![3600, 86400, 600], 0);
+        let stages = config.stages_for(hours(48), vec![(None, None)]);
+
+        assert_eq!(stages, vec![86400, 3600, 600]);
+    }
+
+    #[test]
+    fn stages_clamped_to_longest_delay() {
+        let config = config(vec![3600, 60], 0);
+        let stages = config.stages_for(hours(48), vec![(None, None)]);
+
+        // Period plus jitter.
+        assert_eq!(stages, vec![3600, 330]);
+    }
+
+    #[test]
+    fn stages_deduplicated() {
+        let config = config(vec![3600, 60, 120], 0);
+        let overrides: &[i32] = &[3600, 1800];
+
+        let stages = config.stages_for(
+            hours(48),
+            vec![(None, None), (Some(overrides), None), (None, None)],
+        );
+
+        assert_eq!(stages, vec![3600, 1800, 330]);
+    }
+
+    #[test]
+    fn stages_with_percent() {
+        let config = config(vec![], 5);
+
+        // 5% of ten hours is half an hour.
+        let stages = config.stages_for(hours(10), vec![(None, None)]);
+        assert_eq!(stages, vec![1800]);
+
+        // Asset types can turn the percentage off, or change it.
+        let stages = config.stages_for(hours(10), vec![(None, Some(0))]);
+        assert_eq!(stages, vec![330]);
+
+        let stages = config.stages_for(hours(10), vec![(None, Some(10))]);
+        assert_eq!(stages, vec![3600]);
+    }
+
+    #[test]
+    fn stages_never_empty() {
+        let config = config(vec![], 0);
+        let none: &[i32] = &[];
+        let no_types: Vec<(Option<&[i32]>, Option<i32>)> = vec![];
+
+        assert_eq!(config.stages_for(hours(1), vec![(None, None)]), vec![330]);
+        assert_eq!(
+            config.stages_for(hours(1), vec![(Some(none), None)]),
+            vec![330]
+        );
+        assert_eq!(config.stages_for(hours(1), no_types), vec![330]);
+    }
 }
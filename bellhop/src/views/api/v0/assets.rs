@@ -1,13 +1,26 @@
+use crate::approvals;
 use crate::errors::*;
 use crate::hooks::Data as HookData;
 use crate::internal::db::Db;
 use crate::internal::hooks::Hooks;
 use crate::internal::uri::Base;
-use crate::models::asset::{Asset, CreateAsset};
+use crate::models::asset::{Asset, AssetStatus, CreateAsset, SetAssetStatusForm};
 use crate::models::asset_type::AssetType;
-use crate::models::lease::{CreateLeaseForm, Lease};
+use crate::models::ended_lease::EndedLease;
+use crate::models::lease::{
+    CreateLeaseForm, ExtendLeaseForm, Extended, Lease, Reserved, RevokeLeaseForm, TransferLeaseForm,
+};
+use crate::models::lease_field::{FieldValue, LeaseField};
+use crate::models::lease_quota;
+use crate::models::lease_request::LeaseRequest;
+use crate::models::lease_transfer::{CreateLeaseTransfer, LeaseTransfer};
+use crate::models::probe::ProbeResult;
+use crate::models::reservation::Reservation;
 use crate::models::tag::{CreateOwnedTag, Tag};
 use crate::models::user::User;
+use crate::waitlist;
+
+use chrono::prelude::*;
 
 use diesel::prelude::*;
 
@@ -19,7 +32,8 @@ use rocket_contrib::json::Json;
 
 use std::result::Result as StdResult;
 
-use super::Paged;
+use super::leases::ReleaseResponse;
+use super::{Paged, Refused};
 
 #[get("/", format = "application/json")]
 pub fn list(db: Db, _user: User) -> Result<Json<Paged<Asset>>> {
@@ -213,6 +227,16 @@ pub(crate) enum CreateLeaseResponse {
     #[response(status = 201)]
     Success(Json<Lease>),
 
+    /// The asset type requires approval, so a request was made instead.
+    #[response(status = 202)]
+    Requested(Json<LeaseRequest>),
+
+    /// The asset is in maintenance or retired.
+    #[response(status = 409)]
+    OutOfService(Json<Asset>),
+
+    Refused(Refused),
+
     Status(Status),
 }
 
@@ -224,31 +248,79 @@ pub(crate) fn create_lease(
     create: Json<CreateLeaseForm>,
     hooks: State<Hooks>,
 ) -> Result<CreateLeaseResponse> {
-    use crate::schema::assets::dsl::*;
-
-    let create_lease = create.into_inner().into_create_lease(user.id());
-
-    let created = create_lease.insert(&(&*db).into())?;
+    let asset = match Asset::by_id(&*db, asset_id)? {
+        Some(x) => x,
+        None => return Ok(CreateLeaseResponse::Status(Status::NotFound)),
+    };
 
-    let to_update = assets.filter(id.eq(asset_id).and(lease_id.is_null()));
+    if !asset.is_available() {
+        return Ok(CreateLeaseResponse::OutOfService(Json(asset)));
+    }
 
-    // TODO: Leaks leases when the update fails
-    let updated: Option<Asset> = diesel::update(to_update)
-        .set(lease_id.eq(Some(created.id())))
-        .get_result(&*db)
-        .optional()
-        .chain_err(|| "unable to update asset with new lease")?;
+    let lease_fields = LeaseField::for_types(&*db, &[asset.type_id()])?;
 
-    let asset = match updated {
+    let fields = match LeaseField::resolve(&lease_fields, create.fields()) {
         Some(x) => x,
-        None => return Ok(CreateLeaseResponse::Status(Status::Conflict)),
+        None => return Ok(CreateLeaseResponse::Status(Status::BadRequest)),
     };
 
     let asset_type = AssetType::by_id(&*db, asset.type_id())?.chain_err(|| "missing asset_type")?;
 
-    let data = HookData::new(&created, &asset, &asset_type);
-    hooks.leased(&*db, data)?;
-    Ok(CreateLeaseResponse::Success(Json(created)))
+    let values = create.fields().clone();
+    let create_lease = create.into_inner().into_create_lease(user.id());
+
+    if asset_type.requires_approval() {
+        if !create_lease.is_valid() {
+            return Ok(CreateLeaseResponse::Status(Status::BadRequest));
+        }
+
+        let request = approvals::request(
+            &*db,
+            &hooks,
+            &asset,
+            &asset_type,
+            &user,
+            &create_lease,
+            &values,
+        );
+
+        return match request {
+            Ok(x) => Ok(CreateLeaseResponse::Requested(Json(x))),
+            Err(e) => Ok(CreateLeaseResponse::Refused(Refused::from_error(e)?)),
+        };
+    }
+
+    // The hooks run inside the transaction, so they can cancel the lease.
+    let result = db.transaction::<_, Error, _>(|| {
+        let (lease, started) = match create_lease.reserve(&*db, asset_id, &fields)? {
+            Reserved::Started(lease, asset) => (lease, Some(asset)),
+            Reserved::Upcoming(lease) => (lease, None),
+            Reserved::Conflict => return Ok(CreateLeaseResponse::Status(Status::Conflict)),
+            Reserved::Invalid => return Ok(CreateLeaseResponse::Status(Status::BadRequest)),
+        };
+
+        lease_quota::enforce(&*db, &lease, &[asset.type_id()])?;
+
+        let fields = FieldValue::for_lease(&*db, lease.id())?;
+
+        // Upcoming leases don't hold the asset yet, so the sheriff calls
+        // `leased` when they start.
+        let held = started.as_ref().unwrap_or(&asset);
+        let data = HookData::new(&lease, held, &asset_type).with_fields(&fields);
+
+        hooks.before_lease(&*db, data.clone())?;
+
+        if started.is_some() {
+            hooks.leased(&*db, data)?;
+        }
+
+        Ok(CreateLeaseResponse::Success(Json(lease)))
+    });
+
+    match result {
+        Ok(x) => Ok(x),
+        Err(e) => Ok(CreateLeaseResponse::Refused(Refused::from_error(e)?)),
+    }
 }
 
 #[delete("/<asset_id>/lease")]
@@ -257,46 +329,440 @@ pub(crate) fn delete_lease(
     db: Db,
     user: User,
     hooks: State<Hooks>,
-) -> Result<Option<Status>> {
-    use crate::schema::leases::dsl as leases;
+) -> Result<ReleaseResponse> {
+    let lease = match current_lease(&*db, asset_id)? {
+        Some(x) => x,
+        None => return Ok(ReleaseResponse::Status(Status::NotFound)),
+    };
+
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(ReleaseResponse::Status(Status::Forbidden));
+    }
+
+    super::leases::release_response(&*db, &hooks, &lease)
+}
+
+#[post(
+    "/<asset_id>/lease/revoke",
+    data = "<revoke>",
+    format = "application/json"
+)]
+pub(crate) fn revoke_lease(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    revoke: Json<RevokeLeaseForm>,
+    hooks: State<Hooks>,
+) -> Result<Status> {
+    if !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
 
+    if revoke.reason().is_empty() {
+        return Ok(Status::BadRequest);
+    }
+
+    let lease = match current_lease(&*db, asset_id)? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
+
+    if super::leases::revoke(&*db, &hooks, &lease, &user, revoke.reason())? {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum SetStatusResponse {
+    Success(Json<Asset>),
+    Status(Status),
+}
+
+#[put("/<asset_id>/status", data = "<form>", format = "application/json")]
+pub(crate) fn set_status(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    form: Json<SetAssetStatusForm>,
+    hooks: State<Hooks>,
+) -> Result<SetStatusResponse> {
+    if !user.can_write() {
+        return Ok(SetStatusResponse::Status(Status::Forbidden));
+    }
+
+    // The asset is locked, so the prober or the sheriff can't change its
+    // status between reading it and deciding which hooks to call.
+    let changed = db.transaction::<_, Error, _>(|| {
+        let asset = match Asset::lock(&*db, asset_id)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let asset_type =
+            AssetType::by_id(&*db, asset.type_id())?.chain_err(|| "missing asset_type")?;
+
+        let updated = asset.set_status(&*db, &form)?;
+
+        let was_maintenance = asset.status() == AssetStatus::Maintenance;
+        let is_maintenance = updated.status() == AssetStatus::Maintenance;
+
+        if is_maintenance && !was_maintenance {
+            hooks.entered_maintenance(&*db, &updated, &asset_type)?;
+        } else if was_maintenance && !is_maintenance {
+            hooks.left_maintenance(&*db, &updated, &asset_type)?;
+        }
+
+        Ok(Some((asset.is_available(), updated)))
+    })?;
+
+    let (was_available, updated) = match changed {
+        Some(x) => x,
+        None => return Ok(SetStatusResponse::Status(Status::NotFound)),
+    };
+
+    println!(
+        "User {} set status of asset id {} to {:?}",
+        user.id(),
+        asset_id,
+        updated.status()
+    );
+
+    // Someone might have been waiting for the asset to come back.
+    if updated.is_available() && !was_available {
+        waitlist::hand_off(&*db, &hooks, &updated)?;
+    }
+
+    Ok(SetStatusResponse::Success(Json(updated)))
+}
+
+#[get("/<asset_id>/leases", format = "application/json")]
+pub fn leases(asset_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<EndedLease>>>> {
+    if let None = Asset::by_id(&*db, asset_id)? {
+        return Ok(None);
+    }
+
+    let ended = EndedLease::for_asset(&*db, asset_id)?
+        .into_iter()
+        .map(|(lease, _)| lease)
+        .collect();
+
+    Ok(Some(Json(Paged::new(ended))))
+}
+
+#[get("/<asset_id>/health", format = "application/json")]
+pub fn health(asset_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<ProbeResult>>>> {
+    if let None = Asset::by_id(&*db, asset_id)? {
+        return Ok(None);
+    }
+
+    let results = ProbeResult::for_asset(&*db, asset_id)?
+        .into_iter()
+        .map(|(_, result)| result)
+        .collect();
+
+    Ok(Some(Json(Paged::new(results))))
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum ExtendLeaseResponse {
+    Success(Json<Lease>),
+
+    Refused(Refused),
+
+    Status(Status),
+}
+
+#[patch("/<asset_id>/lease", data = "<extend>", format = "application/json")]
+pub(crate) fn extend_lease(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    extend: Json<ExtendLeaseForm>,
+    hooks: State<Hooks>,
+) -> Result<ExtendLeaseResponse> {
     let asset = match Asset::by_id(&db, asset_id)? {
         Some(x) => x,
-        None => return Ok(None),
+        None => return Ok(ExtendLeaseResponse::Status(Status::NotFound)),
+    };
+
+    let lease = match asset.lease_id() {
+        Some(x) => Lease::by_id(&*db, x)?,
+        None => None,
     };
 
-    let lease_id = match asset.lease_id() {
+    let lease = match lease {
         Some(x) => x,
-        None => return Ok(None),
+        None => return Ok(ExtendLeaseResponse::Status(Status::NotFound)),
     };
 
-    let lease = match Lease::by_id(&*db, lease_id)? {
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(ExtendLeaseResponse::Status(Status::Forbidden));
+    }
+
+    let asset_type = AssetType::by_id(&*db, asset.type_id())?.chain_err(|| "missing asset_type")?;
+
+    // Hooks are queued in the same transaction, so they only hear about the
+    // extension if it happens.
+    let result = db.transaction::<_, Error, _>(|| {
+        use crate::schema::asset_types::dsl as at;
+
+        let lease = match lease.extend(&*db, &asset_type, extend.end_time())? {
+            Extended::Extended(x) => x,
+            other => return Ok(other),
+        };
+
+        // Bundles hold more than just this asset.
+        let held: Vec<(Asset, AssetType)> = Asset::belonging_to(&lease)
+            .inner_join(at::asset_types)
+            .load(&*db)
+            .chain_err(|| "unable to get assets and asset types for lease")?;
+
+        let fields = FieldValue::for_lease(&*db, lease.id())?;
+
+        for (asset, asset_type) in held.iter() {
+            let data = HookData::new(&lease, asset, asset_type).with_fields(&fields);
+            hooks.extended(&*db, data)?;
+        }
+
+        Ok(Extended::Extended(lease))
+    });
+
+    let extended = match result {
+        Ok(x) => x,
+        Err(e) => return Ok(ExtendLeaseResponse::Refused(Refused::from_error(e)?)),
+    };
+
+    let status = match extended {
+        Extended::Extended(lease) => return Ok(ExtendLeaseResponse::Success(Json(lease))),
+        Extended::Invalid => Status::BadRequest,
+        Extended::TooLong | Extended::TooManyExtensions => Status::UnprocessableEntity,
+        Extended::Conflict => Status::Conflict,
+    };
+
+    Ok(ExtendLeaseResponse::Status(status))
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum HeartbeatResponse {
+    Success(Json<Lease>),
+
+    Status(Status),
+}
+
+#[post("/<asset_id>/lease/heartbeat")]
+pub(crate) fn heartbeat(asset_id: i32, db: Db, user: User) -> Result<HeartbeatResponse> {
+    let lease = match current_lease(&*db, asset_id)? {
+        Some(x) => x,
+        None => return Ok(HeartbeatResponse::Status(Status::NotFound)),
+    };
+
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(HeartbeatResponse::Status(Status::Forbidden));
+    }
+
+    // Only leases created with a heartbeat interval can be abandoned.
+    if lease.heartbeat_secs().is_none() {
+        return Ok(HeartbeatResponse::Status(Status::Conflict));
+    }
+
+    match lease.heartbeat(&*db)? {
+        Some(x) => Ok(HeartbeatResponse::Success(Json(x))),
+        None => Ok(HeartbeatResponse::Status(Status::NotFound)),
+    }
+}
+
+/// The `Lease` currently holding the `Asset` identified by `asset_id`.
+fn current_lease(c: &PgConnection, asset_id: i32) -> Result<Option<Lease>> {
+    match Asset::by_id(c, asset_id)?.and_then(|x| x.lease_id()) {
+        Some(x) => Lease::by_id(c, x),
+        None => Ok(None),
+    }
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum TransferLeaseResponse {
+    #[response(status = 201)]
+    Pending(Json<LeaseTransfer>),
+
+    Transferred(Json<Lease>),
+
+    Refused(Refused),
+
+    Status(Status),
+}
+
+#[post(
+    "/<asset_id>/lease/transfer",
+    data = "<transfer>",
+    format = "application/json"
+)]
+pub(crate) fn transfer_lease(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    transfer: Json<TransferLeaseForm>,
+    hooks: State<Hooks>,
+) -> Result<TransferLeaseResponse> {
+    let lease = match current_lease(&*db, asset_id)? {
+        Some(x) => x,
+        None => return Ok(TransferLeaseResponse::Status(Status::NotFound)),
+    };
+
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(TransferLeaseResponse::Status(Status::Forbidden));
+    }
+
+    let recipient = match User::by_email(&(&db).into(), transfer.email())? {
+        Some(x) => x,
+        None => return Ok(TransferLeaseResponse::Status(Status::BadRequest)),
+    };
+
+    if recipient.id() == lease.user_id() {
+        return Ok(TransferLeaseResponse::Status(Status::BadRequest));
+    }
+
+    // Checked again when the transfer happens, in case the asset type starts
+    // requiring approval in the meantime.
+    if let Err(e) = approvals::check_holder(&*db, &lease, recipient.id()) {
+        return Ok(TransferLeaseResponse::Refused(Refused::from_error(e)?));
+    }
+
+    if transfer.require_accept() {
+        let pending = CreateLeaseTransfer::new(&lease, recipient.id()).insert(&*db)?;
+        return Ok(TransferLeaseResponse::Pending(Json(pending)));
+    }
+
+    match super::leases::transfer(&*db, &hooks, &lease, &recipient) {
+        Ok(Some(x)) => Ok(TransferLeaseResponse::Transferred(Json(x))),
+        Ok(None) => Ok(TransferLeaseResponse::Status(Status::Conflict)),
+        Err(e) => Ok(TransferLeaseResponse::Refused(Refused::from_error(e)?)),
+    }
+}
+
+#[get("/<asset_id>/lease/transfer", format = "application/json")]
+pub fn pending_transfer(asset_id: i32, db: Db, _user: User) -> Result<Option<Json<LeaseTransfer>>> {
+    let lease = match current_lease(&*db, asset_id)? {
         Some(x) => x,
         None => return Ok(None),
     };
 
-    let num_deleted_rows = match diesel::delete(leases::leases)
-        .filter(leases::id.eq(lease_id).and(leases::user_id.eq(user.id())))
-        .execute(&*db)
-    {
-        Ok(x) => x,
-        Err(e) => bail!("Error deleting lease: {}", e),
+    Ok(LeaseTransfer::by_lease(&*db, lease.id())?.map(Json))
+}
+
+#[post("/<asset_id>/lease/transfer/accept")]
+pub(crate) fn accept_transfer(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<TransferLeaseResponse> {
+    let lease = match current_lease(&*db, asset_id)? {
+        Some(x) => x,
+        None => return Ok(TransferLeaseResponse::Status(Status::NotFound)),
     };
 
-    println!(
-        "Deleted {} rows for lease id {}",
-        num_deleted_rows, lease_id
-    );
+    let pending = match LeaseTransfer::by_lease(&*db, lease.id())? {
+        Some(x) => x,
+        None => return Ok(TransferLeaseResponse::Status(Status::NotFound)),
+    };
 
-    let retval = match num_deleted_rows {
-        1 => Ok(Some(Status::NoContent)),
-        _ => return Ok(Some(Status::Forbidden)),
+    if pending.to_user_id() != user.id() {
+        return Ok(TransferLeaseResponse::Status(Status::Forbidden));
+    }
+
+    // The lease changed hands after the transfer was offered, so the offer
+    // no longer stands.
+    if pending.from_user_id() != lease.user_id() {
+        pending.delete(&*db)?;
+        return Ok(TransferLeaseResponse::Status(Status::Conflict));
+    }
+
+    match super::leases::transfer(&*db, &hooks, &lease, &user) {
+        Ok(Some(x)) => Ok(TransferLeaseResponse::Transferred(Json(x))),
+        Ok(None) => Ok(TransferLeaseResponse::Status(Status::Conflict)),
+        Err(e) => Ok(TransferLeaseResponse::Refused(Refused::from_error(e)?)),
+    }
+}
+
+/// Withdraw a pending transfer, if `user` owns the lease, or decline it, if
+/// `user` is the recipient.
+#[delete("/<asset_id>/lease/transfer")]
+pub(crate) fn delete_transfer(asset_id: i32, db: Db, user: User) -> Result<Status> {
+    let lease = match current_lease(&*db, asset_id)? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
     };
 
-    let asset_type = AssetType::by_id(&*db, asset.type_id())?.chain_err(|| "missing asset_type")?;
+    let pending = match LeaseTransfer::by_lease(&*db, lease.id())? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
 
-    let data = HookData::new(&lease, &asset, &asset_type);
-    hooks.returned(&*db, data)?;
+    if pending.to_user_id() != user.id() && !lease.is_owner(&*db, user.id())? {
+        return Ok(Status::Forbidden);
+    }
 
-    retval
+    if pending.delete(&*db)? {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[get("/<asset_id>/reservations", format = "application/json")]
+pub fn reservations(asset_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<Lease>>>> {
+    if let None = Asset::by_id(&*db, asset_id)? {
+        return Ok(None);
+    }
+
+    let upcoming = Reservation::upcoming(&*db, asset_id)?
+        .into_iter()
+        .map(|(lease, _)| lease)
+        .collect();
+
+    Ok(Some(Json(Paged::new(upcoming))))
+}
+
+#[delete("/<asset_id>/reservations/<lease_id>")]
+pub(crate) fn delete_reservation(
+    asset_id: i32,
+    lease_id: i32,
+    db: Db,
+    user: User,
+) -> Result<Status> {
+    use crate::schema::leases::dsl as l;
+    use crate::schema::reservations::dsl as r;
+
+    let lease: Option<Lease> = l::leases
+        .inner_join(r::reservations)
+        .filter(r::asset_id.eq(asset_id).and(r::lease_id.eq(lease_id)))
+        .select(crate::schema::leases::all_columns)
+        .get_result(&*db)
+        .optional()
+        .chain_err(|| "unable to get reservation")?;
+
+    let lease = match lease {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
+
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(Status::Forbidden);
+    }
+
+    // Reservations that have already started have to be released like any
+    // other lease, so the hooks get called.
+    let to_delete = l::leases.filter(l::id.eq(lease.id()).and(l::start_time.gt(Utc::now())));
+
+    let num_deleted_rows = diesel::delete(to_delete)
+        .execute(&*db)
+        .chain_err(|| "unable to delete reservation")?;
+
+    if num_deleted_rows == 1 {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::Conflict)
+    }
 }
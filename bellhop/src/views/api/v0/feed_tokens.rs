@@ -0,0 +1,78 @@
+use crate::errors::*;
+use crate::internal::db::Db;
+use crate::internal::uri::Base;
+use crate::models::feed_token::FeedToken;
+use crate::models::user::User;
+
+use diesel::prelude::*;
+
+use rocket::http::hyper::header::Location;
+use rocket::http::Status;
+
+use rocket_contrib::json::Json;
+
+use super::Paged;
+
+#[get("/", format = "application/json")]
+pub fn list(db: Db, user: User) -> Result<Json<Paged<FeedToken>>> {
+    let tokens = FeedToken::for_user(&*db, user.id())?;
+
+    Ok(Json(Paged::new(tokens)))
+}
+
+#[get("/<token_id>", format = "application/json")]
+pub fn detail(token_id: i32, db: Db, user: User) -> Result<Option<Json<FeedToken>>> {
+    let token = match FeedToken::by_id(&*db, token_id)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    // Tokens are secrets, so other users' tokens might as well not exist.
+    if token.user_id() != user.id() {
+        return Ok(None);
+    }
+
+    Ok(Some(Json(token)))
+}
+
+#[derive(Debug, Responder)]
+#[response(status = 201)]
+pub struct CreateSuccess {
+    body: Json<FeedToken>,
+    location: Location,
+}
+
+#[post("/")]
+pub fn create(db: Db, user: User, base: Base) -> Result<CreateSuccess> {
+    let created = FeedToken::create(&*db, user.id())?;
+    let location = uri!(detail: token_id = created.id());
+
+    Ok(CreateSuccess {
+        location: Location(base.join(location).to_string()),
+        body: Json(created),
+    })
+}
+
+/// Revoke a token, so feeds can't be read with it anymore.
+#[delete("/<token_id>")]
+pub fn delete(token_id: i32, db: Db, user: User) -> Result<Status> {
+    let token = match FeedToken::by_id(&*db, token_id)? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
+
+    // Like `detail`, so token ids can't be probed. Admins can revoke anyone's.
+    if token.user_id() != user.id() && !user.can_write() {
+        return Ok(Status::NotFound);
+    }
+
+    let num_deleted_rows = diesel::delete(&token)
+        .execute(&*db)
+        .chain_err(|| "unable to delete feed token")?;
+
+    if num_deleted_rows == 1 {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
@@ -0,0 +1,89 @@
+use crate::errors::*;
+use crate::internal::db::Db;
+use crate::models::hook_delivery::{DeliveryStatus, HookDelivery};
+use crate::models::user::User;
+
+use rocket::http::Status;
+
+use rocket_contrib::json::Json;
+
+use std::result::Result as StdResult;
+
+use super::Paged;
+
+/// How many deliveries are listed.
+const MAX_DELIVERIES: i64 = 100;
+
+/// The most recent hook events in the outbox, newest first, optionally only
+/// those with `status`. Only admins can see them.
+#[get("/deliveries?<status>", format = "application/json")]
+pub fn deliveries(
+    status: Option<DeliveryStatus>,
+    db: Db,
+    user: User,
+) -> Result<StdResult<Json<Paged<HookDelivery>>, Status>> {
+    if !user.can_write() {
+        return Ok(Err(Status::Forbidden));
+    }
+
+    let recent = HookDelivery::recent(&*db, status, MAX_DELIVERIES)?;
+
+    Ok(Ok(Json(Paged::new(recent))))
+}
+
+#[get("/deliveries/<delivery_id>", format = "application/json")]
+pub fn delivery(
+    delivery_id: i32,
+    db: Db,
+    user: User,
+) -> Result<StdResult<Json<HookDelivery>, Status>> {
+    if !user.can_write() {
+        return Ok(Err(Status::Forbidden));
+    }
+
+    match HookDelivery::by_id(&*db, delivery_id)? {
+        Some(x) => Ok(Ok(Json(x))),
+        None => Ok(Err(Status::NotFound)),
+    }
+}
+
+/// Deliver an event again, with a fresh set of attempts, whether it's dead,
+/// delivered, or still being retried.
+#[post("/deliveries/<delivery_id>/replay")]
+pub fn replay(
+    delivery_id: i32,
+    db: Db,
+    user: User,
+) -> Result<StdResult<Json<HookDelivery>, Status>> {
+    if !user.can_write() {
+        return Ok(Err(Status::Forbidden));
+    }
+
+    let delivery = match HookDelivery::by_id(&*db, delivery_id)? {
+        Some(x) => x,
+        None => return Ok(Err(Status::NotFound)),
+    };
+
+    Ok(Ok(Json(delivery.replay(&*db)?)))
+}
+
+#[derive(Debug, Serialize)]
+pub struct Replayed {
+    replayed: usize,
+}
+
+/// Deliver every dead event again, optionally only those for `hook`.
+#[post("/deliveries/replay?<hook>")]
+pub fn replay_dead(
+    hook: Option<String>,
+    db: Db,
+    user: User,
+) -> Result<StdResult<Json<Replayed>, Status>> {
+    if !user.can_write() {
+        return Ok(Err(Status::Forbidden));
+    }
+
+    let replayed = HookDelivery::replay_dead(&*db, hook.as_ref().map(String::as_str))?;
+
+    Ok(Ok(Json(Replayed { replayed })))
+}
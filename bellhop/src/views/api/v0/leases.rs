@@ -0,0 +1,452 @@
+use crate::approvals;
+use crate::errors::*;
+use crate::hooks::Data as HookData;
+use crate::internal::db::Db;
+use crate::internal::hooks::Hooks;
+use crate::internal::uri::Base;
+use crate::models::asset::Asset;
+use crate::models::asset_type::AssetType;
+use crate::models::ended_lease::EndReason;
+use crate::models::lease::{BundleItem, Bundled, CreateBundleForm, Lease};
+use crate::models::lease_field::{FieldValue, LeaseField};
+use crate::models::lease_quota;
+use crate::models::lease_user::LeaseUser;
+use crate::models::tag::WantedTag;
+use crate::models::tag_type::TagType;
+use crate::models::user::User;
+use crate::waitlist;
+
+use diesel::prelude::*;
+
+use rocket::http::hyper::header::Location;
+use rocket::http::Status;
+use rocket::request::State;
+
+use rocket_contrib::json::Json;
+
+use super::{Paged, Refused, Rejection};
+
+/// A `Lease` and every `Asset` it holds.
+#[derive(Debug, Serialize)]
+pub struct Bundle {
+    lease: Lease,
+    assets: Vec<Asset>,
+}
+
+/// End `lease` because its owner gave it back, then run the `returned` hook
+/// and offer the asset to its waitlist, for every asset the lease held.
+///
+/// Everything happens in one transaction, after asking the `before_return`
+/// hooks. If one of them rejects the release, the error has
+/// `ErrorKind::Rejected`. Returns `false` if the lease had already ended.
+pub(crate) fn release(c: &PgConnection, hooks: &Hooks, lease: &Lease) -> Result<bool> {
+    use crate::db::Db as PubDb;
+    use crate::schema::asset_types::dsl as at;
+
+    c.transaction::<_, Error, _>(|| {
+        let assets: Vec<(Asset, AssetType)> = Asset::belonging_to(lease)
+            .inner_join(at::asset_types)
+            .load(c)
+            .chain_err(|| "unable to get assets and asset types for lease")?;
+
+        // Field values and co-owners are deleted along with the lease.
+        let fields = FieldValue::for_lease(c, lease.id())?;
+        let owners = lease.owners(&PubDb::from(c))?;
+
+        for (asset, asset_type) in assets.iter() {
+            let data = HookData::new(lease, asset, asset_type)
+                .with_fields(&fields)
+                .with_owners(&owners);
+            hooks.before_return(c, data)?;
+        }
+
+        let ended = match lease.end(c, EndReason::Returned)? {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+
+        for (asset, asset_type) in assets.iter() {
+            let mut data = HookData::new(lease, asset, asset_type)
+                .with_fields(&fields)
+                .with_owners(&owners);
+            if let Some(x) = ended.iter().find(|x| x.asset_id() == asset.id()) {
+                data = data.with_ended(x);
+            }
+
+            hooks.returned(c, data)?;
+        }
+
+        for (asset, _) in assets.iter() {
+            waitlist::hand_off(c, hooks, asset)?;
+        }
+
+        println!("Returned lease id {}", lease.id());
+
+        Ok(true)
+    })
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum ReleaseResponse {
+    #[response(status = 422)]
+    Rejected(Json<Rejection>),
+
+    Status(Status),
+}
+
+/// `release`, as a response for the routes that return leases.
+pub(crate) fn release_response(
+    c: &PgConnection,
+    hooks: &Hooks,
+    lease: &Lease,
+) -> Result<ReleaseResponse> {
+    match release(c, hooks, lease) {
+        Ok(true) => Ok(ReleaseResponse::Status(Status::NoContent)),
+        Ok(false) => Ok(ReleaseResponse::Status(Status::NotFound)),
+        Err(e) => Ok(ReleaseResponse::Rejected(Json(Rejection::from_error(e)?))),
+    }
+}
+
+/// End `lease` on behalf of `by`, an administrator, then run the `revoked`
+/// hook and offer the asset to its waitlist, for every asset the lease held.
+///
+/// Returns `false` if the lease had already ended.
+pub(crate) fn revoke(
+    c: &PgConnection,
+    hooks: &Hooks,
+    lease: &Lease,
+    by: &User,
+    reason: &str,
+) -> Result<bool> {
+    use crate::db::Db as PubDb;
+    use crate::schema::asset_types::dsl as at;
+
+    let assets: Vec<(Asset, AssetType)> = Asset::belonging_to(lease)
+        .inner_join(at::asset_types)
+        .load(c)
+        .chain_err(|| "unable to get assets and asset types for lease")?;
+
+    // Field values and co-owners are deleted along with the lease.
+    let fields = FieldValue::for_lease(c, lease.id())?;
+    let owners = lease.owners(&PubDb::from(c))?;
+
+    // Hooks are queued in the same transaction, so they only hear about the
+    // revocation if it happens.
+    let revoked = c.transaction::<_, Error, _>(|| {
+        let ended = match lease.revoke(c, by.id(), reason)? {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+
+        for (asset, asset_type) in assets.iter() {
+            let mut data = HookData::new(lease, asset, asset_type)
+                .with_fields(&fields)
+                .with_owners(&owners);
+            if let Some(x) = ended.iter().find(|x| x.asset_id() == asset.id()) {
+                data = data.with_ended(x);
+            }
+
+            hooks.revoked(c, data)?;
+        }
+
+        Ok(true)
+    })?;
+
+    if !revoked {
+        return Ok(false);
+    }
+
+    println!("User {} revoked lease id {}", by.id(), lease.id());
+
+    for (asset, _) in assets.iter() {
+        waitlist::hand_off(c, hooks, asset)?;
+    }
+
+    Ok(true)
+}
+
+/// Hand `lease` to `to`, then run the `transferred` hook for every asset the
+/// lease holds.
+///
+/// Returns `None` if the lease has ended, or changed owner in the meantime.
+/// If the lease would put `to` over their quota, the error has
+/// `ErrorKind::QuotaExceeded`. If it's on an asset type that requires
+/// approval, and `to` isn't an approver, the error has `ErrorKind::Rejected`.
+pub(crate) fn transfer(
+    c: &PgConnection,
+    hooks: &Hooks,
+    lease: &Lease,
+    to: &User,
+) -> Result<Option<Lease>> {
+    use crate::db::Db as PubDb;
+    use crate::schema::asset_types::dsl as at;
+
+    let from =
+        User::by_id(&PubDb::from(c), lease.user_id())?.chain_err(|| "missing lease owner")?;
+
+    let transferred = c.transaction::<_, Error, _>(|| {
+        approvals::check_holder(c, lease, to.id())?;
+
+        let (transferred, ended) = match lease.transfer(c, to.id())? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let assets: Vec<(Asset, AssetType)> = Asset::belonging_to(&transferred)
+            .inner_join(at::asset_types)
+            .load(c)
+            .chain_err(|| "unable to get assets and asset types for lease")?;
+
+        let fields = FieldValue::for_lease(c, transferred.id())?;
+
+        for (asset, asset_type) in assets.iter() {
+            let mut data = HookData::new(&transferred, asset, asset_type).with_fields(&fields);
+            if let Some(x) = ended.iter().find(|x| x.asset_id() == asset.id()) {
+                data = data.with_ended(x);
+            }
+
+            hooks.transferred(c, data, &from, to)?;
+        }
+
+        Ok(Some(transferred))
+    })?;
+
+    if transferred.is_some() {
+        println!(
+            "Transferred lease id {} from user {} to user {}",
+            lease.id(),
+            from.id(),
+            to.id()
+        );
+    }
+
+    Ok(transferred)
+}
+
+/// The primary keys of the asset types of every item in `items`, or `None`
+/// if an asset or asset type doesn't exist, or a wanted tag doesn't belong to
+/// its asset type.
+fn type_ids(c: &PgConnection, items: &[BundleItem]) -> Result<Option<Vec<i32>>> {
+    let mut ids = Vec::with_capacity(items.len());
+
+    for item in items {
+        match item {
+            BundleItem::Asset { asset_id } => match Asset::by_id(c, *asset_id)? {
+                Some(x) => ids.push(x.type_id()),
+                None => return Ok(None),
+            },
+            BundleItem::Claim { type_id, tags } => {
+                let asset_type = match AssetType::by_id(c, *type_id)? {
+                    Some(x) => x,
+                    None => return Ok(None),
+                };
+
+                let tag_types: Vec<TagType> = TagType::belonging_to(&asset_type)
+                    .load(c)
+                    .chain_err(|| "unable to get tag types belonging to an asset type")?;
+
+                if !WantedTag::all_valid(tags, &tag_types) {
+                    return Ok(None);
+                }
+
+                ids.push(asset_type.id());
+            }
+        }
+    }
+
+    ids.sort();
+    ids.dedup();
+
+    Ok(Some(ids))
+}
+
+#[derive(Debug, Responder)]
+#[response(status = 201)]
+pub struct CreateSuccess {
+    body: Json<Bundle>,
+    location: Location,
+}
+
+#[derive(Debug, Responder)]
+pub enum Create {
+    Success(CreateSuccess),
+
+    Refused(Refused),
+
+    Status(Status),
+}
+
+#[post("/", data = "<create>", format = "application/json")]
+pub(crate) fn create(
+    db: Db,
+    user: User,
+    create: Json<CreateBundleForm>,
+    base: Base,
+    hooks: State<Hooks>,
+) -> Result<Create> {
+    let type_ids = match type_ids(&*db, create.assets())? {
+        Some(x) => x,
+        None => return Ok(Create::Status(Status::BadRequest)),
+    };
+
+    for type_id in type_ids.iter() {
+        let asset_type = AssetType::by_id(&*db, *type_id)?.chain_err(|| "missing asset_type")?;
+
+        if asset_type.requires_approval() {
+            return Ok(Create::Refused(Refused::requires_approval(&asset_type)));
+        }
+    }
+
+    let lease_fields = LeaseField::for_types(&*db, &type_ids)?;
+
+    let fields = match LeaseField::resolve(&lease_fields, create.fields()) {
+        Some(x) => x,
+        None => return Ok(Create::Status(Status::BadRequest)),
+    };
+
+    let create_lease = create.to_create_lease(user.id());
+
+    // The hooks run inside the transaction, so they can cancel the lease.
+    let result = db.transaction::<_, Error, _>(|| {
+        let (lease, assets) = match create_lease.bundle(&*db, create.assets(), &fields)? {
+            Bundled::Started(lease, assets) => (lease, assets),
+            Bundled::Conflict => return Ok(Create::Status(Status::Conflict)),
+            Bundled::Invalid => return Ok(Create::Status(Status::BadRequest)),
+        };
+
+        lease_quota::enforce(&*db, &lease, &type_ids)?;
+
+        let fields = FieldValue::for_lease(&*db, lease.id())?;
+
+        let mut types = Vec::with_capacity(assets.len());
+        for asset in assets.iter() {
+            let asset_type =
+                AssetType::by_id(&*db, asset.type_id())?.chain_err(|| "missing asset_type")?;
+
+            let data = HookData::new(&lease, asset, &asset_type).with_fields(&fields);
+            hooks.before_lease(&*db, data)?;
+
+            types.push(asset_type);
+        }
+
+        for (asset, asset_type) in assets.iter().zip(types.iter()) {
+            let data = HookData::new(&lease, asset, asset_type).with_fields(&fields);
+            hooks.leased(&*db, data)?;
+        }
+
+        let location = uri!(detail: lease_id = lease.id());
+
+        let result = CreateSuccess {
+            location: Location(base.join(location).to_string()),
+            body: Json(Bundle { lease, assets }),
+        };
+
+        Ok(Create::Success(result))
+    });
+
+    match result {
+        Ok(x) => Ok(x),
+        Err(e) => Ok(Create::Refused(Refused::from_error(e)?)),
+    }
+}
+
+#[get("/<lease_id>", format = "application/json")]
+pub fn detail(lease_id: i32, db: Db, _user: User) -> Result<Option<Json<Bundle>>> {
+    let lease = match Lease::by_id(&*db, lease_id)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let assets = Asset::belonging_to(&lease)
+        .load(&*db)
+        .chain_err(|| "unable to get assets for lease")?;
+
+    Ok(Some(Json(Bundle { lease, assets })))
+}
+
+#[delete("/<lease_id>")]
+pub(crate) fn delete(
+    lease_id: i32,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<ReleaseResponse> {
+    let lease = match Lease::by_id(&*db, lease_id)? {
+        Some(x) => x,
+        None => return Ok(ReleaseResponse::Status(Status::NotFound)),
+    };
+
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(ReleaseResponse::Status(Status::Forbidden));
+    }
+
+    release_response(&*db, &hooks, &lease)
+}
+
+#[get("/<lease_id>/users", format = "application/json")]
+pub fn co_owners(lease_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<User>>>> {
+    let lease = match Lease::by_id(&*db, lease_id)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Json(Paged::new(lease.co_owners(&*db)?))))
+}
+
+#[derive(Debug, Responder)]
+pub enum AddCoOwnerResponse {
+    #[response(status = 422)]
+    Rejected(Json<Rejection>),
+
+    Status(Status),
+}
+
+#[put("/<lease_id>/users/<user_id>")]
+pub fn add_co_owner(lease_id: i32, user_id: i32, db: Db, user: User) -> Result<AddCoOwnerResponse> {
+    let lease = match Lease::by_id(&*db, lease_id)? {
+        Some(x) => x,
+        None => return Ok(AddCoOwnerResponse::Status(Status::NotFound)),
+    };
+
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(AddCoOwnerResponse::Status(Status::Forbidden));
+    }
+
+    if let None = User::by_id(&(&db).into(), user_id)? {
+        return Ok(AddCoOwnerResponse::Status(Status::NotFound));
+    }
+
+    // The owner is already an owner.
+    if lease.user_id() == user_id {
+        return Ok(AddCoOwnerResponse::Status(Status::NoContent));
+    }
+
+    if let Err(e) = approvals::check_holder(&*db, &lease, user_id) {
+        let rejection = Rejection::from_error(e)?;
+        return Ok(AddCoOwnerResponse::Rejected(Json(rejection)));
+    }
+
+    if LeaseUser::new(lease_id, user_id).insert(&*db)? {
+        Ok(AddCoOwnerResponse::Status(Status::Created))
+    } else {
+        Ok(AddCoOwnerResponse::Status(Status::NoContent))
+    }
+}
+
+#[delete("/<lease_id>/users/<user_id>")]
+pub fn remove_co_owner(lease_id: i32, user_id: i32, db: Db, user: User) -> Result<Status> {
+    let lease = match Lease::by_id(&*db, lease_id)? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
+
+    if !lease.is_owner(&*db, user.id())? {
+        return Ok(Status::Forbidden);
+    }
+
+    if LeaseUser::new(lease_id, user_id).delete(&*db)? {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
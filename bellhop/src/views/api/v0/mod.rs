@@ -1,8 +1,20 @@
 pub mod assets;
+pub mod feed_tokens;
+pub mod hooks;
+pub mod leases;
+pub mod requests;
+pub mod sheriff;
 pub mod types;
+pub mod users;
+
+use crate::errors::*;
+use crate::models::asset_type::AssetType;
+use crate::models::lease_quota::Exceeded;
 
 use rocket::response::content::Html;
 
+use rocket_contrib::json::Json;
+
 use url::Url;
 
 #[derive(Debug, Serialize)]
@@ -32,6 +44,81 @@ impl<T> Paged<T> {
     }
 }
 
+/// Why a hook refused to let an operation happen.
+#[derive(Debug, Serialize)]
+pub struct Rejection {
+    reason: String,
+}
+
+impl Rejection {
+    /// Pick out rejections by `before_*` hooks, which the user should see,
+    /// from other errors.
+    pub(crate) fn from_error(e: Error) -> Result<Rejection> {
+        match e.kind() {
+            ErrorKind::Rejected(reason) => Ok(Rejection {
+                reason: reason.clone(),
+            }),
+            _ => Err(e),
+        }
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// Which quota a new lease would have broken.
+#[derive(Debug, Serialize)]
+pub struct OverQuota {
+    #[serde(flatten)]
+    quota: Exceeded,
+
+    reason: String,
+}
+
+/// Why a valid request for a new lease was turned down.
+#[derive(Debug, Responder)]
+pub enum Refused {
+    #[response(status = 403)]
+    OverQuota(Json<OverQuota>),
+
+    #[response(status = 422)]
+    Rejected(Json<Rejection>),
+}
+
+impl Refused {
+    /// Pick out quota and hook refusals, which the user should see, from
+    /// other errors.
+    pub(crate) fn from_error(e: Error) -> Result<Refused> {
+        if let ErrorKind::QuotaExceeded(quota) = e.kind() {
+            return Ok(Refused::OverQuota(Json(OverQuota {
+                quota: quota.clone(),
+                reason: quota.to_string(),
+            })));
+        }
+
+        Ok(Refused::Rejected(Json(Rejection::from_error(e)?)))
+    }
+
+    /// Leases on asset types that require approval have to be requested for
+    /// a specific asset, so they can't be claimed, bundled, or waited for.
+    pub(crate) fn requires_approval(asset_type: &AssetType) -> Refused {
+        let reason = format!(
+            "{} require approval; request a specific asset instead.",
+            asset_type.plural_name()
+        );
+
+        Refused::Rejected(Json(Rejection { reason }))
+    }
+
+    pub fn reason(&self) -> &str {
+        match self {
+            Refused::OverQuota(x) => &x.reason,
+            Refused::Rejected(x) => x.reason(),
+        }
+    }
+}
+
 include!(concat!(env!("OUT_DIR"), "/redoc_static.rs"));
 
 #[get("/")]
@@ -0,0 +1,135 @@
+use crate::approvals::{self, Approved};
+use crate::errors::*;
+use crate::internal::db::Db;
+use crate::internal::hooks::Hooks;
+use crate::models::lease::Lease;
+use crate::models::lease_request::{DenyRequestForm, LeaseRequest, RequestStatus};
+use crate::models::user::User;
+
+use diesel::prelude::*;
+
+use rocket::http::Status;
+use rocket::request::State;
+
+use rocket_contrib::json::Json;
+
+use super::{Paged, Refused};
+
+/// Every pending request, oldest first.
+#[get("/", format = "application/json")]
+pub fn list(db: Db, _user: User) -> Result<Json<Paged<LeaseRequest>>> {
+    let pending = LeaseRequest::pending(&*db)?
+        .into_iter()
+        .map(|(request, _, _)| request)
+        .collect();
+
+    Ok(Json(Paged::new(pending)))
+}
+
+#[get("/<request_id>", format = "application/json")]
+pub fn detail(request_id: i32, db: Db, _user: User) -> Result<Option<Json<LeaseRequest>>> {
+    Ok(LeaseRequest::by_id(&*db, request_id)?.map(Json))
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum ApproveResponse {
+    Success(Json<Lease>),
+
+    Refused(Refused),
+
+    Status(Status),
+}
+
+#[post("/<request_id>/approve")]
+pub(crate) fn approve(
+    request_id: i32,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<ApproveResponse> {
+    let request = match LeaseRequest::by_id(&*db, request_id)? {
+        Some(x) => x,
+        None => return Ok(ApproveResponse::Status(Status::NotFound)),
+    };
+
+    if !approvals::can_decide(&*db, &user, &request)? {
+        return Ok(ApproveResponse::Status(Status::Forbidden));
+    }
+
+    match approvals::approve(&*db, &hooks, request_id, &user) {
+        Ok(Approved::Approved(_, lease)) => Ok(ApproveResponse::Success(Json(lease))),
+        Ok(Approved::NotPending) | Ok(Approved::Conflict) => {
+            Ok(ApproveResponse::Status(Status::Conflict))
+        }
+        Ok(Approved::Invalid) => Ok(ApproveResponse::Status(Status::BadRequest)),
+        Err(e) => Ok(ApproveResponse::Refused(Refused::from_error(e)?)),
+    }
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum DenyResponse {
+    Success(Json<LeaseRequest>),
+
+    Status(Status),
+}
+
+#[post("/<request_id>/deny", data = "<deny>", format = "application/json")]
+pub(crate) fn deny(
+    request_id: i32,
+    db: Db,
+    user: User,
+    deny: Json<DenyRequestForm>,
+    hooks: State<Hooks>,
+) -> Result<DenyResponse> {
+    let request = match LeaseRequest::by_id(&*db, request_id)? {
+        Some(x) => x,
+        None => return Ok(DenyResponse::Status(Status::NotFound)),
+    };
+
+    if !approvals::can_decide(&*db, &user, &request)? {
+        return Ok(DenyResponse::Status(Status::Forbidden));
+    }
+
+    let denied = approvals::deny(
+        &*db,
+        &hooks,
+        &request,
+        RequestStatus::Denied,
+        Some(&user),
+        deny.reason(),
+    )?;
+
+    match denied {
+        Some(x) => Ok(DenyResponse::Success(Json(x))),
+        None => Ok(DenyResponse::Status(Status::Conflict)),
+    }
+}
+
+/// Withdraw a pending request.
+#[delete("/<request_id>")]
+pub fn delete(request_id: i32, db: Db, user: User) -> Result<Status> {
+    use crate::schema::lease_requests::dsl::*;
+
+    let request = match LeaseRequest::by_id(&*db, request_id)? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
+
+    if request.user_id() != user.id() && !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    let to_delete = lease_requests
+        .filter(id.eq(request_id))
+        .filter(status.eq(RequestStatus::Pending));
+
+    let num_deleted_rows = diesel::delete(to_delete)
+        .execute(&*db)
+        .chain_err(|| "unable to delete lease request")?;
+
+    if num_deleted_rows == 1 {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::Conflict)
+    }
+}
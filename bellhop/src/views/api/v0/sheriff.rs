@@ -0,0 +1,108 @@
+use crate::errors::*;
+use crate::internal::db::Db;
+use crate::models::sheriff::{Sheriff as SheriffModel, SheriffFailure, SheriffRun};
+use crate::models::user::User;
+use crate::sheriff::{DryRun, Sheriff};
+
+use chrono::prelude::*;
+
+use rocket::http::Status;
+use rocket::request::State;
+
+use rocket_contrib::json::Json;
+
+use std::result::Result as StdResult;
+
+use super::Paged;
+
+/// How many failures are listed.
+const MAX_FAILURES: i64 = 100;
+
+/// How many runs and failures are shown in the status.
+const MAX_RECENT: i64 = 20;
+
+#[derive(Debug, Serialize)]
+pub struct SheriffStatus {
+    period_secs: u64,
+
+    last_checked: DateTime<Utc>,
+    next_run: Option<DateTime<Utc>>,
+
+    last_run: Option<SheriffRun>,
+    runs: Vec<SheriffRun>,
+
+    failures: Vec<SheriffFailure>,
+}
+
+/// What the sheriff has been up to, and when it'll run next. Only admins can
+/// see it.
+#[get("/", format = "application/json")]
+pub(crate) fn status(
+    db: Db,
+    user: User,
+    sheriff: State<Sheriff>,
+) -> Result<StdResult<Json<SheriffStatus>, Status>> {
+    if !user.can_write() {
+        return Ok(Err(Status::Forbidden));
+    }
+
+    let model = SheriffModel::get(&*db)?;
+    let runs = SheriffRun::recent(&*db, MAX_RECENT)?;
+
+    Ok(Ok(Json(SheriffStatus {
+        period_secs: sheriff.period().as_secs(),
+        last_checked: model.last_checked(),
+        next_run: model.next_run(),
+        last_run: runs.first().cloned(),
+        runs,
+        failures: SheriffFailure::recent(&*db, MAX_RECENT)?,
+    })))
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum RunResponse {
+    Success(Json<SheriffRun>),
+
+    DryRun(Json<DryRun>),
+
+    Status(Status),
+}
+
+/// Run the sheriff's tasks right away. With `dry_run`, report what would be
+/// evicted or warned instead of doing it. Only admins can run the sheriff.
+#[post("/run?<dry_run>")]
+pub(crate) fn run(
+    dry_run: Option<bool>,
+    db: Db,
+    user: User,
+    sheriff: State<Sheriff>,
+) -> Result<RunResponse> {
+    if !user.can_write() {
+        return Ok(RunResponse::Status(Status::Forbidden));
+    }
+
+    if dry_run.unwrap_or(false) {
+        return Ok(RunResponse::DryRun(Json(sheriff.dry_run(&*db)?)));
+    }
+
+    // The tasks get connections of their own.
+    drop(db);
+
+    match sheriff.run_now()? {
+        Some(x) => Ok(RunResponse::Success(Json(x))),
+        None => Ok(RunResponse::Status(Status::Conflict)),
+    }
+}
+
+/// The most recent errors the sheriff ran into, newest first. Only admins
+/// can see them.
+#[get("/failures", format = "application/json")]
+pub fn failures(db: Db, user: User) -> Result<StdResult<Json<Paged<SheriffFailure>>, Status>> {
+    if !user.can_write() {
+        return Ok(Err(Status::Forbidden));
+    }
+
+    let recent = SheriffFailure::recent(&*db, MAX_FAILURES)?;
+
+    Ok(Ok(Json(Paged::new(recent))))
+}
@@ -1,19 +1,34 @@
 use crate::errors::*;
+use crate::hooks::Data as HookData;
 use crate::internal::db::Db;
+use crate::internal::hooks::Hooks;
 use crate::internal::uri::Base;
+use crate::models::approver::Approver;
 use crate::models::asset::Asset;
-use crate::models::asset_type::{AssetType, CreateAssetType};
+use crate::models::asset_type::{
+    AssetType, CreateAssetType, SetApprovalForm, SetGraceForm, SetWarningsForm,
+};
+use crate::models::lease::{ClaimLeaseForm, Lease, Reserved};
+use crate::models::lease_field::{CreateOwnedLeaseField, FieldValue, LeaseField};
+use crate::models::lease_quota::{self, CreateOwnedLeaseQuota, LeaseQuota};
+use crate::models::probe::{CreateOwnedProbe, Probe, ProbeCommands, ProbeResult};
+use crate::models::recurring_reservation::{
+    CreateRecurringReservationForm, RecurringReservation, RecurringReservationTag,
+};
+use crate::models::tag::WantedTag;
 use crate::models::tag_type::{CreateOwnedTagType, TagType};
 use crate::models::user::User;
+use crate::models::waitlist::{CreateWaitlistEntryForm, WaitlistEntry, WaitlistTag};
 
 use diesel::prelude::*;
 
 use rocket::http::hyper::header::Location;
 use rocket::http::Status;
+use rocket::request::State;
 
 use rocket_contrib::json::Json;
 
-use super::Paged;
+use super::{Paged, Refused};
 
 #[get("/", format = "application/json")]
 pub fn list(db: Db, _user: User) -> Result<Json<Paged<AssetType>>> {
@@ -46,6 +61,10 @@ pub fn create(db: Db, user: User, create: Json<CreateAssetType>, base: Base) ->
         return Ok(Create::Status(Status::Forbidden));
     }
 
+    if !create.is_valid() {
+        return Ok(Create::Status(Status::BadRequest));
+    }
+
     let created = create.insert(&db.into())?;
     let location = uri!(detail: type_id = created.id());
 
@@ -183,6 +202,263 @@ pub fn tag_types(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<
     Ok(Some(Json(Paged::new(types))))
 }
 
+#[get("/<type_id>/lease-fields", format = "application/json")]
+pub fn lease_fields(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<LeaseField>>>> {
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(None);
+    }
+
+    let fields = LeaseField::for_types(&*db, &[type_id])?;
+
+    Ok(Some(Json(Paged::new(fields))))
+}
+
+#[derive(Debug, Responder)]
+pub enum CreateLeaseFieldResponse {
+    #[response(status = 201)]
+    Success(Json<LeaseField>),
+
+    Status(Status),
+}
+
+#[post(
+    "/<type_id>/lease-fields",
+    data = "<create>",
+    format = "application/json"
+)]
+pub fn create_lease_field(
+    type_id: i32,
+    db: Db,
+    user: User,
+    create: Json<CreateOwnedLeaseField>,
+) -> Result<CreateLeaseFieldResponse> {
+    if !user.can_write() {
+        return Ok(CreateLeaseFieldResponse::Status(Status::Forbidden));
+    }
+
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(CreateLeaseFieldResponse::Status(Status::NotFound));
+    }
+
+    let form = create.into_inner().into_create_lease_field(type_id);
+
+    if !form.is_valid() {
+        return Ok(CreateLeaseFieldResponse::Status(Status::BadRequest));
+    }
+
+    let existing = LeaseField::for_types(&*db, &[type_id])?;
+    if existing.iter().any(|x| x.name() == form.name()) {
+        return Ok(CreateLeaseFieldResponse::Status(Status::Conflict));
+    }
+
+    let created = form.insert(&db.into())?;
+
+    Ok(CreateLeaseFieldResponse::Success(Json(created)))
+}
+
+#[delete("/<type_id>/lease-fields/<lease_field_id>")]
+pub fn delete_lease_field(type_id: i32, lease_field_id: i32, db: Db, user: User) -> Result<Status> {
+    use crate::schema::lease_fields::dsl as lf;
+
+    if !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    let num_deleted_rows = diesel::delete(lf::lease_fields)
+        .filter(lf::id.eq(lease_field_id).and(lf::asset_type_id.eq(type_id)))
+        .execute(&*db)
+        .chain_err(|| "unable to delete lease field")?;
+
+    if num_deleted_rows == 1 {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[get("/<type_id>/quotas", format = "application/json")]
+pub fn quotas(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<LeaseQuota>>>> {
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(None);
+    }
+
+    let quotas = LeaseQuota::for_type(&*db, type_id)?;
+
+    Ok(Some(Json(Paged::new(quotas))))
+}
+
+#[derive(Debug, Responder)]
+pub enum SetQuotaResponse {
+    Success(Json<LeaseQuota>),
+    Status(Status),
+}
+
+/// Replace the quota for `user_id`, or the default quota if it's `None`.
+fn set_quota(
+    type_id: i32,
+    user_id: Option<i32>,
+    db: Db,
+    user: User,
+    create: Json<CreateOwnedLeaseQuota>,
+) -> Result<SetQuotaResponse> {
+    if !user.can_write() {
+        return Ok(SetQuotaResponse::Status(Status::Forbidden));
+    }
+
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(SetQuotaResponse::Status(Status::NotFound));
+    }
+
+    if let Some(x) = user_id {
+        if let None = User::by_id(&(&db).into(), x)? {
+            return Ok(SetQuotaResponse::Status(Status::NotFound));
+        }
+    }
+
+    let form = create
+        .into_inner()
+        .into_create_lease_quota(type_id, user_id);
+
+    if !form.is_valid() {
+        return Ok(SetQuotaResponse::Status(Status::BadRequest));
+    }
+
+    Ok(SetQuotaResponse::Success(Json(form.insert(&*db)?)))
+}
+
+fn delete_quota(type_id: i32, user_id: Option<i32>, db: Db, user: User) -> Result<Status> {
+    if !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    if LeaseQuota::delete(&*db, type_id, user_id)? {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[put(
+    "/<type_id>/quotas/default",
+    data = "<create>",
+    format = "application/json"
+)]
+pub fn set_default_quota(
+    type_id: i32,
+    db: Db,
+    user: User,
+    create: Json<CreateOwnedLeaseQuota>,
+) -> Result<SetQuotaResponse> {
+    set_quota(type_id, None, db, user, create)
+}
+
+#[delete("/<type_id>/quotas/default")]
+pub fn delete_default_quota(type_id: i32, db: Db, user: User) -> Result<Status> {
+    delete_quota(type_id, None, db, user)
+}
+
+#[put(
+    "/<type_id>/quotas/users/<user_id>",
+    data = "<create>",
+    format = "application/json"
+)]
+pub fn set_user_quota(
+    type_id: i32,
+    user_id: i32,
+    db: Db,
+    user: User,
+    create: Json<CreateOwnedLeaseQuota>,
+) -> Result<SetQuotaResponse> {
+    set_quota(type_id, Some(user_id), db, user, create)
+}
+
+#[delete("/<type_id>/quotas/users/<user_id>")]
+pub fn delete_user_quota(type_id: i32, user_id: i32, db: Db, user: User) -> Result<Status> {
+    delete_quota(type_id, Some(user_id), db, user)
+}
+
+#[get("/<type_id>/probes", format = "application/json")]
+pub fn probes(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<Probe>>>> {
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(None);
+    }
+
+    let probes = Probe::for_type(&*db, type_id)?;
+
+    Ok(Some(Json(Paged::new(probes))))
+}
+
+#[derive(Debug, Responder)]
+pub enum CreateProbeResponse {
+    #[response(status = 201)]
+    Success(Json<Probe>),
+
+    Status(Status),
+}
+
+#[post("/<type_id>/probes", data = "<create>", format = "application/json")]
+pub(crate) fn create_probe(
+    type_id: i32,
+    db: Db,
+    user: User,
+    commands: State<ProbeCommands>,
+    create: Json<CreateOwnedProbe>,
+) -> Result<CreateProbeResponse> {
+    if !user.can_write() {
+        return Ok(CreateProbeResponse::Status(Status::Forbidden));
+    }
+
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(CreateProbeResponse::Status(Status::NotFound));
+    }
+
+    let form = create.into_inner().into_create_probe(type_id);
+
+    if !form.is_valid(&commands) {
+        return Ok(CreateProbeResponse::Status(Status::BadRequest));
+    }
+
+    let existing = Probe::for_type(&*db, type_id)?;
+    if existing.iter().any(|x| x.name() == form.name()) {
+        return Ok(CreateProbeResponse::Status(Status::Conflict));
+    }
+
+    let created = form.insert(&db.into())?;
+
+    Ok(CreateProbeResponse::Success(Json(created)))
+}
+
+#[delete("/<type_id>/probes/<probe_id>")]
+pub fn delete_probe(type_id: i32, probe_id: i32, db: Db, user: User) -> Result<Status> {
+    use crate::schema::probes::dsl as p;
+
+    if !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    let num_deleted_rows = diesel::delete(p::probes)
+        .filter(p::id.eq(probe_id).and(p::asset_type_id.eq(type_id)))
+        .execute(&*db)
+        .chain_err(|| "unable to delete probe")?;
+
+    if num_deleted_rows == 1 {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[get("/<type_id>/health", format = "application/json")]
+pub fn health(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<ProbeResult>>>> {
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(None);
+    }
+
+    let results = ProbeResult::for_type(&*db, type_id)?;
+
+    Ok(Some(Json(Paged::new(results))))
+}
+
 #[get("/<type_id>/assets", format = "application/json")]
 pub fn assets(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<Asset>>>> {
     let asset_type = match AssetType::by_id(&*db, type_id)? {
@@ -196,3 +472,505 @@ pub fn assets(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<Ass
 
     Ok(Some(Json(Paged::new(assets))))
 }
+
+#[derive(Debug, Serialize)]
+pub struct Waiter {
+    #[serde(flatten)]
+    entry: WaitlistEntry,
+    tags: Vec<WaitlistTag>,
+}
+
+#[get("/<type_id>/waitlist", format = "application/json")]
+pub fn waitlist(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<Waiter>>>> {
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(None);
+    }
+
+    let entries: Vec<WaitlistEntry> = WaitlistEntry::queue(&*db, type_id)?
+        .into_iter()
+        .map(|(entry, _)| entry)
+        .collect();
+
+    let tags = WaitlistTag::belonging_to(&entries)
+        .load::<WaitlistTag>(&*db)
+        .chain_err(|| "unable to get tags for waitlist")?
+        .grouped_by(&entries);
+
+    let waiters = entries
+        .into_iter()
+        .zip(tags)
+        .map(|(entry, tags)| Waiter { entry, tags })
+        .collect();
+
+    Ok(Some(Json(Paged::new(waiters))))
+}
+
+#[get("/<type_id>/waitlist/<entry_id>", format = "application/json")]
+pub fn waitlist_detail(
+    type_id: i32,
+    entry_id: i32,
+    db: Db,
+    _user: User,
+) -> Result<Option<Json<Waiter>>> {
+    let entry = match WaitlistEntry::by_id(&*db, entry_id)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    if entry.asset_type_id() != type_id {
+        return Ok(None);
+    }
+
+    let tags = WaitlistTag::belonging_to(&entry)
+        .load(&*db)
+        .chain_err(|| "unable to get tags for waitlist entry")?;
+
+    Ok(Some(Json(Waiter { entry, tags })))
+}
+
+#[derive(Debug, Responder)]
+#[response(status = 201)]
+pub struct JoinWaitlistSuccess {
+    body: Json<Waiter>,
+    location: Location,
+}
+
+#[derive(Debug, Responder)]
+pub enum JoinWaitlist {
+    Success(JoinWaitlistSuccess),
+    Status(Status),
+}
+
+#[post("/<type_id>/waitlist", data = "<create>", format = "application/json")]
+pub(crate) fn join_waitlist(
+    type_id: i32,
+    db: Db,
+    user: User,
+    create: Json<CreateWaitlistEntryForm>,
+    base: Base,
+    hooks: State<Hooks>,
+) -> Result<JoinWaitlist> {
+    let asset_type = match AssetType::by_id(&*db, type_id)? {
+        Some(x) => x,
+        None => return Ok(JoinWaitlist::Status(Status::NotFound)),
+    };
+
+    let joined = crate::waitlist::join(&*db, &hooks, &asset_type, user.id(), &create)?;
+
+    let (entry, tags) = match joined {
+        Some(x) => x,
+        None => return Ok(JoinWaitlist::Status(Status::BadRequest)),
+    };
+
+    let location = uri!(
+        waitlist_detail: type_id = type_id,
+        entry_id = entry.id()
+    );
+
+    let result = JoinWaitlistSuccess {
+        location: Location(base.join(location).to_string()),
+        body: Json(Waiter { entry, tags }),
+    };
+
+    Ok(JoinWaitlist::Success(result))
+}
+
+#[delete("/<type_id>/waitlist/<entry_id>")]
+pub fn leave_waitlist(type_id: i32, entry_id: i32, db: Db, user: User) -> Result<Status> {
+    let entry = match WaitlistEntry::by_id(&*db, entry_id)? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
+
+    if entry.asset_type_id() != type_id {
+        return Ok(Status::NotFound);
+    }
+
+    if entry.user_id() != user.id() && !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    let num_deleted_rows = diesel::delete(&entry)
+        .execute(&*db)
+        .chain_err(|| "unable to delete waitlist entry")?;
+
+    if num_deleted_rows == 1 {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Recurring {
+    #[serde(flatten)]
+    rule: RecurringReservation,
+    tags: Vec<RecurringReservationTag>,
+}
+
+/// Look up the recurring reservation identified by `rule_id`, if it belongs
+/// to the asset type identified by `type_id`.
+fn find_recurring(
+    c: &PgConnection,
+    type_id: i32,
+    rule_id: i32,
+) -> Result<Option<RecurringReservation>> {
+    let rule = RecurringReservation::by_id(c, rule_id)?;
+
+    Ok(rule.filter(|x| x.asset_type_id() == type_id))
+}
+
+#[get("/<type_id>/recurring", format = "application/json")]
+pub fn recurring(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<Recurring>>>> {
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(None);
+    }
+
+    let rules: Vec<RecurringReservation> = RecurringReservation::for_type(&*db, type_id)?
+        .into_iter()
+        .map(|(rule, _)| rule)
+        .collect();
+
+    let tags = RecurringReservationTag::belonging_to(&rules)
+        .load::<RecurringReservationTag>(&*db)
+        .chain_err(|| "unable to get tags for recurring reservations")?
+        .grouped_by(&rules);
+
+    let recurring = rules
+        .into_iter()
+        .zip(tags)
+        .map(|(rule, tags)| Recurring { rule, tags })
+        .collect();
+
+    Ok(Some(Json(Paged::new(recurring))))
+}
+
+#[get("/<type_id>/recurring/<rule_id>", format = "application/json")]
+pub fn recurring_detail(
+    type_id: i32,
+    rule_id: i32,
+    db: Db,
+    _user: User,
+) -> Result<Option<Json<Recurring>>> {
+    let rule = match find_recurring(&*db, type_id, rule_id)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let tags = RecurringReservationTag::belonging_to(&rule)
+        .load(&*db)
+        .chain_err(|| "unable to get tags for recurring reservation")?;
+
+    Ok(Some(Json(Recurring { rule, tags })))
+}
+
+#[derive(Debug, Responder)]
+#[response(status = 201)]
+pub struct CreateRecurringSuccess {
+    body: Json<Recurring>,
+    location: Location,
+}
+
+#[derive(Debug, Responder)]
+pub enum CreateRecurring {
+    Success(CreateRecurringSuccess),
+    Status(Status),
+}
+
+#[post("/<type_id>/recurring", data = "<create>", format = "application/json")]
+pub(crate) fn create_recurring(
+    type_id: i32,
+    db: Db,
+    user: User,
+    create: Json<CreateRecurringReservationForm>,
+    base: Base,
+) -> Result<CreateRecurring> {
+    let asset_type = match AssetType::by_id(&*db, type_id)? {
+        Some(x) => x,
+        None => return Ok(CreateRecurring::Status(Status::NotFound)),
+    };
+
+    if !create.is_valid_for(&*db, &asset_type)? {
+        return Ok(CreateRecurring::Status(Status::BadRequest));
+    }
+
+    let (rule, tags) = create.insert(&*db, type_id, user.id())?;
+
+    let location = uri!(
+        recurring_detail: type_id = type_id,
+        rule_id = rule.id()
+    );
+
+    let result = CreateRecurringSuccess {
+        location: Location(base.join(location).to_string()),
+        body: Json(Recurring { rule, tags }),
+    };
+
+    Ok(CreateRecurring::Success(result))
+}
+
+#[derive(Debug, Responder)]
+pub enum UpdateRecurring {
+    Success(Json<Recurring>),
+    Status(Status),
+}
+
+/// Replace a recurring reservation's schedule. Upcoming leases from the old
+/// schedule are deleted, and the sheriff creates new ones on its next round.
+#[put(
+    "/<type_id>/recurring/<rule_id>",
+    data = "<update>",
+    format = "application/json"
+)]
+pub(crate) fn update_recurring(
+    type_id: i32,
+    rule_id: i32,
+    db: Db,
+    user: User,
+    update: Json<CreateRecurringReservationForm>,
+) -> Result<UpdateRecurring> {
+    let rule = match find_recurring(&*db, type_id, rule_id)? {
+        Some(x) => x,
+        None => return Ok(UpdateRecurring::Status(Status::NotFound)),
+    };
+
+    if rule.user_id() != user.id() && !user.can_write() {
+        return Ok(UpdateRecurring::Status(Status::Forbidden));
+    }
+
+    let asset_type = AssetType::by_id(&*db, type_id)?.chain_err(|| "missing asset_type")?;
+
+    if !update.is_valid_for(&*db, &asset_type)? {
+        return Ok(UpdateRecurring::Status(Status::BadRequest));
+    }
+
+    let (rule, tags) = rule.update(&*db, &update)?;
+
+    Ok(UpdateRecurring::Success(Json(Recurring { rule, tags })))
+}
+
+/// Delete a recurring reservation, along with the upcoming leases created
+/// for it. Leases that have already started are left alone.
+#[delete("/<type_id>/recurring/<rule_id>")]
+pub fn delete_recurring(type_id: i32, rule_id: i32, db: Db, user: User) -> Result<Status> {
+    let rule = match find_recurring(&*db, type_id, rule_id)? {
+        Some(x) => x,
+        None => return Ok(Status::NotFound),
+    };
+
+    if rule.user_id() != user.id() && !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    let num_deleted_rows = db.transaction::<_, Error, _>(|| {
+        rule.delete_upcoming(&*db)?;
+
+        diesel::delete(&rule)
+            .execute(&*db)
+            .chain_err(|| "unable to delete recurring reservation")
+    })?;
+
+    if num_deleted_rows == 1 {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Claimed {
+    lease: Lease,
+    asset: Asset,
+}
+
+#[derive(Debug, Responder)]
+pub(crate) enum ClaimLeaseResponse {
+    #[response(status = 201)]
+    Success(Json<Claimed>),
+
+    Refused(Refused),
+
+    Status(Status),
+}
+
+#[post("/<type_id>/lease", data = "<claim>", format = "application/json")]
+pub(crate) fn claim_lease(
+    type_id: i32,
+    db: Db,
+    user: User,
+    claim: Json<ClaimLeaseForm>,
+    hooks: State<Hooks>,
+) -> Result<ClaimLeaseResponse> {
+    let asset_type = match AssetType::by_id(&*db, type_id)? {
+        Some(x) => x,
+        None => return Ok(ClaimLeaseResponse::Status(Status::NotFound)),
+    };
+
+    if asset_type.requires_approval() {
+        let refused = Refused::requires_approval(&asset_type);
+        return Ok(ClaimLeaseResponse::Refused(refused));
+    }
+
+    let tag_types: Vec<TagType> = TagType::belonging_to(&asset_type)
+        .load(&*db)
+        .chain_err(|| "unable to get tag types belonging to an asset type")?;
+
+    if !WantedTag::all_valid(claim.tags(), &tag_types) {
+        return Ok(ClaimLeaseResponse::Status(Status::BadRequest));
+    }
+
+    let lease_fields = LeaseField::for_types(&*db, &[type_id])?;
+
+    let fields = match LeaseField::resolve(&lease_fields, claim.fields()) {
+        Some(x) => x,
+        None => return Ok(ClaimLeaseResponse::Status(Status::BadRequest)),
+    };
+
+    let create_lease = claim.to_create_lease(user.id());
+
+    // The hooks run inside the transaction, so they can cancel the lease.
+    let result = db.transaction::<_, Error, _>(|| {
+        let (lease, asset) = match create_lease.claim(&*db, type_id, claim.tags(), &fields)? {
+            Reserved::Started(lease, asset) => (lease, asset),
+            Reserved::Upcoming(_) | Reserved::Conflict => {
+                return Ok(ClaimLeaseResponse::Status(Status::Conflict))
+            }
+            Reserved::Invalid => return Ok(ClaimLeaseResponse::Status(Status::BadRequest)),
+        };
+
+        lease_quota::enforce(&*db, &lease, &[type_id])?;
+
+        let fields = FieldValue::for_lease(&*db, lease.id())?;
+
+        let data = HookData::new(&lease, &asset, &asset_type).with_fields(&fields);
+        hooks.before_lease(&*db, data.clone())?;
+        hooks.leased(&*db, data)?;
+
+        Ok(ClaimLeaseResponse::Success(Json(Claimed { lease, asset })))
+    });
+
+    match result {
+        Ok(x) => Ok(x),
+        Err(e) => Ok(ClaimLeaseResponse::Refused(Refused::from_error(e)?)),
+    }
+}
+
+#[derive(Debug, Responder)]
+pub enum UpdateResponse {
+    Success(Json<AssetType>),
+    Status(Status),
+}
+
+#[put("/<type_id>/approval", data = "<form>", format = "application/json")]
+pub fn set_approval(
+    type_id: i32,
+    db: Db,
+    user: User,
+    form: Json<SetApprovalForm>,
+) -> Result<UpdateResponse> {
+    if !user.can_write() {
+        return Ok(UpdateResponse::Status(Status::Forbidden));
+    }
+
+    let asset_type = match AssetType::by_id(&*db, type_id)? {
+        Some(x) => x,
+        None => return Ok(UpdateResponse::Status(Status::NotFound)),
+    };
+
+    let updated = asset_type.set_approval(&*db, &form)?;
+
+    Ok(UpdateResponse::Success(Json(updated)))
+}
+
+#[put("/<type_id>/warnings", data = "<form>", format = "application/json")]
+pub fn set_warnings(
+    type_id: i32,
+    db: Db,
+    user: User,
+    form: Json<SetWarningsForm>,
+) -> Result<UpdateResponse> {
+    if !user.can_write() {
+        return Ok(UpdateResponse::Status(Status::Forbidden));
+    }
+
+    if !form.is_valid() {
+        return Ok(UpdateResponse::Status(Status::BadRequest));
+    }
+
+    let asset_type = match AssetType::by_id(&*db, type_id)? {
+        Some(x) => x,
+        None => return Ok(UpdateResponse::Status(Status::NotFound)),
+    };
+
+    let updated = asset_type.set_warnings(&*db, &form)?;
+
+    Ok(UpdateResponse::Success(Json(updated)))
+}
+
+#[put("/<type_id>/grace", data = "<form>", format = "application/json")]
+pub fn set_grace(
+    type_id: i32,
+    db: Db,
+    user: User,
+    form: Json<SetGraceForm>,
+) -> Result<UpdateResponse> {
+    if !user.can_write() {
+        return Ok(UpdateResponse::Status(Status::Forbidden));
+    }
+
+    if !form.is_valid() {
+        return Ok(UpdateResponse::Status(Status::BadRequest));
+    }
+
+    let asset_type = match AssetType::by_id(&*db, type_id)? {
+        Some(x) => x,
+        None => return Ok(UpdateResponse::Status(Status::NotFound)),
+    };
+
+    let updated = asset_type.set_grace(&*db, &form)?;
+
+    Ok(UpdateResponse::Success(Json(updated)))
+}
+
+#[get("/<type_id>/approvers", format = "application/json")]
+pub fn approvers(type_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<User>>>> {
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(None);
+    }
+
+    let approvers = Approver::for_type(&*db, type_id)?;
+
+    Ok(Some(Json(Paged::new(approvers))))
+}
+
+#[put("/<type_id>/approvers/<user_id>")]
+pub fn add_approver(type_id: i32, user_id: i32, db: Db, user: User) -> Result<Status> {
+    if !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    if let None = AssetType::by_id(&*db, type_id)? {
+        return Ok(Status::NotFound);
+    }
+
+    if let None = User::by_id(&(&db).into(), user_id)? {
+        return Ok(Status::NotFound);
+    }
+
+    Approver::new(type_id, user_id).insert(&*db)?;
+
+    Ok(Status::NoContent)
+}
+
+#[delete("/<type_id>/approvers/<user_id>")]
+pub fn delete_approver(type_id: i32, user_id: i32, db: Db, user: User) -> Result<Status> {
+    if !user.can_write() {
+        return Ok(Status::Forbidden);
+    }
+
+    if Approver::new(type_id, user_id).delete(&*db)? {
+        Ok(Status::NoContent)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
@@ -0,0 +1,22 @@
+use crate::errors::*;
+use crate::internal::db::Db;
+use crate::models::ended_lease::EndedLease;
+use crate::models::user::User;
+
+use rocket_contrib::json::Json;
+
+use super::Paged;
+
+#[get("/<user_id>/leases", format = "application/json")]
+pub fn leases(user_id: i32, db: Db, _user: User) -> Result<Option<Json<Paged<EndedLease>>>> {
+    if let None = User::by_id(&(&db).into(), user_id)? {
+        return Ok(None);
+    }
+
+    let ended = EndedLease::for_user(&*db, user_id)?
+        .into_iter()
+        .map(|(lease, _)| lease)
+        .collect();
+
+    Ok(Some(Json(Paged::new(ended))))
+}
@@ -0,0 +1,112 @@
+use crate::errors::*;
+use crate::internal::db::Db;
+use crate::internal::hooks::Hooks;
+use crate::models::approver::Approver;
+use crate::models::asset::Asset;
+use crate::models::lease_request::{DenyRequestForm, LeaseRequest};
+use crate::models::user::User;
+
+use rocket::http::Status;
+use rocket::request::{FlashMessage, Form, State};
+use rocket::response::{Flash, Redirect};
+
+use rocket_contrib::json::Json;
+use rocket_contrib::templates::Template;
+
+use std::result::Result as StdResult;
+
+/********************************************
+Everything below is mouted under: "/approvals"
+*********************************************/
+
+#[get("/")]
+pub fn list(db: Db, user: User, flash: Option<FlashMessage>) -> Result<Template> {
+    let type_ids = Approver::type_ids_for(&db, user.id())?;
+
+    let mut mine = vec![];
+    let mut to_decide = vec![];
+
+    for (request, asset, requester) in LeaseRequest::pending(&db)? {
+        if request.user_id() == user.id() {
+            mine.push((request, asset));
+        } else if type_ids.contains(&asset.type_id()) {
+            to_decide.push((request, asset, requester));
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Context {
+        mine: Vec<(LeaseRequest, Asset)>,
+        to_decide: Vec<(LeaseRequest, Asset, User)>,
+        user: User,
+        flash: Option<(String, String)>,
+    }
+
+    let flash = flash.map(|x| (x.name().to_owned(), x.msg().to_owned()));
+
+    Ok(Template::render(
+        "approvals/list",
+        Context {
+            mine,
+            to_decide,
+            user,
+            flash,
+        },
+    ))
+}
+
+#[post("/<request_id>/approve")]
+pub(crate) fn approve(
+    request_id: i32,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<Option<StdResult<Flash<Redirect>, Status>>> {
+    use crate::views::api::v0::requests::{self as api, ApproveResponse};
+
+    let dest = Redirect::to("/approvals");
+
+    match api::approve(request_id, db, user, hooks)? {
+        ApproveResponse::Success(_) => Ok(Some(Ok(Flash::success(dest, "Approved.")))),
+        ApproveResponse::Refused(x) => Ok(Some(Ok(Flash::error(dest, x.reason())))),
+        ApproveResponse::Status(Status::Conflict) => Ok(Some(Ok(Flash::error(
+            dest,
+            "The request was already decided on, or the asset isn't free anymore.",
+        )))),
+        ApproveResponse::Status(Status::NotFound) => Ok(None),
+        ApproveResponse::Status(x) => Ok(Some(Err(x))),
+    }
+}
+
+#[post("/<request_id>/deny", data = "<form>")]
+pub(crate) fn deny(
+    request_id: i32,
+    form: Form<DenyRequestForm>,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<Option<StdResult<Flash<Redirect>, Status>>> {
+    use crate::views::api::v0::requests::{self as api, DenyResponse};
+
+    let dest = Redirect::to("/approvals");
+
+    match api::deny(request_id, db, user, Json(form.into_inner()), hooks)? {
+        DenyResponse::Success(_) => Ok(Some(Ok(Flash::success(dest, "Denied.")))),
+        DenyResponse::Status(Status::Conflict) => Ok(Some(Ok(Flash::error(
+            dest,
+            "The request was already decided on.",
+        )))),
+        DenyResponse::Status(Status::NotFound) => Ok(None),
+        DenyResponse::Status(x) => Ok(Some(Err(x))),
+    }
+}
+
+#[delete("/<request_id>")]
+pub fn delete(request_id: i32, db: Db, user: User) -> Result<StdResult<Redirect, Status>> {
+    use crate::views::api::v0::requests as api;
+
+    match api::delete(request_id, db, user)? {
+        Status::NoContent => Ok(Ok(Redirect::to("/approvals"))),
+        x => Ok(Err(x)),
+    }
+}
@@ -1,21 +1,27 @@
 use crate::errors::*;
-use crate::hooks::Data as HookData;
 use crate::internal::db::Db;
 use crate::internal::hooks::Hooks;
-use crate::models::asset::Asset;
+use crate::models::asset::{Asset, SetAssetStatusForm};
 use crate::models::asset_type::AssetType;
-use crate::models::lease::{CreateLeaseForm, Lease};
+use crate::models::ended_lease::EndedLease;
+use crate::models::lease::{
+    CreateLeaseForm, ExtendLeaseForm, Lease, RevokeLeaseForm, TransferLeaseForm,
+};
+use crate::models::lease_field::{FieldValue, LeaseField};
+use crate::models::lease_transfer::LeaseTransfer;
+use crate::models::probe::{Probe, ProbeResult};
+use crate::models::reservation::Reservation;
 use crate::models::tag::Tag;
 use crate::models::tag_type::TagType;
 use crate::models::user::User;
 
-use diesel;
 use diesel::prelude::*;
 
 use rocket::http::Status;
-use rocket::request::{Form, State};
-use rocket::response::Redirect;
+use rocket::request::{FlashMessage, Form, State};
+use rocket::response::{Flash, Redirect};
 
+use rocket_contrib::json::Json;
 use rocket_contrib::templates::Template;
 
 use std::result::Result as StdResult;
@@ -31,58 +37,259 @@ pub(crate) fn create_lease(
     db: Db,
     user: User,
     hooks: State<Hooks>,
+) -> Result<Option<StdResult<Flash<Redirect>, Status>>> {
+    use crate::views::api::v0::assets::{self as api, CreateLeaseResponse};
+
+    let dest = format!("/assets/{}", asset_id);
+
+    match api::create_lease(asset_id, db, user, Json(form.into_inner()), hooks)? {
+        CreateLeaseResponse::Success(_) => {
+            Ok(Some(Ok(Flash::success(Redirect::to(dest), "Leased."))))
+        }
+        CreateLeaseResponse::Requested(_) => Ok(Some(Ok(Flash::success(
+            Redirect::to("/approvals"),
+            "Requested. You'll get the lease once an approver agrees.",
+        )))),
+        CreateLeaseResponse::OutOfService(x) => {
+            let msg = match x.status_reason() {
+                Some(reason) => format!("This asset is out of service: {}", reason),
+                None => "This asset is out of service.".to_owned(),
+            };
+
+            Ok(Some(Ok(Flash::error(Redirect::to(dest), msg))))
+        }
+        CreateLeaseResponse::Refused(x) => {
+            Ok(Some(Ok(Flash::error(Redirect::to(dest), x.reason()))))
+        }
+        CreateLeaseResponse::Status(Status::NotFound) => Ok(None),
+        CreateLeaseResponse::Status(x) => Ok(Some(Err(x))),
+    }
+}
+
+#[delete("/<asset_id>/lease")]
+pub(crate) fn delete_lease(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<Option<StdResult<Flash<Redirect>, Status>>> {
+    use crate::views::api::v0::assets as api;
+    use crate::views::api::v0::leases::ReleaseResponse;
+
+    let dest = format!("/assets/{}", asset_id);
+
+    match api::delete_lease(asset_id, db, user, hooks)? {
+        ReleaseResponse::Status(Status::NoContent) => {
+            Ok(Some(Ok(Flash::success(Redirect::to(dest), "Returned."))))
+        }
+        ReleaseResponse::Rejected(x) => Ok(Some(Ok(Flash::error(Redirect::to(dest), x.reason())))),
+        ReleaseResponse::Status(Status::NotFound) => Ok(None),
+        ReleaseResponse::Status(x) => Ok(Some(Err(x))),
+    }
+}
+
+#[patch("/<asset_id>/lease", data = "<form>")]
+pub(crate) fn extend_lease(
+    asset_id: i32,
+    form: Form<ExtendLeaseForm>,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<StdResult<Redirect, Status>> {
+    use crate::views::api::v0::assets::{self as api, ExtendLeaseResponse};
+
+    match api::extend_lease(asset_id, db, user, Json(form.into_inner()), hooks)? {
+        ExtendLeaseResponse::Success(_) => {
+            let dest = format!("/assets/{}", asset_id);
+            Ok(Ok(Redirect::to(dest)))
+        }
+        ExtendLeaseResponse::Status(x) => Ok(Err(x)),
+    }
+}
+
+#[post("/<asset_id>/lease/revoke", data = "<form>")]
+pub(crate) fn revoke_lease(
+    asset_id: i32,
+    form: Form<RevokeLeaseForm>,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<Option<StdResult<Redirect, Status>>> {
+    use crate::views::api::v0::assets as api;
+
+    match api::revoke_lease(asset_id, db, user, Json(form.into_inner()), hooks)? {
+        Status::NoContent => {
+            let dest = format!("/assets/{}", asset_id);
+            Ok(Some(Ok(Redirect::to(dest))))
+        }
+        Status::NotFound => Ok(None),
+        x => Ok(Some(Err(x))),
+    }
+}
+
+#[put("/<asset_id>/status", data = "<form>")]
+pub(crate) fn set_status(
+    asset_id: i32,
+    form: Form<SetAssetStatusForm>,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<Option<StdResult<Redirect, Status>>> {
+    use crate::views::api::v0::assets::{self as api, SetStatusResponse};
+
+    match api::set_status(asset_id, db, user, Json(form.into_inner()), hooks)? {
+        SetStatusResponse::Success(_) => {
+            let dest = format!("/assets/{}", asset_id);
+            Ok(Some(Ok(Redirect::to(dest))))
+        }
+        SetStatusResponse::Status(Status::NotFound) => Ok(None),
+        SetStatusResponse::Status(x) => Ok(Some(Err(x))),
+    }
+}
+
+#[post("/<asset_id>/lease/transfer", data = "<form>")]
+pub(crate) fn transfer_lease(
+    asset_id: i32,
+    form: Form<TransferLeaseForm>,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<Option<StdResult<Redirect, Status>>> {
+    use crate::views::api::v0::assets::{self as api, TransferLeaseResponse};
+
+    match api::transfer_lease(asset_id, db, user, Json(form.into_inner()), hooks)? {
+        TransferLeaseResponse::Status(Status::NotFound) => Ok(None),
+        TransferLeaseResponse::Status(x) => Ok(Some(Err(x))),
+        _ => {
+            let dest = format!("/assets/{}", asset_id);
+            Ok(Some(Ok(Redirect::to(dest))))
+        }
+    }
+}
+
+#[post("/<asset_id>/lease/transfer/accept")]
+pub(crate) fn accept_transfer(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
 ) -> Result<Option<StdResult<Redirect, Status>>> {
-    use crate::schema::assets::dsl::*;
+    use crate::views::api::v0::assets::{self as api, TransferLeaseResponse};
 
-    let lease = form
-        .into_inner()
-        .into_create_lease(user.id())
-        .insert(&(&*db).into())?;
+    match api::accept_transfer(asset_id, db, user, hooks)? {
+        TransferLeaseResponse::Status(Status::NotFound) => Ok(None),
+        TransferLeaseResponse::Status(x) => Ok(Some(Err(x))),
+        _ => {
+            let dest = format!("/assets/{}", asset_id);
+            Ok(Some(Ok(Redirect::to(dest))))
+        }
+    }
+}
 
-    let to_update = assets.filter(id.eq(asset_id).and(lease_id.is_null()));
+#[delete("/<asset_id>/lease/transfer")]
+pub(crate) fn delete_transfer(
+    asset_id: i32,
+    db: Db,
+    user: User,
+) -> Result<Option<StdResult<Redirect, Status>>> {
+    use crate::views::api::v0::assets as api;
 
-    // TODO: Leaks leases when the update fails
-    let updated: Option<Asset> = diesel::update(to_update)
-        .set(lease_id.eq(Some(lease.id())))
-        .get_result(&*db)
-        .optional()
-        .chain_err(|| "unable to update asset with new lease")?;
+    match api::delete_transfer(asset_id, db, user)? {
+        Status::NoContent => {
+            let dest = format!("/assets/{}", asset_id);
+            Ok(Some(Ok(Redirect::to(dest))))
+        }
+        Status::NotFound => Ok(None),
+        x => Ok(Some(Err(x))),
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct AddCoOwnerForm {
+    email: String,
+}
 
-    let asset = match updated {
+#[post("/<asset_id>/lease/users", data = "<form>")]
+pub fn add_co_owner(
+    asset_id: i32,
+    form: Form<AddCoOwnerForm>,
+    db: Db,
+    user: User,
+) -> Result<Option<StdResult<Flash<Redirect>, Status>>> {
+    use crate::views::api::v0::leases::{self as api, AddCoOwnerResponse};
+
+    let lease_id = match Asset::by_id(&db, asset_id)?.and_then(|x| x.lease_id()) {
         Some(x) => x,
-        None => return Ok(Some(Err(Status::Conflict))),
+        None => return Ok(None),
     };
 
-    let asset_type = AssetType::by_id(&*db, asset.type_id())?.chain_err(|| "missing asset_type")?;
+    let dest = format!("/assets/{}", asset_id);
 
-    let data = HookData::new(&lease, &asset, &asset_type);
-    hooks.leased(&*db, data)?;
+    let co_owner = match User::by_email(&(&db).into(), &form.email)? {
+        Some(x) => x,
+        None => return Ok(Some(Err(Status::BadRequest))),
+    };
 
-    let dest = format!("/assets/{}", asset.id());
-    Ok(Some(Ok(Redirect::to(dest))))
+    match api::add_co_owner(lease_id, co_owner.id(), db, user)? {
+        AddCoOwnerResponse::Status(Status::Created)
+        | AddCoOwnerResponse::Status(Status::NoContent) => {
+            Ok(Some(Ok(Flash::success(Redirect::to(dest), "Shared."))))
+        }
+        AddCoOwnerResponse::Rejected(x) => {
+            Ok(Some(Ok(Flash::error(Redirect::to(dest), x.reason()))))
+        }
+        AddCoOwnerResponse::Status(x) => Ok(Some(Err(x))),
+    }
 }
 
-#[delete("/<asset_id>/lease")]
-pub(crate) fn delete_lease(
+#[delete("/<asset_id>/lease/users/<user_id>")]
+pub fn remove_co_owner(
     asset_id: i32,
+    user_id: i32,
     db: Db,
     user: User,
-    hooks: State<Hooks>,
 ) -> Result<Option<StdResult<Redirect, Status>>> {
-    use crate::views::api::v0::assets as api;
+    use crate::views::api::v0::leases as api;
 
-    match api::delete_lease(asset_id, db, user, hooks)? {
-        Some(Status::NoContent) => {
+    let lease_id = match Asset::by_id(&db, asset_id)?.and_then(|x| x.lease_id()) {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    match api::remove_co_owner(lease_id, user_id, db, user)? {
+        Status::NoContent => {
             let dest = format!("/assets/{}", asset_id);
             Ok(Some(Ok(Redirect::to(dest))))
         }
-        Some(x) => Ok(Some(Err(x))),
-        None => Ok(None),
+        x => Ok(Some(Err(x))),
+    }
+}
+
+#[delete("/<asset_id>/reservations/<lease_id>")]
+pub(crate) fn delete_reservation(
+    asset_id: i32,
+    lease_id: i32,
+    db: Db,
+    user: User,
+) -> Result<StdResult<Redirect, Status>> {
+    use crate::views::api::v0::assets as api;
+
+    match api::delete_reservation(asset_id, lease_id, db, user)? {
+        Status::NoContent => {
+            let dest = format!("/assets/{}", asset_id);
+            Ok(Ok(Redirect::to(dest)))
+        }
+        x => Ok(Err(x)),
     }
 }
 
 #[get("/<asset_id>")]
-pub fn detail(asset_id: i32, db: Db, user: User) -> Result<Option<Template>> {
+pub fn detail(
+    asset_id: i32,
+    db: Db,
+    user: User,
+    flash: Option<FlashMessage>,
+) -> Result<Option<Template>> {
     use crate::schema::tag_types::dsl as tt;
     use crate::schema::tags::dsl as t;
 
@@ -107,10 +314,58 @@ pub fn detail(asset_id: i32, db: Db, user: User) -> Result<Option<Template>> {
         .chain_err(|| "unable to get tags for asset")?;
 
     let lease = asset.fetch_lease_owner(&db)?;
-    let user_owns_lease = lease
-        .as_ref()
-        .map(|(x, _)| x.user_id() == user.id())
-        .unwrap_or(false);
+
+    let (user_owns_lease, co_owners) = match &lease {
+        Some((x, _)) => {
+            let user_owns_lease = x.is_owner(&db, user.id())?;
+
+            // Any owner can remove any co-owner.
+            let co_owners = x
+                .co_owners(&db)?
+                .into_iter()
+                .map(|co_owner| (co_owner, user_owns_lease))
+                .collect();
+
+            (user_owns_lease, co_owners)
+        }
+        None => (false, vec![]),
+    };
+
+    let transfer = match &lease {
+        Some((x, _)) => match LeaseTransfer::by_lease(&db, x.id())? {
+            Some(pending) => {
+                let recipient = User::by_id(&(&db).into(), pending.to_user_id())?
+                    .chain_err(|| "missing transfer recipient")?;
+                Some((pending, recipient))
+            }
+            None => None,
+        },
+        None => None,
+    };
+
+    let user_receives_transfer = match &transfer {
+        Some((_, recipient)) => recipient.id() == user.id(),
+        None => false,
+    };
+
+    let reservations = Reservation::upcoming(&db, asset_id)?
+        .into_iter()
+        .map(|(lease, owner)| {
+            let user_owns_reservation = lease.user_id() == user.id();
+            (lease, owner, user_owns_reservation)
+        })
+        .collect();
+
+    let history = EndedLease::for_asset(&db, asset_id)?;
+
+    let lease_fields = LeaseField::for_types(&db, &[asset_type.id()])?;
+
+    let health = ProbeResult::for_asset(&db, asset_id)?;
+
+    let field_values = match &lease {
+        Some((x, _)) => FieldValue::for_lease(&db, x.id())?,
+        None => vec![],
+    };
 
     #[derive(Serialize)]
     struct Context {
@@ -118,15 +373,37 @@ pub fn detail(asset_id: i32, db: Db, user: User) -> Result<Option<Template>> {
         asset_type: AssetType,
         tags: Vec<(TagType, Option<Tag>)>,
         lease: Option<(Lease, User)>,
+        lease_fields: Vec<LeaseField>,
+        field_values: Vec<(String, String)>,
+        co_owners: Vec<(User, bool)>,
+        transfer: Option<(LeaseTransfer, User)>,
+        reservations: Vec<(Lease, User, bool)>,
+        history: Vec<(EndedLease, User)>,
+        health: Vec<(Probe, ProbeResult)>,
         user: User,
         user_owns_lease: bool,
+        user_receives_transfer: bool,
+        flash: Option<(String, String)>,
+        out_of_service: bool,
     }
 
+    let flash = flash.map(|x| (x.name().to_owned(), x.msg().to_owned()));
+
     Ok(Some(Template::render(
         "assets/detail",
         Context {
             lease,
+            lease_fields,
+            field_values,
+            co_owners,
+            transfer,
+            reservations,
+            history,
+            health,
             user_owns_lease,
+            user_receives_transfer,
+            flash,
+            out_of_service: !asset.is_available(),
             tags,
             asset,
             asset_type,
@@ -0,0 +1,288 @@
+//! Read-only iCalendar feeds of current and upcoming leases.
+//!
+//! Calendar apps can't log in, so instead of a `User` these routes take a
+//! `FeedToken` in the query string.
+
+use crate::errors::*;
+use crate::internal::db::Db;
+use crate::models::asset::Asset;
+use crate::models::asset_type::AssetType;
+use crate::models::feed_token::FeedToken;
+use crate::models::lease::Lease;
+use crate::models::reservation::{Calendar, Reservation};
+use crate::models::user::User;
+
+use chrono::prelude::*;
+
+use rocket::http::{ContentType, RawStr, Status};
+use rocket::request::FromParam;
+use rocket::response::content::Content;
+
+use std::result::Result as StdResult;
+
+/// Longest line allowed by RFC 5545, in octets, not counting the line break.
+const MAX_LINE: usize = 75;
+
+/// The `<id>.ics` file name at the end of a feed's path.
+pub struct IcsFile(i32);
+
+impl<'a> FromParam<'a> for IcsFile {
+    type Error = &'a RawStr;
+
+    fn from_param(param: &'a RawStr) -> StdResult<Self, Self::Error> {
+        if !param.as_str().ends_with(".ics") {
+            return Err(param);
+        }
+
+        let id = &param.as_str()[..param.len() - 4];
+
+        id.parse().map(IcsFile).map_err(|_| param)
+    }
+}
+
+type Feed = StdResult<Content<String>, Status>;
+
+#[get("/assets/<asset_id>?<token>")]
+pub fn asset(asset_id: IcsFile, token: String, db: Db) -> Result<Option<Feed>> {
+    if !is_authorized(&db, &token)? {
+        return Ok(Some(Err(Status::Forbidden)));
+    }
+
+    let asset = match Asset::by_id(&db, asset_id.0)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let found = Reservation::for_calendar(&db, Calendar::Asset(asset.id()))?;
+
+    Ok(Some(Ok(render(asset.name(), found))))
+}
+
+#[get("/types/<type_id>?<token>")]
+pub fn asset_type(type_id: IcsFile, token: String, db: Db) -> Result<Option<Feed>> {
+    if !is_authorized(&db, &token)? {
+        return Ok(Some(Err(Status::Forbidden)));
+    }
+
+    let asset_type = match AssetType::by_id(&db, type_id.0)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let found = Reservation::for_calendar(&db, Calendar::AssetType(asset_type.id()))?;
+
+    Ok(Some(Ok(render(asset_type.plural_name(), found))))
+}
+
+#[get("/users/<user_id>?<token>")]
+pub fn user(user_id: IcsFile, token: String, db: Db) -> Result<Option<Feed>> {
+    if !is_authorized(&db, &token)? {
+        return Ok(Some(Err(Status::Forbidden)));
+    }
+
+    let user = match User::by_id(&(&db).into(), user_id.0)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let found = Reservation::for_calendar(&db, Calendar::User(user.id()))?;
+
+    Ok(Some(Ok(render(user.email(), found))))
+}
+
+/// Any valid token can read any feed, just like any logged in user can see
+/// every lease.
+fn is_authorized(db: &Db, token: &str) -> Result<bool> {
+    Ok(FeedToken::by_token(db, token)?.is_some())
+}
+
+/// Build a calendar named `name`, with one event for each lease in `found`.
+/// `found` must be ordered so the rows for a lease are next to each other.
+fn render(name: &str, found: Vec<(Lease, Asset, User)>) -> Content<String> {
+    let mut out = String::new();
+
+    line(&mut out, "BEGIN", "VCALENDAR");
+    line(&mut out, "VERSION", "2.0");
+    line(
+        &mut out,
+        "PRODID",
+        concat!("-//Bellhop//Bellhop ", env!("CARGO_PKG_VERSION"), "//EN"),
+    );
+    line(&mut out, "CALSCALE", "GREGORIAN");
+    line(
+        &mut out,
+        "X-WR-CALNAME",
+        &escape(&format!("Bellhop: {}", name)),
+    );
+
+    let now = Utc::now();
+
+    let mut found = found.into_iter().peekable();
+
+    while let Some((lease, asset, owner)) = found.next() {
+        // Leases that hold several assets are shown as one event.
+        let mut names = vec![asset.name().to_owned()];
+        while let Some((next, _, _)) = found.peek() {
+            if next.id() != lease.id() {
+                break;
+            }
+
+            let (_, asset, _) = found.next().unwrap();
+            names.push(asset.name().to_owned());
+        }
+
+        let summary = format!("{} ({})", names.join(", "), owner.email());
+
+        line(&mut out, "BEGIN", "VEVENT");
+        line(&mut out, "UID", &format!("lease-{}@bellhop", lease.id()));
+        line(&mut out, "DTSTAMP", &timestamp(now));
+        line(&mut out, "DTSTART", &timestamp(lease.start_time()));
+
+        // Leases without an end time are shown as starting, and nothing more.
+        if let Some(x) = lease.end_time() {
+            line(&mut out, "DTEND", &timestamp(x));
+        }
+
+        line(&mut out, "SUMMARY", &escape(&summary));
+
+        if let Some(x) = lease.purpose() {
+            line(&mut out, "DESCRIPTION", &escape(x));
+        }
+
+        line(&mut out, "END", "VEVENT");
+    }
+
+    line(&mut out, "END", "VCALENDAR");
+
+    Content(ContentType::new("text", "calendar"), out)
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape `text` for use as a property's value.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => (),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Append a content line to `out`, folding it if it's too long.
+fn line(out: &mut String, name: &str, value: &str) {
+    let full = format!("{}:{}", name, value);
+
+    let mut len = 0;
+    for c in full.chars() {
+        // Folding adds a leading space, so continuations hold one less.
+        if len + c.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+
+        out.push(c);
+        len += c.len_utf8();
+    }
+
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(out: &str) -> Vec<&str> {
+        out.split_terminator("\r\n").collect()
+    }
+
+    /// Undo folding, as a calendar app would.
+    fn unfold(out: &str) -> String {
+        out.replace("\r\n ", "")
+    }
+
+    #[test]
+    fn escape_special_characters() {
+        assert_eq!(escape("a;b,c\\d"), "a\\;b\\,c\\\\d");
+        assert_eq!(escape("one\ntwo"), "one\\ntwo");
+        assert_eq!(escape("one\r\ntwo"), "one\\ntwo");
+        assert_eq!(escape("plain: text"), "plain: text");
+    }
+
+    #[test]
+    fn line_short() {
+        let mut out = String::new();
+        line(&mut out, "SUMMARY", "hello");
+
+        assert_eq!(out, "SUMMARY:hello\r\n");
+    }
+
+    #[test]
+    fn line_exactly_max() {
+        let value = "x".repeat(MAX_LINE - "SUMMARY:".len());
+
+        let mut out = String::new();
+        line(&mut out, "SUMMARY", &value);
+
+        assert_eq!(lines(&out).len(), 1);
+        assert_eq!(lines(&out)[0].len(), MAX_LINE);
+    }
+
+    #[test]
+    fn line_folded() {
+        let value = "x".repeat(200);
+
+        let mut out = String::new();
+        line(&mut out, "SUMMARY", &value);
+
+        let folded = lines(&out);
+        assert!(folded.len() > 1);
+
+        for (i, x) in folded.iter().enumerate() {
+            assert!(x.len() <= MAX_LINE);
+            assert_eq!(x.starts_with(' '), i > 0);
+        }
+
+        assert_eq!(unfold(&out), format!("SUMMARY:{}\r\n", value));
+    }
+
+    #[test]
+    fn line_folds_between_characters() {
+        // Three octets each, so a naive fold would split one in half.
+        let value = "\u{2603}".repeat(60);
+
+        let mut out = String::new();
+        line(&mut out, "SUMMARY", &value);
+
+        for x in lines(&out) {
+            assert!(x.len() <= MAX_LINE);
+        }
+
+        assert_eq!(unfold(&out), format!("SUMMARY:{}\r\n", value));
+    }
+
+    #[test]
+    fn line_moves_character_that_would_overflow() {
+        // One octet short of the limit, then a two octet character.
+        let value = format!("{}\u{e9}", "x".repeat(MAX_LINE - "SUMMARY:".len() - 1));
+
+        let mut out = String::new();
+        line(&mut out, "SUMMARY", &value);
+
+        let folded = lines(&out);
+        assert_eq!(folded.len(), 2);
+        assert_eq!(folded[0].len(), MAX_LINE - 1);
+        assert_eq!(folded[1], " \u{e9}");
+    }
+}
@@ -1,5 +1,7 @@
 pub mod api;
+pub mod approvals;
 pub mod assets;
 pub mod favicon;
+pub mod ical;
 pub mod types;
 pub mod user;
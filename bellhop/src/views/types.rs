@@ -1,19 +1,30 @@
 use crate::errors::*;
 use crate::internal::db::{get_all_types, Db};
+use crate::internal::hooks::Hooks;
 use crate::models::asset::Asset;
 use crate::models::asset_type::AssetType;
 use crate::models::lease::Lease;
+use crate::models::lease_field::{FieldValue, LeaseField};
+use crate::models::probe::{Probe, ProbeResult};
+use crate::models::recurring_reservation::{RecurringReservation, RecurringReservationTag};
 use crate::models::tag::Tag;
 use crate::models::tag_type::TagType;
 use crate::models::user::User;
+use crate::models::waitlist::{JoinWaitlistForm, WaitlistEntry, WaitlistTag};
+use crate::waitlist;
 
 use chrono::prelude::*;
 
 use diesel::prelude::*;
 
+use rocket::http::Status;
+use rocket::request::{Form, State};
+use rocket::response::Redirect;
+
 use rocket_contrib::templates::Template;
 
 use std::collections::HashMap;
+use std::result::Result as StdResult;
 
 /*****************************************
 Everything below is mouted under: "/"
@@ -85,11 +96,32 @@ pub fn detail(asset_type_id: i32, db: Db, user: User) -> Result<Option<Template>
         .load(&*db)
         .chain_err(|| "unable to get tag types belonging to an asset type")?;
 
+    let lease_ids = assets_to_leases
+        .iter()
+        .filter_map(|(_, lease)| lease.as_ref().map(Lease::id))
+        .collect::<Vec<_>>();
+
+    let mut field_values = FieldValue::for_leases(&*db, &lease_ids)?;
+
     let tags = Tag::belonging_to(&assets)
         .load::<Tag>(&*db)
         .chain_err(|| "unable to get tags belonging to assets")?
         .grouped_by(&assets);
 
+    let probe_names = Probe::for_type(&*db, asset_type.id())?
+        .into_iter()
+        .map(|p| (p.id(), p.name().to_owned()))
+        .collect::<HashMap<_, _>>();
+
+    let mut health = HashMap::<_, Vec<_>>::new();
+    for result in ProbeResult::for_type(&*db, asset_type.id())? {
+        let name = probe_names.get(&result.probe_id()).cloned();
+        health
+            .entry(result.asset_id())
+            .or_default()
+            .push((name.unwrap_or_default(), result));
+    }
+
     let asset_tags = assets_to_leases
         .into_iter()
         .zip(tags)
@@ -104,19 +136,132 @@ pub fn detail(asset_type_id: i32, db: Db, user: User) -> Result<Option<Template>
                 .map(move |tt| tags_by_type.remove(&tt.id()))
                 .collect();
 
+            // Why the asset is held, followed by the lease's custom fields.
+            let mut held_for = vec![];
+
+            if let Some(lease) = &lease {
+                held_for.extend(lease.purpose().map(str::to_owned));
+
+                let fields = field_values.remove(&lease.id()).unwrap_or_default();
+                held_for.extend(
+                    fields
+                        .into_iter()
+                        .map(|(name, value)| format!("{}: {}", name, value)),
+                );
+            }
+
+            let out_of_service = !asset.is_available();
+
+            let checks = health.remove(&asset.id()).unwrap_or_default();
+
             (
                 asset,
                 lease.map(|x| x.user_id() == user.id()).unwrap_or(false),
                 tags,
+                held_for,
+                out_of_service,
+                checks,
             )
         })
         .collect::<Vec<_>>();
 
+    let lease_fields = LeaseField::for_types(&*db, &[asset_type.id()])?;
+
+    let all_tag_types: Vec<TagType> = TagType::belonging_to(&asset_type)
+        .order(tt::rightness.asc())
+        .load(&*db)
+        .chain_err(|| "unable to get tag types belonging to an asset type")?;
+
+    let (entries, waiting_users): (Vec<WaitlistEntry>, Vec<User>) =
+        WaitlistEntry::queue(&*db, asset_type.id())?
+            .into_iter()
+            .unzip();
+
+    let wanted = WaitlistTag::belonging_to(&entries)
+        .load::<WaitlistTag>(&*db)
+        .chain_err(|| "unable to get tags for waitlist")?
+        .grouped_by(&entries);
+
+    let tag_type_names = all_tag_types
+        .iter()
+        .map(|tt| (tt.id(), tt.name()))
+        .collect::<HashMap<_, _>>();
+
+    let waitlist = entries
+        .into_iter()
+        .zip(waiting_users)
+        .zip(wanted)
+        .map(|((entry, waiter), wanted)| {
+            let wanted = wanted
+                .iter()
+                .map(|w| {
+                    let name = tag_type_names.get(&w.tag_type_id()).cloned();
+                    format!("{}: {}", name.unwrap_or_default(), w.value())
+                })
+                .collect();
+
+            let own = entry.user_id() == user.id();
+
+            (entry, waiter, own, wanted)
+        })
+        .collect::<Vec<_>>();
+
+    let (rules, rule_users): (Vec<RecurringReservation>, Vec<User>) =
+        RecurringReservation::for_type(&*db, asset_type.id())?
+            .into_iter()
+            .unzip();
+
+    let rule_tags = RecurringReservationTag::belonging_to(&rules)
+        .load::<RecurringReservationTag>(&*db)
+        .chain_err(|| "unable to get tags for recurring reservations")?
+        .grouped_by(&rules);
+
+    let asset_names = assets
+        .iter()
+        .map(|a| (a.id(), a.name()))
+        .collect::<HashMap<_, _>>();
+
+    let recurring = rules
+        .into_iter()
+        .zip(rule_users)
+        .zip(rule_tags)
+        .map(|((rule, owner), tags)| {
+            // Either a specific asset, or the tags any asset needs.
+            let wanted = match rule.asset_id() {
+                Some(x) => vec![asset_names.get(&x).cloned().unwrap_or_default().to_owned()],
+                None => tags
+                    .iter()
+                    .map(|w| {
+                        let name = tag_type_names.get(&w.tag_type_id()).cloned();
+                        format!("{}: {}", name.unwrap_or_default(), w.value())
+                    })
+                    .collect(),
+            };
+
+            let can_delete = rule.user_id() == user.id() || user.can_write();
+            let schedule = rule.describe();
+
+            (rule, owner, can_delete, schedule, wanted)
+        })
+        .collect::<Vec<_>>();
+
     #[derive(Serialize)]
     struct Context {
         tag_types: Vec<TagType>,
+        all_tag_types: Vec<TagType>,
         asset_type: AssetType,
-        asset_tags: Vec<(Asset, bool, Vec<Option<Tag>>)>,
+        asset_tags: Vec<(
+            Asset,
+            bool,
+            Vec<Option<Tag>>,
+            Vec<String>,
+            bool,
+            Vec<(String, ProbeResult)>,
+        )>,
+        has_probes: bool,
+        lease_fields: Vec<LeaseField>,
+        waitlist: Vec<(WaitlistEntry, User, bool, Vec<String>)>,
+        recurring: Vec<(RecurringReservation, User, bool, String, Vec<String>)>,
         now: DateTime<Utc>,
         user: User,
     }
@@ -125,10 +270,75 @@ pub fn detail(asset_type_id: i32, db: Db, user: User) -> Result<Option<Template>
         "types/detail",
         Context {
             tag_types,
+            all_tag_types,
             asset_type,
             asset_tags,
+            has_probes: !probe_names.is_empty(),
+            lease_fields,
+            waitlist,
+            recurring,
             now,
             user,
         },
     )))
 }
+
+#[post("/<asset_type_id>/waitlist", data = "<form>")]
+pub(crate) fn join_waitlist(
+    asset_type_id: i32,
+    form: Form<JoinWaitlistForm>,
+    db: Db,
+    user: User,
+    hooks: State<Hooks>,
+) -> Result<Option<StdResult<Redirect, Status>>> {
+    let asset_type = match AssetType::by_id(&db, asset_type_id)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let create = form.into_inner().into_create_waitlist_entry();
+
+    match waitlist::join(&*db, &hooks, &asset_type, user.id(), &create)? {
+        Some(_) => {
+            let dest = format!("/types/{}", asset_type_id);
+            Ok(Some(Ok(Redirect::to(dest))))
+        }
+        None => Ok(Some(Err(Status::BadRequest))),
+    }
+}
+
+#[delete("/<asset_type_id>/waitlist/<entry_id>")]
+pub fn leave_waitlist(
+    asset_type_id: i32,
+    entry_id: i32,
+    db: Db,
+    user: User,
+) -> Result<StdResult<Redirect, Status>> {
+    use crate::views::api::v0::types as api;
+
+    match api::leave_waitlist(asset_type_id, entry_id, db, user)? {
+        Status::NoContent => {
+            let dest = format!("/types/{}", asset_type_id);
+            Ok(Ok(Redirect::to(dest)))
+        }
+        x => Ok(Err(x)),
+    }
+}
+
+#[delete("/<asset_type_id>/recurring/<rule_id>")]
+pub fn delete_recurring(
+    asset_type_id: i32,
+    rule_id: i32,
+    db: Db,
+    user: User,
+) -> Result<StdResult<Redirect, Status>> {
+    use crate::views::api::v0::types as api;
+
+    match api::delete_recurring(asset_type_id, rule_id, db, user)? {
+        Status::NoContent => {
+            let dest = format!("/types/{}", asset_type_id);
+            Ok(Ok(Redirect::to(dest)))
+        }
+        x => Ok(Err(x)),
+    }
+}
@@ -1,20 +1,69 @@
 use crate::errors::*;
 use crate::internal::db::Db;
+use crate::models::asset::Asset;
+use crate::models::ended_lease::EndedLease;
+use crate::models::feed_token::FeedToken;
 use crate::models::user::User;
 
+use rocket::http::Status;
+use rocket::response::Redirect;
+
 use rocket_contrib::templates::Template;
 
+use std::result::Result as StdResult;
+
 #[get("/show/<user_id>")]
-pub fn detail(user_id: i32, db: Db) -> Result<Option<Template>> {
+pub fn detail(user_id: i32, db: Db, viewer: Option<User>) -> Result<Option<Template>> {
     let user = match User::by_id(&(&db).into(), user_id)? {
         Some(x) => x,
         None => return Ok(None),
     };
 
+    let history = EndedLease::for_user(&db, user_id)?;
+
+    // Feed tokens are secrets, so only show them to their owner.
+    let own = viewer.map(|x| x.id() == user_id).unwrap_or(false);
+    let feed_tokens = if own {
+        FeedToken::for_user(&db, user_id)?
+    } else {
+        vec![]
+    };
+
     #[derive(Serialize)]
     struct Context {
         user: User,
+        history: Vec<(EndedLease, Asset)>,
+        own: bool,
+        feed_tokens: Vec<FeedToken>,
     }
 
-    Ok(Some(Template::render("user/detail", Context { user })))
+    Ok(Some(Template::render(
+        "user/detail",
+        Context {
+            user,
+            history,
+            own,
+            feed_tokens,
+        },
+    )))
+}
+
+#[post("/feed-tokens")]
+pub fn create_feed_token(db: Db, user: User) -> Result<Redirect> {
+    FeedToken::create(&db, user.id())?;
+
+    let dest = format!("/users/show/{}", user.id());
+    Ok(Redirect::to(dest))
+}
+
+#[delete("/feed-tokens/<token_id>")]
+pub fn delete_feed_token(token_id: i32, db: Db, user: User) -> Result<StdResult<Redirect, Status>> {
+    use crate::views::api::v0::feed_tokens as api;
+
+    let dest = format!("/users/show/{}", user.id());
+
+    match api::delete(token_id, db, user)? {
+        Status::NoContent => Ok(Ok(Redirect::to(dest))),
+        x => Ok(Err(x)),
+    }
 }
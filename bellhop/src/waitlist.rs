@@ -0,0 +1,173 @@
+//! Hands freed assets to the users waiting for them.
+
+use chrono::prelude::*;
+
+use crate::errors::*;
+use crate::hooks::Data as HookData;
+use crate::internal::hooks::Hooks;
+use crate::models::asset::{Asset, AssetStatus};
+use crate::models::asset_type::AssetType;
+use crate::models::lease::{Lease, Reserved};
+use crate::models::lease_field::{FieldValue, LeaseField};
+use crate::models::lease_quota;
+use crate::models::tag::Tag;
+use crate::models::waitlist::{CreateWaitlistEntryForm, WaitlistEntry, WaitlistTag};
+
+use diesel::prelude::*;
+
+use std::collections::BTreeMap;
+
+/// Add `user_id` to the waitlist for `asset_type`, then hand out any assets
+/// that are already free.
+///
+/// Returns `None` if the form is invalid for this asset type, the type
+/// requires approval, since leases on those have to be requested, or the type
+/// has required lease fields, which waitlist entries have no way to fill in.
+pub(crate) fn join(
+    c: &PgConnection,
+    hooks: &Hooks,
+    asset_type: &AssetType,
+    user_id: i32,
+    form: &CreateWaitlistEntryForm,
+) -> Result<Option<(WaitlistEntry, Vec<WaitlistTag>)>> {
+    use crate::models::tag_type::TagType;
+
+    if asset_type.requires_approval() {
+        return Ok(None);
+    }
+
+    if fields_for(c, asset_type.id())?.is_none() {
+        return Ok(None);
+    }
+
+    let tag_types: Vec<TagType> = TagType::belonging_to(asset_type)
+        .load(c)
+        .chain_err(|| "unable to get tag types belonging to an asset type")?;
+
+    if !form.is_valid(&tag_types) {
+        return Ok(None);
+    }
+
+    let created = form.insert(c, asset_type.id(), user_id)?;
+
+    hand_off_type(c, hooks, asset_type)?;
+
+    Ok(Some(created))
+}
+
+/// Offer every free, in service asset of `asset_type` to its waitlist.
+pub(crate) fn hand_off_type(c: &PgConnection, hooks: &Hooks, asset_type: &AssetType) -> Result<()> {
+    use crate::schema::assets::dsl as a;
+
+    let free: Vec<Asset> = Asset::belonging_to(asset_type)
+        .filter(a::lease_id.is_null())
+        .filter(a::status.eq(AssetStatus::Available))
+        .load(c)
+        .chain_err(|| "unable to get free assets for asset type")?;
+
+    for asset in free {
+        hand_off(c, hooks, &asset)?;
+    }
+
+    Ok(())
+}
+
+/// Lease `asset` to the first user waiting for an asset like it, if there is
+/// one. Users it would put over their quota, or whose lease a `before_lease`
+/// hook refuses, are skipped, but stay waiting.
+///
+/// Nothing is handed off if the asset type requires approval, which it may
+/// have started to after people joined its waitlist.
+///
+/// Calls both the `leased` and `handed_off` hooks for the new lease.
+pub(crate) fn hand_off(c: &PgConnection, hooks: &Hooks, asset: &Asset) -> Result<Option<Lease>> {
+    let tags: Vec<Tag> = Tag::belonging_to(asset)
+        .load(c)
+        .chain_err(|| "unable to fetch tags for asset")?;
+
+    let asset_type = AssetType::by_id(c, asset.type_id())?.chain_err(|| "missing asset_type")?;
+
+    // Nobody approved leases for the users waiting.
+    if asset_type.requires_approval() {
+        return Ok(None);
+    }
+
+    // Entries made before a lease field became required can't be served.
+    let fields = match fields_for(c, asset.type_id())? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    // The hooks are called in the same transaction, so they're only queued
+    // if the hand off happens.
+    c.transaction::<_, Error, _>(|| {
+        let waiting = WaitlistEntry::lock_queue(c, asset.type_id())?;
+
+        let wanted = WaitlistTag::belonging_to(&waiting)
+            .load::<WaitlistTag>(c)
+            .chain_err(|| "unable to fetch tags for waitlist")?
+            .grouped_by(&waiting);
+
+        for (entry, wanted) in waiting.iter().zip(wanted) {
+            if !WaitlistEntry::is_satisfied_by(&wanted, &tags) {
+                continue;
+            }
+
+            let create_lease = entry.to_create_lease(Utc::now());
+
+            // Each entry gets its own savepoint, so a lease that's refused
+            // is undone before trying the next entry.
+            let result = c.transaction::<_, Error, _>(|| {
+                let (lease, asset) = match create_lease.reserve(c, asset.id(), &fields)? {
+                    Reserved::Started(lease, asset) => (lease, asset),
+
+                    // The asset is taken, or this entry's lease would run
+                    // into a reservation. Either way, the next entry might
+                    // still fit.
+                    _ => return Ok(None),
+                };
+
+                lease_quota::enforce(c, &lease, &[asset.type_id()])?;
+
+                let data = HookData::new(&lease, &asset, &asset_type);
+                hooks.before_lease(c, data.clone())?;
+
+                diesel::delete(entry)
+                    .execute(c)
+                    .chain_err(|| "unable to delete waitlist entry")?;
+
+                hooks.leased(c, data.clone())?;
+                hooks.handed_off(c, data)?;
+
+                Ok(Some(lease))
+            });
+
+            match result {
+                Ok(Some(lease)) => return Ok(Some(lease)),
+                Ok(None) => continue,
+                Err(ref e) if is_refusal(e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
+    })
+}
+
+/// The lease fields for leases handed off from the waitlist of the
+/// `AssetType` identified by `type_id`, which are only the optional ones left
+/// blank, or `None` if the type has required fields.
+fn fields_for(c: &PgConnection, type_id: i32) -> Result<Option<Vec<FieldValue>>> {
+    let lease_fields = LeaseField::for_types(c, &[type_id])?;
+
+    Ok(LeaseField::resolve(&lease_fields, &BTreeMap::new()))
+}
+
+/// Returns `true` if `e` means the lease isn't allowed for this user, so the
+/// asset should go to the next user instead.
+fn is_refusal(e: &Error) -> bool {
+    match e.kind() {
+        ErrorKind::QuotaExceeded(_) | ErrorKind::Rejected(_) => true,
+        _ => false,
+    }
+}